@@ -0,0 +1,49 @@
+// Render `examples/test.html` styled with `examples/test.css` to `output.png` and
+// `output.ppm`, going through each stage of the engine in turn:
+//
+//     cargo run --example render
+
+use std::{fs, io::BufWriter, path::Path};
+
+use robinson::{css, dom, font, layout, paint, style};
+
+fn main() {
+    let examples = Path::new(env!("CARGO_MANIFEST_DIR")).join("examples");
+    let html = fs::read_to_string(examples.join("test.html")).expect("couldn't read test.html");
+    let css = fs::read_to_string(examples.join("test.css")).expect("couldn't read test.css");
+
+    // Parse the HTML into a DOM tree, and the CSS into a stylesheet
+    let root_node = dom::Parser::parse(html);
+    let stylesheet = css::Parser::parse(css);
+
+    // Match the stylesheet's rules to the DOM's elements
+    let style_root = style::style_tree(&root_node, &stylesheet);
+
+    // Lay out the styled tree in an 800x600 viewport, measuring text with the built-in font
+    let mut viewport: layout::Dimensions = Default::default();
+    viewport.content.width = 800.0;
+    viewport.content.height = 600.0;
+    let tree = layout::layout_tree_with_metrics(&style_root, viewport, font::text_metrics())
+        .expect("the root element isn't displayed");
+
+    // List what to draw, then draw it
+    let display_list = paint::build_display_list_for_viewport(&tree.root, viewport.content);
+    let canvas = paint::paint(&display_list, viewport.content);
+
+    canvas
+        .save_png(Path::new("output.png"))
+        .expect("couldn't write output.png");
+    let ppm = fs::File::create("output.ppm").expect("couldn't create output.ppm");
+    canvas
+        .write_ppm(BufWriter::new(ppm))
+        .expect("couldn't write output.ppm");
+    println!(
+        "rendered {} boxes as {} display commands to output.png and output.ppm",
+        count_boxes(&tree.root),
+        display_list.len()
+    );
+}
+
+fn count_boxes(layout_box: &layout::LayoutBox) -> usize {
+    1 + layout_box.children.iter().map(count_boxes).sum::<usize>()
+}
@@ -0,0 +1,27 @@
+// Render a small page through the same entry point the WebAssembly build exports, and
+// print a summary of the pixels. In a browser, where a malformed page throws an `Error`,
+// the returned buffer can be drawn with
+//
+//     const pixels = render_to_rgba(html, css, width, height);
+//     const image = new ImageData(new Uint8ClampedArray(pixels), width, height);
+//     canvas.getContext("2d").putImageData(image, 0, 0);
+
+use robinson::wasm::render_to_rgba;
+
+fn main() -> Result<(), robinson::Error> {
+    let html = "<div class=\"a\"><div class=\"b\"></div></div>";
+    let css = ".a { display: block; padding: 10px; background: #336699; }
+               .b { display: block; height: 40px; background: #ffcc00; }";
+    let (width, height) = (100, 80);
+
+    let pixels = render_to_rgba(html, css, width, height)?;
+    let painted = pixels
+        .chunks(4)
+        .filter(|p| p != &[255, 255, 255, 255])
+        .count();
+    println!(
+        "rendered {}x{} pixels, {} of them painted",
+        width, height, painted
+    );
+    Ok(())
+}
@@ -0,0 +1,54 @@
+#ifndef ROBINSON_H
+#define ROBINSON_H
+
+/* Generated by cbindgen from src/ffi.rs. Don't edit by hand. */
+
+#include <stddef.h>
+#include <stdint.h>
+
+// Render a page to a `width` x `height` viewport, with the default stylesheet before
+// `css`. Returns the pixels as 8-bit RGBA values row by row, and stores how many bytes
+// there are in `out_len`, or returns null on failure.
+//
+// # Safety
+//
+// `html` and `css` must be null or point to NUL-terminated strings, and `out_len` must
+// be valid for writes: if it's null, the call fails. The buffer returned must be freed
+// with `robinson_free`.
+uint8_t *robinson_render(const char *html,
+                         const char *css,
+                         uint32_t width,
+                         uint32_t height,
+                         size_t *out_len);
+
+// Free a buffer returned by `robinson_render`. Null is ignored.
+//
+// # Safety
+//
+// `ptr` must be null, or a buffer from `robinson_render` that hasn't been freed, with
+// `len` the length it returned.
+void robinson_free(uint8_t *ptr, size_t len);
+
+// Lay out a page in a `width` x `height` viewport, with the default stylesheet before
+// `css`, and return the layout snapshot as JSON: `null` if the root has
+// `display: none`. Returns null on failure.
+//
+// # Safety
+//
+// `html` and `css` must be null or point to NUL-terminated strings. The string returned
+// must be freed with `robinson_free_string`.
+char *robinson_dump_layout(const char *html, const char *css, uint32_t width, uint32_t height);
+
+// Free a string returned by `robinson_dump_layout`. Null is ignored.
+//
+// # Safety
+//
+// `ptr` must be null, or a string from `robinson_dump_layout` that hasn't been freed.
+void robinson_free_string(char *ptr);
+
+// The message of the last call on this thread that failed, or null if none has. The
+// string belongs to the library, and stays valid until the next call on this thread
+// fails.
+const char *robinson_last_error(void);
+
+#endif  /* ROBINSON_H */
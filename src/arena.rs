@@ -0,0 +1,266 @@
+// An arena-backed document, as an alternative to a tree of `Node`s for large pages. Every
+// node lives in one `Vec` and refers to its parent, first child and next sibling by index,
+// and all the text and attribute values share one `String`, so parsing allocates a handful
+// of times rather than several times per node, and dropping a document is flat no matter
+// how deep it is. `NodeRef` implements `DomNode`, so a document is styled, laid out and
+// painted by borrowing from the arena, just as a tree of nodes is
+
+use std::ops::Range;
+
+use crate::{
+    atom::Atom,
+    dom::{self, DomNode, Node, ParseError, ParseOptions, TreeSink},
+};
+
+pub struct Document {
+    nodes: Vec<NodeData>,
+    // The name and where in `text` the value is, for the attributes of every element
+    attributes: Vec<(Atom, Range<usize>)>,
+    // The text of every text node and attribute value, one after another
+    text: String,
+    root: NodeId,
+}
+
+// The index of a node in its document
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct NodeId(usize);
+
+struct NodeData {
+    kind: NodeKind,
+    parent: Option<NodeId>,
+    first_child: Option<NodeId>,
+    next_sibling: Option<NodeId>,
+}
+
+enum NodeKind {
+    // Where the text is in `Document::text`
+    Text(Range<usize>),
+    Element {
+        tag_name: Atom,
+        // Which of `Document::attributes` are this element's
+        attributes: Range<usize>,
+    },
+}
+
+impl Document {
+    // Parse an HTML document into an arena, or return where and why it's malformed
+    pub fn parse(source: &str) -> Result<Document, ParseError> {
+        Self::parse_with_options(source, ParseOptions::default())
+    }
+
+    pub fn parse_with_options(source: &str, options: ParseOptions) -> Result<Document, ParseError> {
+        let mut document = Document {
+            nodes: Vec::new(),
+            attributes: Vec::new(),
+            text: String::new(),
+            root: NodeId(0),
+        };
+        document.root = dom::Parser::parse_into(source.to_string(), options, &mut document)?;
+        Ok(document)
+    }
+
+    pub fn root(&self) -> NodeRef<'_> {
+        self.get(self.root)
+    }
+
+    pub fn get(&self, id: NodeId) -> NodeRef<'_> {
+        NodeRef { document: self, id }
+    }
+
+    // The number of nodes in the document, including any whitespace between top-level
+    // elements that was parsed and then discarded
+    pub fn node_count(&self) -> usize {
+        self.nodes.len()
+    }
+
+    fn push(&mut self, kind: NodeKind) -> NodeId {
+        self.nodes.push(NodeData {
+            kind,
+            parent: None,
+            first_child: None,
+            next_sibling: None,
+        });
+        NodeId(self.nodes.len() - 1)
+    }
+
+    // Copy `text` to the end of `self.text`, and return where it is
+    fn push_text(&mut self, text: &str) -> Range<usize> {
+        let start = self.text.len();
+        self.text.push_str(text);
+        start..self.text.len()
+    }
+}
+
+impl TreeSink for Document {
+    type Handle = NodeId;
+
+    fn text(&mut self, text: &str) -> NodeId {
+        let text = self.push_text(text);
+        self.push(NodeKind::Text(text))
+    }
+
+    fn element<'i>(
+        &mut self,
+        tag_name: &str,
+        attributes: impl Iterator<Item = (&'i str, &'i str)>,
+    ) -> NodeId {
+        let start = self.attributes.len();
+        for (name, value) in attributes {
+            let value = self.push_text(value);
+            self.attributes.push((Atom::from(name), value));
+        }
+        self.push(NodeKind::Element {
+            tag_name: Atom::from(tag_name),
+            attributes: start..self.attributes.len(),
+        })
+    }
+
+    fn set_children(&mut self, element: &mut NodeId, children: impl Iterator<Item = NodeId>) {
+        let mut previous: Option<NodeId> = None;
+        for child in children {
+            self.nodes[child.0].parent = Some(*element);
+            match previous {
+                Some(previous) => self.nodes[previous.0].next_sibling = Some(child),
+                None => self.nodes[element.0].first_child = Some(child),
+            }
+            previous = Some(child);
+        }
+    }
+
+    fn is_blank(&self, node: &NodeId) -> bool {
+        match self.nodes[node.0].kind {
+            NodeKind::Text(ref text) => self.text[text.clone()].trim().is_empty(),
+            NodeKind::Element { .. } => false,
+        }
+    }
+}
+
+// A node of a document, borrowed from its arena
+#[derive(Clone, Copy)]
+pub struct NodeRef<'a> {
+    document: &'a Document,
+    id: NodeId,
+}
+
+impl<'a> NodeRef<'a> {
+    pub fn id(&self) -> NodeId {
+        self.id
+    }
+
+    pub fn parent(&self) -> Option<NodeRef<'a>> {
+        self.data().parent.map(|id| self.document.get(id))
+    }
+
+    // The chain of ancestors, from the parent up to the root
+    pub fn ancestors(&self) -> impl Iterator<Item = NodeRef<'a>> + 'a {
+        std::iter::successors(self.parent(), NodeRef::parent)
+    }
+
+    // Copy this node and everything under it into a tree of `Node`s, e.g. to edit it. This
+    // recurses per level, as dropping the tree does, so it's not for very deep documents
+    pub fn to_node(&self) -> Node {
+        match self.data().kind {
+            NodeKind::Text(ref text) => dom::text(self.document.text[text.clone()].to_string()),
+            NodeKind::Element {
+                ref tag_name,
+                ref attributes,
+            } => {
+                let attributes = self.document.attributes[attributes.clone()]
+                    .iter()
+                    .map(|(name, value)| {
+                        (name.clone(), self.document.text[value.clone()].to_string())
+                    })
+                    .collect();
+                let children = self.children().map(|child| child.to_node()).collect();
+                dom::elem(tag_name.clone(), attributes, children)
+            }
+        }
+    }
+
+    fn data(&self) -> &'a NodeData {
+        &self.document.nodes[self.id.0]
+    }
+}
+
+impl<'a> DomNode<'a> for NodeRef<'a> {
+    fn tag_name(self) -> Option<&'a str> {
+        match self.data().kind {
+            NodeKind::Element { ref tag_name, .. } => Some(tag_name),
+            NodeKind::Text(_) => None,
+        }
+    }
+
+    fn text(self) -> Option<&'a str> {
+        match self.data().kind {
+            NodeKind::Text(ref text) => Some(&self.document.text[text.clone()]),
+            NodeKind::Element { .. } => None,
+        }
+    }
+
+    // If the attribute is repeated, the last value wins, as it does in a `Node`
+    fn attribute(self, name: &str) -> Option<&'a str> {
+        let NodeKind::Element { ref attributes, .. } = self.data().kind else {
+            return None;
+        };
+        self.document.attributes[attributes.clone()]
+            .iter()
+            .rev()
+            .find(|(attribute, _)| *attribute == name)
+            .map(|(_, value)| &self.document.text[value.clone()])
+    }
+
+    fn children(self) -> impl Iterator<Item = NodeRef<'a>> + 'a {
+        let document = self.document;
+        std::iter::successors(self.data().first_child, move |id| {
+            document.nodes[id.0].next_sibling
+        })
+        .map(move |id| document.get(id))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_into_an_arena() {
+        let source = "<div id=\"a\" id=\"b\"><p class=\"note\">Hello <em>world</em></p><br/></div>";
+        let document = Document::parse(source).unwrap();
+        let root = document.root();
+        assert_eq!(Some("div"), root.tag_name());
+        assert_eq!(Some("b"), root.attribute("id"));
+        assert_eq!(None, root.attribute("class"));
+
+        let children: Vec<_> = root.children().map(|child| child.tag_name()).collect();
+        assert_eq!(vec![Some("p"), Some("br")], children);
+        let p = root.children().next().unwrap();
+        assert_eq!(Some("note"), p.attribute("class"));
+        assert_eq!(Some("Hello "), p.children().next().unwrap().text());
+
+        let em = p.children().nth(1).unwrap();
+        let ancestors: Vec<_> = em.ancestors().map(|node| node.id()).collect();
+        assert_eq!(vec![p.id(), root.id()], ancestors);
+        assert_eq!(
+            dom::parse(source).unwrap().to_string(),
+            root.to_node().to_string()
+        );
+
+        // Errors are the same as for a tree of nodes
+        assert_eq!(
+            dom::parse("<p></div>").unwrap_err(),
+            Document::parse("<p></div>").err().unwrap()
+        );
+    }
+
+    #[test]
+    fn drop_a_deep_document() {
+        let depth = 200_000;
+        let source = format!("{}{}", "<div>".repeat(depth), "</div>".repeat(depth));
+        // Neither parsing nor dropping recurses per level, so the test thread's stack is enough
+        let document = Document::parse(&source).unwrap();
+        assert_eq!(depth, document.node_count());
+        let deepest = document.get(NodeId(depth - 1));
+        assert_eq!(depth - 1, deepest.ancestors().count());
+        drop(document);
+    }
+}
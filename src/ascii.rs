@@ -0,0 +1,179 @@
+// Draw a layout as text, for reading layouts in terminals, CI logs and snapshot tests.
+// Block-level boxes are outlined with box-drawing characters and text is written into the
+// cells it covers, all scaled down from px to character cells
+
+use crate::{
+    dom::DomNode,
+    layout::{BoxType, LayoutBox, Rect},
+};
+
+// The default size of a character cell in px: one character of 16px text set in
+// `MonospaceMetrics`, so such text fills exactly one cell per character
+pub const CELL_WIDTH: f32 = 8.0;
+pub const CELL_HEIGHT: f32 = 16.0;
+
+// Draw a layout on a grid `cols` characters wide, with the default cell size
+pub fn render_text<'a, N: DomNode<'a>>(layout: &LayoutBox<'a, N>, cols: usize) -> String {
+    render_text_with_scale(layout, cols, CELL_WIDTH, CELL_HEIGHT)
+}
+
+// Draw a layout on a grid `cols` characters wide, where each cell covers `cell_width` x
+// `cell_height` px. The grid is as tall as the boxes in the layout, and anything outside
+// it is cut off. Boxes are drawn in tree order, each covering what was drawn before it
+pub fn render_text_with_scale<'a, N: DomNode<'a>>(
+    layout: &LayoutBox<'a, N>,
+    cols: usize,
+    cell_width: f32,
+    cell_height: f32,
+) -> String {
+    let bottom = extent(layout);
+    let rows = (bottom / cell_height).ceil().max(0.0) as usize;
+    let mut grid = Grid {
+        cells: vec![vec![' '; cols]; rows],
+        cell_width,
+        cell_height,
+    };
+    grid.draw(layout);
+
+    let mut text = String::new();
+    for row in grid.cells {
+        let line: String = row.into_iter().collect();
+        text.push_str(line.trim_end());
+        text.push('\n');
+    }
+    text
+}
+
+// The bottom edge of the lowest border box in a layout
+fn extent<'a, N: DomNode<'a>>(layout_box: &LayoutBox<'a, N>) -> f32 {
+    let border_box = layout_box.dimensions.border_box();
+    layout_box
+        .children
+        .iter()
+        .map(extent)
+        .fold(border_box.y + border_box.height, f32::max)
+}
+
+struct Grid {
+    cells: Vec<Vec<char>>,
+    cell_width: f32,
+    cell_height: f32,
+}
+
+impl Grid {
+    fn draw<'a, N: DomNode<'a>>(&mut self, layout_box: &LayoutBox<'a, N>) {
+        if let BoxType::BlockNode(_) | BoxType::FlexContainer(_) = layout_box.box_type {
+            self.draw_box(layout_box.dimensions.border_box());
+        }
+        for line in &layout_box.line_boxes {
+            for fragment in &line.fragments {
+                let col = (fragment.rect.x / self.cell_width).round() as i64;
+                let row = ((fragment.rect.y + fragment.rect.height / 2.0) / self.cell_height)
+                    .floor() as i64;
+                for (i, c) in fragment.text.chars().enumerate() {
+                    self.set(col + i as i64, row, c);
+                }
+            }
+        }
+        for child in &layout_box.children {
+            self.draw(child);
+        }
+    }
+
+    // Outline a rectangle and clear its inside. Boxes need to span at least two cells
+    // each way to be outlined, so smaller and empty boxes aren't drawn
+    fn draw_box(&mut self, rect: Rect) {
+        let left = (rect.x / self.cell_width).round() as i64;
+        let top = (rect.y / self.cell_height).round() as i64;
+        let right = ((rect.x + rect.width) / self.cell_width).round() as i64 - 1;
+        let bottom = ((rect.y + rect.height) / self.cell_height).round() as i64 - 1;
+        if right <= left || bottom <= top {
+            return;
+        }
+
+        for row in top..=bottom {
+            for col in left..=right {
+                let c = match (row == top, row == bottom, col == left, col == right) {
+                    (true, _, true, _) => '┌',
+                    (true, _, _, true) => '┐',
+                    (_, true, true, _) => '└',
+                    (_, true, _, true) => '┘',
+                    (true, _, _, _) | (_, true, _, _) => '─',
+                    (_, _, true, _) | (_, _, _, true) => '│',
+                    _ => ' ',
+                };
+                self.set(col, row, c);
+            }
+        }
+    }
+
+    // Write a character into a cell, if it's on the grid
+    fn set(&mut self, col: i64, row: i64, c: char) {
+        if col < 0 || row < 0 {
+            return;
+        }
+        if let Some(cell) = self
+            .cells
+            .get_mut(row as usize)
+            .and_then(|cells| cells.get_mut(col as usize))
+        {
+            *cell = c;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{css, dom, layout::layout_tree, style::style_tree};
+
+    fn render(html: &str, css: &str, cols: usize) -> String {
+        let root = dom::Parser::parse(html.to_string());
+        let stylesheet = css::Parser::parse(css.to_string());
+        let style_root = style_tree(&root, &stylesheet);
+        let mut viewport: crate::layout::Dimensions = Default::default();
+        viewport.content.width = cols as f32 * CELL_WIDTH;
+        render_text(&layout_tree(&style_root, viewport).unwrap().root, cols)
+    }
+
+    #[test]
+    fn two_columns() {
+        let text = render(
+            "<div class=\"page\">
+                <div class=\"nav\"><p>Home</p><p>About</p></div>
+                <div class=\"main\"><p>Hello, world!</p></div>
+            </div>",
+            ".page { display: flex; padding: 16px; }
+             .nav { display: block; width: 160px; padding: 16px; }
+             .main { display: block; width: 400px; padding: 16px; }
+             p { display: block; }",
+            80,
+        );
+        assert_eq!(
+            "\
+┌──────────────────────────────────────────────────────────────────────────────┐
+│ ┌─────────────────────┐┌───────────────────────────────────────────────────┐ │
+│ │ Home                ││ Hello, world!                                     │ │
+│ │ About               ││                                                   │ │
+│ └─────────────────────┘└───────────────────────────────────────────────────┘ │
+└──────────────────────────────────────────────────────────────────────────────┘
+",
+            text
+        );
+    }
+
+    #[test]
+    fn degenerate_boxes_and_clipping() {
+        let text = render(
+            "<div><p class=\"empty\"></p><p class=\"thin\"></p><p class=\"wide\"></p></div>",
+            "div { display: block; }
+             p { display: block; }
+             .thin { width: 4px; height: 32px; }
+             .wide { width: 400px; height: 48px; margin-left: -8px; }",
+            10,
+        );
+        // The empty and thin paragraphs aren't drawn, and the wide one is cut off at the
+        // edges of the grid
+        assert_eq!("┌────────┐\n│        │\n──────────\n\n──────────\n", text);
+    }
+}
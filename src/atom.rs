@@ -0,0 +1,184 @@
+// Interned strings, for the names that recur all through a page: tag and attribute names,
+// the tags, ids and classes in selectors, and property names. While any atom for a string
+// is alive, every atom for it shares one allocation, so atoms are compared by pointer and
+// cloned without copying. Reading an atom's text doesn't touch the table of interned
+// strings, which only holds weak references, so a string is freed with its last atom
+
+use std::collections::HashMap;
+use std::sync::{Arc, LazyLock, Mutex, Weak};
+
+#[derive(Clone)]
+pub struct Atom(Arc<str>);
+
+#[derive(Default)]
+struct Interner {
+    atoms: HashMap<Box<str>, Weak<str>>,
+    // The table is swept for freed strings when it grows past this many entries
+    sweep_at: usize,
+}
+
+static INTERNER: LazyLock<Mutex<Interner>> = LazyLock::new(Default::default);
+
+impl Atom {
+    // The atom for `name`, adding it to the table if no atom for it is alive
+    pub fn new(name: &str) -> Atom {
+        let mut interner = INTERNER.lock().unwrap();
+        if let Some(atom) = interner.atoms.get(name).and_then(Weak::upgrade) {
+            return Atom(atom);
+        }
+        let atom: Arc<str> = Arc::from(name);
+        interner.atoms.insert(name.into(), Arc::downgrade(&atom));
+        // Sweeping when the table has doubled since the last sweep keeps the cost of
+        // sweeps proportional to the number of strings interned
+        if interner.atoms.len() > interner.sweep_at {
+            interner.atoms.retain(|_, atom| atom.strong_count() > 0);
+            interner.sweep_at = (interner.atoms.len() * 2).max(64);
+        }
+        Atom(atom)
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl std::ops::Deref for Atom {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+// Maps keyed by atoms can be looked up with a `&str`, without interning it
+impl std::borrow::Borrow<str> for Atom {
+    fn borrow(&self) -> &str {
+        self.as_str()
+    }
+}
+
+// Two live atoms with the same text share it, so comparing pointers is enough
+impl PartialEq for Atom {
+    fn eq(&self, other: &Atom) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for Atom {}
+
+// Atoms hash as their text, as `Borrow<str>` requires
+impl std::hash::Hash for Atom {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.as_str().hash(state)
+    }
+}
+
+impl From<&str> for Atom {
+    fn from(name: &str) -> Atom {
+        Atom::new(name)
+    }
+}
+
+impl From<String> for Atom {
+    fn from(name: String) -> Atom {
+        Atom::new(&name)
+    }
+}
+
+impl PartialEq<str> for Atom {
+    fn eq(&self, other: &str) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl PartialEq<&str> for Atom {
+    fn eq(&self, other: &&str) -> bool {
+        self.as_str() == *other
+    }
+}
+
+impl PartialEq<String> for Atom {
+    fn eq(&self, other: &String) -> bool {
+        self.as_str() == other
+    }
+}
+
+// Atoms sort by their text, not by the order they were interned in
+impl Ord for Atom {
+    fn cmp(&self, other: &Atom) -> std::cmp::Ordering {
+        self.as_str().cmp(other.as_str())
+    }
+}
+
+impl PartialOrd for Atom {
+    fn partial_cmp(&self, other: &Atom) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+// Atoms are written, and debugged, as their text
+impl std::fmt::Display for Atom {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl std::fmt::Debug for Atom {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self.as_str())
+    }
+}
+
+// Atoms are serialized as their text
+#[cfg(feature = "serde")]
+impl serde::Serialize for Atom {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dom::{self, NodeType};
+
+    #[test]
+    fn same_names_are_the_same_atom() {
+        let tag_name = |html: &str| match dom::Parser::parse(html.to_string()).node_type {
+            NodeType::Element(elem) => elem.tag_name,
+            NodeType::Text(_) => unreachable!(),
+        };
+        let (first, second) = (tag_name("<div></div>"), tag_name("<div><p></p></div>"));
+        assert_eq!(first, second);
+        assert!(std::ptr::eq(first.as_str(), second.as_str()));
+        assert_ne!(first, tag_name("<p></p>"));
+
+        // A map keyed by atoms is looked up by text
+        let map: std::collections::HashMap<Atom, u32> = [(first.clone(), 1)].into();
+        assert_eq!(Some(&1), map.get("div"));
+
+        assert_eq!("div", first.to_string());
+        assert_eq!("\"div\"", format!("{:?}", first));
+        let (a, b) = (Atom::from("a"), Atom::from("b"));
+        assert!(b > a);
+        assert!(a < b);
+    }
+
+    #[test]
+    fn strings_are_freed_with_their_last_atom() {
+        let name = "strings-are-freed-with-their-last-atom";
+        let atom = Atom::from(name);
+        let weak = Arc::downgrade(&atom.0);
+        let again = Atom::from(name.to_string());
+        assert!(Arc::ptr_eq(&again.0, &atom.0));
+        drop((atom, again));
+        assert!(weak.upgrade().is_none());
+
+        // Interning the name again makes a new string, and dead entries are swept away
+        assert_eq!(name, Atom::from(name).as_str());
+        for i in 0..1000 {
+            Atom::new(&format!("{}-{}", name, i));
+        }
+        assert!(INTERNER.lock().unwrap().atoms.len() < 1000);
+    }
+}
@@ -1,8 +1,44 @@
 use std::cmp::Reverse;
 
-#[derive(Debug, PartialEq)]
+use crate::{atom::Atom, dom::DomNode};
+
+#[derive(Debug, Default, PartialEq)]
 pub struct StyleSheet {
     pub rules: Vec<Rule>,
+    pub keyframes: Vec<Keyframes>,
+    // The URLs of `@import` rules, in source order. Loading the stylesheets they refer to
+    // is up to the caller
+    pub imports: Vec<String>,
+}
+
+impl StyleSheet {
+    pub fn rules(&self) -> &[Rule] {
+        &self.rules
+    }
+
+    pub fn rule_count(&self) -> usize {
+        self.rules.len()
+    }
+
+    // Add the rules of another stylesheet after this one's, so that they win ties in
+    // specificity, as if its text came at the end of this one
+    pub fn merge(&mut self, other: StyleSheet) {
+        self.rules.extend(other.rules);
+        self.keyframes.extend(other.keyframes);
+        self.imports.extend(other.imports);
+    }
+
+    // Iterate over every declaration in the stylesheet, paired with each selector of its
+    // rule, in source order
+    pub fn declarations(&self) -> impl Iterator<Item = (&Selector, &Declaration)> {
+        self.rules.iter().flat_map(|rule| {
+            rule.selectors.iter().flat_map(move |selector| {
+                rule.declarations
+                    .iter()
+                    .map(move |declaration| (selector, declaration))
+            })
+        })
+    }
 }
 
 #[derive(Debug, PartialEq)]
@@ -11,6 +47,20 @@ pub struct Rule {
     pub declarations: Vec<Declaration>,
 }
 
+// An `@keyframes <name> { ... }` block
+#[derive(Debug, PartialEq)]
+pub struct Keyframes {
+    pub name: String,
+    pub frames: Vec<Keyframe>,
+}
+
+// One keyframe rule, e.g. `from, 50% { ... }`. Offsets are percentages, with `from` as 0 and `to` as 100
+#[derive(Debug, PartialEq)]
+pub struct Keyframe {
+    pub offsets: Vec<f32>,
+    pub declarations: Vec<Declaration>,
+}
+
 #[derive(Debug, PartialEq)]
 pub enum Selector {
     Simple(SimpleSelector),
@@ -18,35 +68,131 @@ pub enum Selector {
 
 #[derive(Debug, PartialEq)]
 pub struct SimpleSelector {
-    pub tag_name: Option<String>,
-    pub id: Option<String>,
-    pub class: Vec<String>,
+    pub tag_name: Option<Atom>,
+    pub id: Option<Atom>,
+    pub class: Vec<Atom>,
+    pub pseudo_element: Option<PseudoElement>,
+}
+
+// A pseudo-element at the end of a selector, e.g. `p::before`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PseudoElement {
+    Before,
+    After,
+    FirstLetter,
+    // Parsed, but its rules aren't applied
+    FirstLine,
 }
 
 pub type Specificity = (usize, usize, usize);
 
+// Selectors are written as CSS, e.g. `p.note::before`, with `*` for one that only has a
+// pseudo-element or nothing at all
+impl std::fmt::Display for Selector {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let Selector::Simple(ref simple) = *self;
+        write!(f, "{}", simple)
+    }
+}
+
+impl std::fmt::Display for SimpleSelector {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.tag_name {
+            Some(ref tag_name) => write!(f, "{}", tag_name)?,
+            None if self.id.is_none() && self.class.is_empty() => write!(f, "*")?,
+            None => {}
+        }
+        if let Some(ref id) = self.id {
+            write!(f, "#{}", id)?;
+        }
+        for class in &self.class {
+            write!(f, ".{}", class)?;
+        }
+        if let Some(pseudo_element) = self.pseudo_element {
+            write!(f, "::{}", pseudo_element)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::fmt::Display for PseudoElement {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            PseudoElement::Before => "before",
+            PseudoElement::After => "after",
+            PseudoElement::FirstLetter => "first-letter",
+            PseudoElement::FirstLine => "first-line",
+        })
+    }
+}
+
+impl SimpleSelector {
+    // Whether `node` is an element with the tag name, id and classes this selector asks
+    // for. Any pseudo-element is ignored
+    pub fn matches<'a>(&self, node: impl DomNode<'a>) -> bool {
+        // Text nodes never match
+        let Some(tag_name) = node.tag_name() else {
+            return false;
+        };
+
+        // Check type selector
+        if self.tag_name.iter().any(|name| *name != tag_name) {
+            return false;
+        }
+
+        // Check id selector
+        if self
+            .id
+            .iter()
+            .any(|id| node.attribute("id") != Some(id.as_str()))
+        {
+            return false;
+        }
+
+        // Check class selectors
+        let classes = node.attribute("class").unwrap_or_default();
+        self.class
+            .iter()
+            .all(|class| classes.split_whitespace().any(|c| *class == c))
+    }
+}
+
 impl Selector {
     pub fn specificity(&self) -> Specificity {
         // http://www.w3.org/TR/selectors/#specificity
         let Selector::Simple(ref simple) = *self;
         let a = simple.id.iter().count();
         let b = simple.class.len();
-        let c = simple.tag_name.iter().count();
+        let c = simple.tag_name.iter().count() + simple.pseudo_element.iter().count();
         (a, b, c)
     }
+
+    // Whether this selector matches the node. Selectors with a pseudo-element match the
+    // pseudo-element rather than the element itself, so they never match
+    pub fn matches<'a>(&self, node: impl DomNode<'a>) -> bool {
+        let Selector::Simple(ref simple) = *self;
+        simple.pseudo_element.is_none() && simple.matches(node)
+    }
 }
 
 #[derive(Debug, PartialEq)]
 pub struct Declaration {
-    pub name: String,
+    pub name: Atom,
     pub value: Value,
+    // Set by `!important`, which makes the declaration win over normal ones in the cascade
+    pub important: bool,
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum Value {
     Keyword(String),
     Length(f32, Unit),
+    Number(f32),
     ColorValue(Color),
+    StringValue(String),
+    LinearGradient(LinearGradient),
+    // A comma-separated list of values, e.g. `Arial, "Helvetica Neue", sans-serif`
+    List(Vec<Value>),
 }
 
 impl Value {
@@ -57,18 +203,147 @@ impl Value {
             _ => 0.0,
         }
     }
+
+    // Convert a relative length to an absolute px length. Other values are returned as-is
+    pub fn resolve(&self, context: &LengthContext) -> Value {
+        match *self {
+            Value::Length(size, Unit::Em) => Value::Length(size * context.font_size, Unit::Px),
+            Value::Length(size, Unit::Rem) => {
+                Value::Length(size * context.root_font_size, Unit::Px)
+            }
+            _ => self.clone(),
+        }
+    }
+
+    // Convert a percentage to a px length relative to `base`. Other values are returned as-is
+    pub fn resolve_percentage(&self, base: f32) -> Value {
+        match *self {
+            Value::Length(size, Unit::Percent) => Value::Length(size / 100.0 * base, Unit::Px),
+            _ => self.clone(),
+        }
+    }
+
+    // Convert a `border-width` keyword (`thin`, `medium`, or `thick`) to its length.
+    // Other values are returned as-is
+    pub fn border_width(&self) -> Value {
+        match *self {
+            Value::Keyword(ref k) => match &**k {
+                "thin" => Value::Length(1.0, Unit::Px),
+                "medium" => Value::Length(3.0, Unit::Px),
+                "thick" => Value::Length(5.0, Unit::Px),
+                _ => self.clone(),
+            },
+            _ => self.clone(),
+        }
+    }
+
+    // Return the value of a unitless number, or `None` for anything else
+    pub fn to_number(&self) -> Option<f32> {
+        match *self {
+            Value::Number(n) => Some(n),
+            _ => None,
+        }
+    }
+}
+
+// Values are written in CSS syntax
+impl std::fmt::Display for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            Value::Keyword(ref k) => write!(f, "{}", k),
+            Value::Length(size, ref unit) => {
+                let unit = match unit {
+                    Unit::Px => "px",
+                    Unit::Em => "em",
+                    Unit::Rem => "rem",
+                    Unit::Percent => "%",
+                };
+                write!(f, "{}{}", size, unit)
+            }
+            Value::Number(n) => write!(f, "{}", n),
+            Value::ColorValue(color) => write!(f, "{}", color),
+            Value::StringValue(ref s) => write!(f, "{:?}", s),
+            Value::LinearGradient(gradient) => {
+                let direction = if gradient.vertical { "bottom" } else { "right" };
+                write!(
+                    f,
+                    "linear-gradient(to {}, {}, {})",
+                    direction, gradient.start, gradient.end
+                )
+            }
+            Value::List(ref values) => {
+                for (i, value) in values.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", value)?;
+                }
+                Ok(())
+            }
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum Unit {
     Px,
+    Em,
+    Rem,
+    Percent,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+// Colors are written as `#rrggbb`, or `#rrggbbaa` if they're translucent
+impl std::fmt::Display for Color {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "#{:02x}{:02x}{:02x}", self.r, self.g, self.b)?;
+        if self.a != 255 {
+            write!(f, "{:02x}", self.a)?;
+        }
+        Ok(())
+    }
+}
+
+// The font sizes (in px) that relative lengths are resolved against
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LengthContext {
+    // Computed font-size of the element, for `em`
+    pub font_size: f32,
+    // Computed font-size of the root element, for `rem`
+    pub root_font_size: f32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Color {
     pub r: u8,
     pub g: u8,
     pub b: u8,
+    // Opacity, from 0 (transparent) to 255 (opaque)
+    pub a: u8,
+}
+
+// A two-stop `linear-gradient(to <side>, <color>, <color>)`. Gradients towards the left
+// or top are stored as the reverse gradient towards the right or bottom
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LinearGradient {
+    pub vertical: bool,
+    pub start: Color,
+    pub end: Color,
+}
+
+// A declaration value split into comma-separated groups of space-separated tokens, e.g.
+// `bold 16px/1.5 Arial, sans-serif` is `[[bold, 16px, /, 1.5, Arial], [sans-serif]]`
+struct DeclarationValue {
+    groups: Vec<Vec<Token>>,
+    important: bool,
+}
+
+#[derive(Debug, PartialEq)]
+enum Token {
+    Value(Value),
+    // The `/` separating the font size from the line height in the `font` shorthand
+    Slash,
 }
 
 pub struct Parser {
@@ -77,156 +352,560 @@ pub struct Parser {
 }
 
 impl Parser {
+    // Parse a stylesheet, panicking if it's malformed. Malformed declarations are
+    // skipped, but a malformed selector or `@keyframes` rule is an error
     pub fn parse(source: String) -> StyleSheet {
+        Self::try_parse(source).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    // Parse a stylesheet, or return where and why it's malformed
+    pub fn try_parse(source: String) -> Result<StyleSheet, ParseError> {
         let mut parser = Self {
             pos: 0,
             input: source,
         };
-        StyleSheet {
-            rules: parser.parse_rules(),
+        let mut stylesheet = StyleSheet {
+            rules: Vec::new(),
+            keyframes: Vec::new(),
+            imports: Vec::new(),
+        };
+        parser.parse_rules(&mut stylesheet, false)?;
+        Ok(stylesheet)
+    }
+
+    // Parse a list of rule sets and at-rules, separated by optional whitespace, up to the
+    // end of the input, or in a block up to and including its closing `}`
+    fn parse_rules(&mut self, stylesheet: &mut StyleSheet, block: bool) -> Result<(), ParseError> {
+        loop {
+            self.consume_whitespace();
+            if self.eof() {
+                return Ok(()); // the end of the input closes an unterminated block
+            }
+            if block && self.next_char() == '}' {
+                self.consume_char();
+                return Ok(());
+            }
+            if self.next_char() == '@' {
+                self.parse_at_rule(stylesheet)?;
+            } else {
+                stylesheet.rules.push(self.parse_rule()?);
+            }
+        }
+    }
+
+    // Parse an at-rule. Unsupported at-rules are skipped
+    fn parse_at_rule(&mut self, stylesheet: &mut StyleSheet) -> Result<(), ParseError> {
+        assert_eq!(self.consume_char(), '@');
+        match &*self.parse_identifier().to_ascii_lowercase() {
+            "keyframes" => {
+                let keyframes = self.parse_keyframes()?;
+                stylesheet.keyframes.push(keyframes);
+            }
+            "import" => {
+                let url = self.parse_import_url();
+                let media = self.consume_while(|c| c != ';' && c != '{' && c != '}');
+                if media_matches(&media) {
+                    stylesheet.imports.extend(url);
+                }
+                self.skip_at_rule();
+            }
+            "media" => {
+                let media = self.consume_while(|c| c != ';' && c != '{' && c != '}');
+                if self.expect_char('{').is_none() {
+                    self.skip_at_rule();
+                    return Ok(());
+                }
+                // The rules are parsed either way, so errors in them are reported
+                let mut rules = StyleSheet::default();
+                self.parse_rules(&mut rules, true)?;
+                if media_matches(&media) {
+                    stylesheet.merge(rules);
+                }
+            }
+            _ => self.skip_at_rule(),
+        }
+        Ok(())
+    }
+
+    // Parse the URL of `@import "<url>"` or `@import url(<url>)`, leaving anything after
+    // it, like a media query list, unparsed
+    fn parse_import_url(&mut self) -> Option<String> {
+        self.consume_whitespace();
+        if !self.eof() && matches!(self.next_char(), '"' | '\'') {
+            return match self.parse_string()? {
+                Value::StringValue(url) => Some(url),
+                _ => None,
+            };
         }
+        if !self.parse_identifier().eq_ignore_ascii_case("url") {
+            return None;
+        }
+        self.expect_char('(')?;
+        self.consume_whitespace();
+        let url = match self.peek()? {
+            '"' | '\'' => match self.parse_string()? {
+                Value::StringValue(url) => url,
+                _ => return None,
+            },
+            _ => self.consume_while(|c| c != ')' && !c.is_whitespace()),
+        };
+        self.consume_whitespace();
+        self.expect_char(')')?;
+        Some(url)
     }
 
-    // Parse a list of rule sets, separated by optional whitespace
-    fn parse_rules(&mut self) -> Vec<Rule> {
-        let mut rules = Vec::new();
+    // Parse the rest of `@keyframes <name> { <keyframe>* }`
+    fn parse_keyframes(&mut self) -> Result<Keyframes, ParseError> {
+        self.consume_whitespace();
+        let name = self.parse_identifier();
+        self.consume_whitespace();
+        self.require_char('{')?;
+
+        let mut frames = Vec::new();
         loop {
             self.consume_whitespace();
             if self.eof() {
+                break; // unterminated block
+            }
+            if self.next_char() == '}' {
+                self.consume_char();
                 break;
             }
-            rules.push(self.parse_rule());
+            frames.push(Keyframe {
+                offsets: self.parse_keyframe_selectors()?,
+                declarations: self.parse_declarations()?,
+            });
         }
-        rules
+        Ok(Keyframes { name, frames })
     }
 
-    // Parse a rule set: `<selectors> { <declarations> }`
-    fn parse_rule(&mut self) -> Rule {
-        Rule {
-            selectors: self.parse_selectors(),
-            declarations: self.parse_declarations(),
+    // Parse a comma-separated list of keyframe selectors: `from`, `to`, or a percentage
+    fn parse_keyframe_selectors(&mut self) -> Result<Vec<f32>, ParseError> {
+        let mut offsets = Vec::new();
+        loop {
+            self.consume_whitespace();
+            let start = self.pos;
+            if !self.eof() && matches!(self.next_char(), '0'..='9' | '.') {
+                let offset = self.parse_float();
+                offsets.push(offset.ok_or_else(|| ParseError {
+                    pos: start,
+                    message: "invalid keyframe selector".to_string(),
+                })?);
+                self.require_char('%')?;
+            } else {
+                match &*self.parse_identifier().to_ascii_lowercase() {
+                    "from" => offsets.push(0.0),
+                    "to" => offsets.push(100.0),
+                    "" => return Err(self.unexpected("in a keyframe selector list")),
+                    s => {
+                        return Err(ParseError {
+                            pos: start,
+                            message: format!("unexpected keyframe selector '{}'", s),
+                        })
+                    }
+                }
+            }
+            self.consume_whitespace();
+            match self.peek() {
+                Some(',') => {
+                    self.consume_char();
+                }
+                Some('{') => return Ok(offsets), // start of declarations
+                _ => return Err(self.unexpected("in a keyframe selector list")),
+            }
         }
     }
 
+    // Skip an unsupported at-rule, up to its terminating `;` or the end of its block
+    fn skip_at_rule(&mut self) {
+        let mut depth = 0;
+        while !self.eof() {
+            match self.consume_char() {
+                ';' if depth == 0 => break,
+                '{' => depth += 1,
+                '}' => {
+                    depth -= 1;
+                    if depth <= 0 {
+                        break;
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    // Parse a rule set: `<selectors> { <declarations> }`
+    fn parse_rule(&mut self) -> Result<Rule, ParseError> {
+        Ok(Rule {
+            selectors: self.parse_selectors()?,
+            declarations: self.parse_declarations()?,
+        })
+    }
+
     // Parse a comma-separated list of selectors
-    fn parse_selectors(&mut self) -> Vec<Selector> {
+    fn parse_selectors(&mut self) -> Result<Vec<Selector>, ParseError> {
         let mut selectors = Vec::new();
         loop {
-            selectors.push(Selector::Simple(self.parse_simple_selector()));
+            selectors.push(Selector::Simple(self.parse_simple_selector()?));
             self.consume_whitespace();
-            match self.next_char() {
-                ',' => {
+            match self.peek() {
+                Some(',') => {
                     self.consume_char();
                     self.consume_whitespace();
                 }
-                '{' => break, // start of declarations
-                c => panic!("Unexpected character {} in selector list", c),
+                Some('{') => break, // start of declarations
+                _ => return Err(self.unexpected("in a selector list")),
             }
         }
         // Return selectors with highest specificity first, for use in matching
         selectors.sort_by_key(|b| Reverse(b.specificity()));
-        selectors
+        Ok(selectors)
     }
 
     // Parse one simple selector, e.g.: `type#id.class1.class2.class3`
-    fn parse_simple_selector(&mut self) -> SimpleSelector {
+    fn parse_simple_selector(&mut self) -> Result<SimpleSelector, ParseError> {
         let mut selector = SimpleSelector {
             tag_name: None,
             id: None,
             class: Vec::new(),
+            pseudo_element: None,
         };
         while !self.eof() {
             match self.next_char() {
                 '#' => {
                     self.consume_char();
-                    selector.id = Some(self.parse_identifier());
+                    selector.id = Some(self.parse_identifier().into());
                 }
                 '.' => {
                     self.consume_char();
-                    selector.class.push(self.parse_identifier());
+                    selector.class.push(self.parse_identifier().into());
                 }
                 '*' => {
                     // universal selector
                     self.consume_char();
                 }
+                ':' => {
+                    // Accept the legacy single-colon syntax too
+                    self.consume_char();
+                    if !self.eof() && self.next_char() == ':' {
+                        self.consume_char();
+                    }
+                    let start = self.pos;
+                    selector.pseudo_element = match &*self.parse_identifier() {
+                        "before" => Some(PseudoElement::Before),
+                        "after" => Some(PseudoElement::After),
+                        "first-letter" => Some(PseudoElement::FirstLetter),
+                        "first-line" => Some(PseudoElement::FirstLine),
+                        name => {
+                            return Err(ParseError {
+                                pos: start,
+                                message: format!("unsupported pseudo-element '{}'", name),
+                            })
+                        }
+                    };
+                }
                 c if valid_identifier_char(c) => {
-                    selector.tag_name = Some(self.parse_identifier());
+                    selector.tag_name = Some(self.parse_identifier().into());
                 }
                 _ => break,
             }
         }
-        selector
+        Ok(selector)
     }
 
     // Parse a list of declarations enclosed in `{ ... }`
-    fn parse_declarations(&mut self) -> Vec<Declaration> {
-        assert_eq!(self.consume_char(), '{');
+    fn parse_declarations(&mut self) -> Result<Vec<Declaration>, ParseError> {
+        self.require_char('{')?;
         let mut declarations = Vec::new();
         loop {
             self.consume_whitespace();
-            if self.next_char() == '}' {
-                self.consume_char();
-                break;
+            if self.eof() {
+                break; // unterminated block
+            }
+            match self.next_char() {
+                '}' => {
+                    self.consume_char();
+                    break;
+                }
+                // Skip empty declarations, e.g. `;;`
+                ';' => {
+                    self.consume_char();
+                }
+                // Malformed declarations are discarded
+                _ => declarations.extend(self.parse_declaration()),
             }
-            declarations.push(self.parse_declaration());
         }
-        declarations
+        Ok(declarations)
+    }
+
+    // Parse one `<property>:<value>;` declaration, expanding shorthands into their
+    // longhand declarations. The semicolon may be omitted on the last declaration of a
+    // block. If the declaration is malformed, skip to the start of the next one and
+    // return nothing
+    fn parse_declaration(&mut self) -> Vec<Declaration> {
+        let declarations = self.try_parse_declaration();
+        self.skip_declaration();
+        declarations.unwrap_or_default()
     }
 
-    // Parse one `<property>:<value>;` declaration
-    fn parse_declaration(&mut self) -> Declaration {
+    fn try_parse_declaration(&mut self) -> Option<Vec<Declaration>> {
         let property_name = self.parse_identifier();
         self.consume_whitespace();
-        assert_eq!(self.consume_char(), ':');
-        self.consume_whitespace();
-        let value = self.parse_value();
-        self.consume_whitespace();
-        assert_eq!(self.consume_char(), ';');
+        if property_name.is_empty() || self.eof() || self.next_char() != ':' {
+            return None;
+        }
+        self.consume_char();
+        let DeclarationValue { groups, important } = self.parse_value()?;
 
-        Declaration {
-            name: property_name,
-            value,
+        let mut declarations = match &*property_name {
+            "font" => expand_font(groups)?,
+            "font-family" => vec![Declaration {
+                name: property_name.into(),
+                value: font_family(groups)?,
+                important,
+            }],
+            _ => {
+                let mut values = component_values(groups)?;
+                match &*property_name {
+                    "border" | "border-top" | "border-right" | "border-bottom" | "border-left" => {
+                        expand_border(&property_name, values)?
+                    }
+                    // A single value is kept as it is, since each side falls back to it anyway
+                    "border-width" | "border-style" | "border-color" if values.len() > 1 => {
+                        let component = &property_name["border-".len()..];
+                        expand_sides("border", Some(component), values)?
+                    }
+                    "margin" | "padding" if values.len() > 1 => {
+                        expand_sides(&property_name, None, values)?
+                    }
+                    // Other properties take a single value
+                    _ if values.len() == 1 => vec![Declaration {
+                        name: property_name.into(),
+                        value: values.remove(0),
+                        important,
+                    }],
+                    _ => return None,
+                }
+            }
+        };
+        // Longhands expanded from a shorthand inherit its importance
+        for declaration in &mut declarations {
+            declaration.important = important;
+        }
+        Some(declarations)
+    }
+
+    // Tokenize a declaration value up to the end of the declaration, into comma-separated
+    // groups of space-separated tokens. A trailing `!important` is stripped and recorded.
+    // Returns `None` if a token is malformed, or the value or one of its groups is empty
+    fn parse_value(&mut self) -> Option<DeclarationValue> {
+        let mut groups = vec![Vec::new()];
+        let mut important = false;
+        loop {
+            self.consume_whitespace();
+            if self.eof() {
+                break;
+            }
+            match self.next_char() {
+                // The end of the declaration, consumed by `parse_declaration`
+                ';' | '}' => break,
+                _ if important => return None, // nothing may follow `!important`
+                '!' => {
+                    self.consume_char();
+                    self.consume_whitespace();
+                    if !self.parse_identifier().eq_ignore_ascii_case("important") {
+                        return None;
+                    }
+                    important = true;
+                }
+                ',' => {
+                    self.consume_char();
+                    groups.push(Vec::new());
+                }
+                '/' => {
+                    self.consume_char();
+                    groups.last_mut()?.push(Token::Slash);
+                }
+                _ => {
+                    let value = self.parse_component()?;
+                    groups.last_mut()?.push(Token::Value(value));
+                }
+            }
+        }
+        if groups.iter().any(Vec::is_empty) {
+            return None;
+        }
+        Some(DeclarationValue { groups, important })
+    }
+
+    // Discard input up to and including the next `;`, or up to the `}` closing the block.
+    // After a malformed declaration, this skips whatever is left of it
+    fn skip_declaration(&mut self) {
+        self.consume_while(|c| c != ';' && c != '}');
+        if !self.eof() && self.next_char() == ';' {
+            self.consume_char();
         }
     }
 
-    fn parse_value(&mut self) -> Value {
+    // Parse a single component of a value: a length or number, color, string, keyword or
+    // function
+    fn parse_component(&mut self) -> Option<Value> {
+        if self.eof() {
+            return None;
+        }
+        let mut next = self.input[self.pos..].chars().skip(1);
         match self.next_char() {
-            '0'..='9' => self.parse_length(),
+            '0'..='9' | '.' => self.parse_length(),
+            '-' if matches!(next.next(), Some('0'..='9' | '.')) => self.parse_length(),
             '#' => self.parse_color(),
-            _ => Value::Keyword(self.parse_identifier()),
+            '"' | '\'' => self.parse_string(),
+            _ => {
+                let keyword = self.parse_identifier();
+                if keyword.is_empty() {
+                    None
+                } else if !self.eof() && self.next_char() == '(' {
+                    self.parse_function(&keyword)
+                } else {
+                    Some(Value::Keyword(keyword))
+                }
+            }
+        }
+    }
+
+    // Parse the arguments of a functional notation. Only `linear-gradient` is supported
+    fn parse_function(&mut self, name: &str) -> Option<Value> {
+        if name != "linear-gradient" {
+            return None;
+        }
+        assert_eq!(self.consume_char(), '(');
+        self.consume_whitespace();
+        if self.parse_identifier() != "to" {
+            return None;
+        }
+        self.consume_whitespace();
+        let side = self.parse_identifier();
+        let mut stops = Vec::new();
+        for _ in 0..2 {
+            self.consume_whitespace();
+            self.expect_char(',')?;
+            self.consume_whitespace();
+            match self.parse_component()? {
+                Value::ColorValue(color) => stops.push(color),
+                Value::Keyword(name) => stops.push(named_color(&name)?),
+                _ => return None,
+            }
+        }
+        self.consume_whitespace();
+        self.expect_char(')')?;
+
+        let (start, end) = (stops[0], stops[1]);
+        let (vertical, start, end) = match &*side {
+            "right" => (false, start, end),
+            "left" => (false, end, start),
+            "bottom" => (true, start, end),
+            "top" => (true, end, start),
+            _ => return None,
+        };
+        Some(Value::LinearGradient(LinearGradient {
+            vertical,
+            start,
+            end,
+        }))
+    }
+
+    // Consume the given character, or return `None` if the input doesn't continue with it
+    fn expect_char(&mut self, c: char) -> Option<()> {
+        if self.eof() || self.next_char() != c {
+            return None;
+        }
+        self.consume_char();
+        Some(())
+    }
+
+    // Consume the given character, or fail if the input doesn't continue with it
+    fn require_char(&mut self, c: char) -> Result<(), ParseError> {
+        match self.expect_char(c) {
+            Some(()) => Ok(()),
+            None => Err(self.unexpected(&format!("where '{}' was expected", c))),
+        }
+    }
+
+    // An error for the character at the current position, or the end of the input
+    fn unexpected(&self, context: &str) -> ParseError {
+        let found = match self.peek() {
+            Some(c) => format!("'{}'", c),
+            None => "end of input".to_string(),
+        };
+        ParseError {
+            pos: self.pos,
+            message: format!("unexpected {} {}", found, context),
         }
     }
 
-    fn parse_length(&mut self) -> Value {
-        Value::Length(self.parse_float(), self.parse_unit())
+    // Parse a number, followed by an optional unit
+    fn parse_length(&mut self) -> Option<Value> {
+        let num = self.parse_float()?;
+        if !self.eof() && self.next_char() == '%' {
+            self.consume_char();
+            Some(Value::Length(num, Unit::Percent))
+        } else if !self.eof() && valid_identifier_char(self.next_char()) {
+            Some(Value::Length(num, self.parse_unit()?))
+        } else {
+            Some(Value::Number(num))
+        }
     }
 
-    fn parse_float(&mut self) -> f32 {
+    fn parse_float(&mut self) -> Option<f32> {
+        let sign = if self.next_char() == '-' {
+            self.consume_char();
+            -1.0
+        } else {
+            1.0
+        };
         let s = self.consume_while(|c| matches!(c, '0'..='9' | '.'));
-        s.parse().unwrap()
+        s.parse::<f32>().ok().map(|n| sign * n)
     }
 
-    fn parse_unit(&mut self) -> Unit {
+    fn parse_unit(&mut self) -> Option<Unit> {
         match &*self.parse_identifier().to_ascii_lowercase() {
-            "px" => Unit::Px,
-            _ => panic!("unrecognized unit"),
+            "px" => Some(Unit::Px),
+            "em" => Some(Unit::Em),
+            "rem" => Some(Unit::Rem),
+            _ => None, // unrecognized unit
+        }
+    }
+
+    // Parse a quoted string. A backslash escapes the following character
+    fn parse_string(&mut self) -> Option<Value> {
+        let quote = self.consume_char();
+        let mut string = String::new();
+        while !self.eof() {
+            match self.consume_char() {
+                c if c == quote => return Some(Value::StringValue(string)),
+                '\\' if !self.eof() => string.push(self.consume_char()),
+                c => string.push(c),
+            }
         }
+        None // unterminated string
     }
 
-    fn parse_color(&mut self) -> Value {
+    // Parse a `#rrggbb` or `#rrggbbaa` color
+    fn parse_color(&mut self) -> Option<Value> {
         assert_eq!(self.consume_char(), '#');
-        Value::ColorValue(Color {
-            r: self.parse_hex_pair(),
-            g: self.parse_hex_pair(),
-            b: self.parse_hex_pair(),
-        })
+        Some(Value::ColorValue(Color {
+            r: self.parse_hex_pair()?,
+            g: self.parse_hex_pair()?,
+            b: self.parse_hex_pair()?,
+            a: self.parse_hex_pair().unwrap_or(255),
+        }))
     }
 
     // Parse two hexadecimal digits
-    fn parse_hex_pair(&mut self) -> u8 {
-        let s = &self.input[self.pos..self.pos + 2];
+    fn parse_hex_pair(&mut self) -> Option<u8> {
+        let s = self.input.get(self.pos..self.pos + 2)?;
+        let value = u8::from_str_radix(s, 16).ok()?;
         self.pos += 2;
-        u8::from_str_radix(s, 16).unwrap()
+        Some(value)
     }
 
     // Parse identifier
@@ -236,10 +915,8 @@ impl Parser {
 
     // Return the current character, and advance self.pos to the next character
     fn consume_char(&mut self) -> char {
-        let mut iter = self.input[self.pos..].char_indices();
-        let (_, cur_char) = iter.next().unwrap();
-        let (next_pos, _) = iter.next().unwrap_or((1, ' '));
-        self.pos += next_pos;
+        let cur_char = self.input[self.pos..].chars().next().unwrap();
+        self.pos += cur_char.len_utf8();
         cur_char
     }
 
@@ -265,16 +942,305 @@ impl Parser {
         self.input[self.pos..].chars().next().unwrap()
     }
 
+    // Return the current character without consuming it, or `None` at the end of the input
+    fn peek(&self) -> Option<char> {
+        self.input[self.pos..].chars().next()
+    }
+
     // Return true if all input is consumed
     fn eof(&self) -> bool {
         self.pos >= self.input.len()
     }
 }
 
+// Parse a stylesheet, or return where and why it's malformed
+pub fn parse(source: &str) -> Result<StyleSheet, ParseError> {
+    Parser::try_parse(source.to_string())
+}
+
+// Parse a single simple selector, e.g. `section.main`, with nothing before or after it but
+// whitespace
+pub fn parse_selector(source: &str) -> Result<SimpleSelector, ParseError> {
+    let mut parser = Parser {
+        pos: 0,
+        input: source.to_string(),
+    };
+    parser.consume_whitespace();
+    let selector = parser.parse_simple_selector()?;
+    parser.consume_whitespace();
+    match parser.eof() {
+        true => Ok(selector),
+        false => Err(parser.unexpected("after a selector")),
+    }
+}
+
+/// Parse a stylesheet, failing on a malformed selector or `@keyframes` rule.
+///
+/// ```
+/// use robinson::css::StyleSheet;
+///
+/// let stylesheet: StyleSheet = "div { color: red }".parse()?;
+/// assert_eq!(1, stylesheet.rule_count());
+/// assert!("div > p {}".parse::<StyleSheet>().is_err());
+/// # Ok::<(), robinson::css::ParseError>(())
+/// ```
+impl std::str::FromStr for StyleSheet {
+    type Err = ParseError;
+
+    fn from_str(source: &str) -> Result<StyleSheet, ParseError> {
+        Parser::try_parse(source.to_string())
+    }
+}
+
+// Why a stylesheet couldn't be parsed, and the byte offset in the source where parsing
+// stopped
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    pub pos: usize,
+    pub message: String,
+}
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} at byte {}", self.message, self.pos)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+// Whether a media query list, from a `media` attribute, `@media` or `@import`, applies to
+// the screen pages are rendered for. An empty list matches. Only the media type of each
+// query is checked: media features like `(min-width: 600px)` aren't supported, so they're
+// assumed to match
+pub fn media_matches(media: &str) -> bool {
+    media.split(',').any(|query| {
+        let query = query.trim().to_ascii_lowercase();
+        let mut words = query.split_whitespace().peekable();
+        let negated = words.next_if_eq(&"not").is_some();
+        words.next_if_eq(&"only");
+        let screen = match words.next() {
+            None => true,
+            Some(word) if word.starts_with('(') => true,
+            Some(media_type) => matches!(media_type, "all" | "screen"),
+        };
+        screen != negated
+    })
+}
+
 fn valid_identifier_char(c: char) -> bool {
     matches!(c, 'a'..='z' | 'A'..='Z' | '0'..='9' | '-' | '_')
 }
 
+// The color a basic CSS color keyword stands for, ignoring case, e.g. `red` or `Navy`
+fn named_color(name: &str) -> Option<Color> {
+    let (r, g, b) = match &*name.to_ascii_lowercase() {
+        "black" => (0, 0, 0),
+        "silver" => (192, 192, 192),
+        "gray" | "grey" => (128, 128, 128),
+        "white" => (255, 255, 255),
+        "maroon" => (128, 0, 0),
+        "red" => (255, 0, 0),
+        "purple" => (128, 0, 128),
+        "fuchsia" => (255, 0, 255),
+        "green" => (0, 128, 0),
+        "lime" => (0, 255, 0),
+        "olive" => (128, 128, 0),
+        "yellow" => (255, 255, 0),
+        "navy" => (0, 0, 128),
+        "blue" => (0, 0, 255),
+        "teal" => (0, 128, 128),
+        "aqua" => (0, 255, 255),
+        "orange" => (255, 165, 0),
+        "transparent" => {
+            return Some(Color {
+                r: 0,
+                g: 0,
+                b: 0,
+                a: 0,
+            })
+        }
+        _ => return None,
+    };
+    Some(Color { r, g, b, a: 255 })
+}
+
+// Expand a `border` or `border-<side>` shorthand into its width, style and color
+// longhands, which may be given in any order. Returns `None` if a component is repeated
+fn expand_border(name: &str, values: Vec<Value>) -> Option<Vec<Declaration>> {
+    let mut declarations: Vec<Declaration> = Vec::new();
+    for value in values {
+        let component = match value {
+            Value::Length(..) | Value::Number(_) => "width",
+            Value::Keyword(ref k) if matches!(&**k, "thin" | "medium" | "thick") => "width",
+            Value::Keyword(ref k) if BORDER_STYLES.contains(&&**k) => "style",
+            _ => "color",
+        };
+        let name = format!("{}-{}", name, component);
+        if declarations.iter().any(|d| d.name == name) {
+            return None;
+        }
+        declarations.push(Declaration {
+            name: name.into(),
+            value,
+            important: false,
+        });
+    }
+    Some(declarations)
+}
+
+// Expand one to four values given for the top, right, bottom and left sides into the
+// longhands `<prefix>-<side>`, or `<prefix>-<side>-<component>` if there's a component. A
+// side without a value takes the value of the opposite side, and with one value every
+// side takes it
+fn expand_sides(
+    prefix: &str,
+    component: Option<&str>,
+    values: Vec<Value>,
+) -> Option<Vec<Declaration>> {
+    let indices = match values.len() {
+        1 => [0, 0, 0, 0],
+        2 => [0, 1, 0, 1],
+        3 => [0, 1, 2, 1],
+        4 => [0, 1, 2, 3],
+        _ => return None,
+    };
+    let sides = ["top", "right", "bottom", "left"];
+    Some(
+        sides
+            .iter()
+            .zip(indices)
+            .map(|(side, i)| Declaration {
+                name: match component {
+                    Some(component) => format!("{}-{}-{}", prefix, side, component).into(),
+                    None => format!("{}-{}", prefix, side).into(),
+                },
+                value: values[i].clone(),
+                important: false,
+            })
+            .collect(),
+    )
+}
+
+// The component values of a longhand: the tokens of a single group, or one value from
+// each of several groups, as a `Value::List`. Color keywords become color values
+fn component_values(mut groups: Vec<Vec<Token>>) -> Option<Vec<Value>> {
+    let values = |group: Vec<Token>| -> Option<Vec<Value>> {
+        group
+            .into_iter()
+            .map(|token| match token {
+                Token::Value(Value::Keyword(name)) => Some(match named_color(&name) {
+                    Some(color) => Value::ColorValue(color),
+                    None => Value::Keyword(name),
+                }),
+                Token::Value(value) => Some(value),
+                Token::Slash => None,
+            })
+            .collect()
+    };
+    if groups.len() == 1 {
+        return values(groups.pop()?);
+    }
+    // Items of a list are single values
+    let list = groups
+        .into_iter()
+        .map(|group| {
+            let mut values = values(group)?;
+            match values.len() {
+                1 => values.pop(),
+                _ => None,
+            }
+        })
+        .collect::<Option<_>>()?;
+    Some(vec![Value::List(list)])
+}
+
+// A list of font families, as a `Value::List` if there's more than one. A family is a
+// quoted string, or identifiers separated by spaces, e.g. `Times New Roman`
+fn font_family(groups: Vec<Vec<Token>>) -> Option<Value> {
+    let mut families = groups
+        .into_iter()
+        .map(|group| {
+            let mut names = Vec::new();
+            for token in group {
+                match token {
+                    Token::Value(Value::StringValue(name)) if names.is_empty() => {
+                        return Some(Value::StringValue(name))
+                    }
+                    Token::Value(Value::Keyword(name)) => names.push(name),
+                    _ => return None,
+                }
+            }
+            Some(Value::Keyword(names.join(" ")))
+        })
+        .collect::<Option<Vec<_>>>()?;
+    match families.len() {
+        1 => families.pop(),
+        _ => Some(Value::List(families)),
+    }
+}
+
+// Expand a `font` shorthand into its longhands: an optional style and weight in either
+// order, the font size with an optional `/<line-height>`, then the family, which runs to
+// the end of the declaration. Components that aren't given are reset to `normal`
+fn expand_font(mut groups: Vec<Vec<Token>>) -> Option<Vec<Declaration>> {
+    let normal = || Value::Keyword("normal".to_string());
+    let mut tokens = std::mem::take(groups.first_mut()?).into_iter().peekable();
+    let (mut style, mut weight) = (None, None);
+    let size = loop {
+        let Token::Value(value) = tokens.next()? else {
+            return None;
+        };
+        let slot = match value {
+            Value::Length(..) => break value,
+            Value::Keyword(ref k) if k == "normal" => None,
+            Value::Keyword(ref k) if matches!(&**k, "italic" | "oblique") => Some(&mut style),
+            Value::Keyword(ref k) if matches!(&**k, "bold" | "bolder" | "lighter") => {
+                Some(&mut weight)
+            }
+            Value::Number(_) => Some(&mut weight),
+            _ => return None,
+        };
+        if slot.is_some_and(|slot| slot.replace(value).is_some()) {
+            return None;
+        }
+    };
+    let line_height = match tokens.next_if_eq(&Token::Slash) {
+        Some(_) => match tokens.next()? {
+            Token::Value(value) => value,
+            Token::Slash => return None,
+        },
+        None => normal(),
+    };
+    // The rest of the first group is the first family
+    groups[0] = tokens.collect();
+    if groups[0].is_empty() {
+        return None;
+    }
+    let family = font_family(groups)?;
+
+    let longhands = [
+        ("font-style", style.unwrap_or_else(normal)),
+        ("font-weight", weight.unwrap_or_else(normal)),
+        ("font-size", size),
+        ("line-height", line_height),
+        ("font-family", family),
+    ];
+    Some(
+        longhands
+            .into_iter()
+            .map(|(name, value)| Declaration {
+                name: name.into(),
+                value,
+                important: false,
+            })
+            .collect(),
+    )
+}
+
+const BORDER_STYLES: &[&str] = &[
+    "none", "hidden", "dotted", "dashed", "solid", "double", "groove", "ridge", "inset", "outset",
+];
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -296,51 +1262,60 @@ mod tests {
                 Rule {
                     selectors: vec![
                         Selector::Simple(SimpleSelector {
-                            tag_name: Some("h1".to_string()),
+                            tag_name: Some("h1".into()),
                             id: None,
                             class: vec![],
+                            pseudo_element: None,
                         }),
                         Selector::Simple(SimpleSelector {
-                            tag_name: Some("h2".to_string()),
+                            tag_name: Some("h2".into()),
                             id: None,
                             class: vec![],
+                            pseudo_element: None,
                         }),
                         Selector::Simple(SimpleSelector {
-                            tag_name: Some("h3".to_string()),
+                            tag_name: Some("h3".into()),
                             id: None,
                             class: vec![],
+                            pseudo_element: None,
                         }),
                     ],
                     declarations: vec![
                         Declaration {
-                            name: "margin".to_string(),
+                            name: "margin".into(),
                             value: Value::Keyword("auto".to_string()),
+                            important: false,
                         },
                         Declaration {
-                            name: "color".to_string(),
+                            name: "color".into(),
                             value: Value::ColorValue(Color {
                                 r: 0xcc,
                                 g: 0x00,
                                 b: 0x00,
+                                a: 0xff,
                             }),
+                            important: false,
                         },
                     ],
                 },
                 // div.note { margin-bottom: 20px; padding: 10px; }
                 Rule {
                     selectors: vec![Selector::Simple(SimpleSelector {
-                        tag_name: Some("div".to_string()),
+                        tag_name: Some("div".into()),
                         id: None,
-                        class: vec!["note".to_string()],
+                        class: vec!["note".into()],
+                        pseudo_element: None,
                     })],
                     declarations: vec![
                         Declaration {
-                            name: "margin-bottom".to_string(),
+                            name: "margin-bottom".into(),
                             value: Value::Length(20.0, Unit::Px),
+                            important: false,
                         },
                         Declaration {
-                            name: "padding".to_string(),
+                            name: "padding".into(),
                             value: Value::Length(10.0, Unit::Px),
+                            important: false,
                         },
                     ],
                 },
@@ -348,17 +1323,691 @@ mod tests {
                 Rule {
                     selectors: vec![Selector::Simple(SimpleSelector {
                         tag_name: None,
-                        id: Some("answer".to_string()),
+                        id: Some("answer".into()),
                         class: vec![],
+                        pseudo_element: None,
                     })],
                     declarations: vec![Declaration {
-                        name: "display".to_string(),
+                        name: "display".into(),
                         value: Value::Keyword("none".to_string()),
+                        important: false,
                     }],
                 },
             ],
+            keyframes: vec![],
+            imports: vec![],
         };
 
         assert_eq!(expected, css);
     }
+
+    #[test]
+    fn parse_missing_trailing_semicolon() {
+        let css = Parser::parse("p { margin : 10px ;color:#ff0000 }".to_string());
+        assert_eq!(
+            vec![
+                Declaration {
+                    name: "margin".into(),
+                    value: Value::Length(10.0, Unit::Px),
+                    important: false,
+                },
+                Declaration {
+                    name: "color".into(),
+                    value: Value::ColorValue(Color {
+                        r: 0xff,
+                        g: 0x00,
+                        b: 0x00,
+                        a: 0xff,
+                    }),
+                    important: false,
+                },
+            ],
+            css.rules[0].declarations
+        );
+    }
+
+    #[test]
+    fn parse_empty_declarations() {
+        let css = Parser::parse("p { ;display: block;; ; }".to_string());
+        assert_eq!(
+            vec![Declaration {
+                name: "display".into(),
+                value: Value::Keyword("block".to_string()),
+                important: false,
+            }],
+            css.rules[0].declarations
+        );
+    }
+
+    #[test]
+    fn parse_recovers_from_malformed_declarations() {
+        let css = Parser::parse(
+            "p { color: #zz0000; margin 10px; width: 10qq; padding: 5px }
+             h1 { display: ; height: 1.2.3px; display: block; }
+             h2 { margin }
+             h3 { display: none; }"
+                .to_string(),
+        );
+        assert_eq!(
+            vec![Declaration {
+                name: "padding".into(),
+                value: Value::Length(5.0, Unit::Px),
+                important: false,
+            }],
+            css.rules[0].declarations
+        );
+        assert_eq!(
+            vec![Declaration {
+                name: "display".into(),
+                value: Value::Keyword("block".to_string()),
+                important: false,
+            }],
+            css.rules[1].declarations
+        );
+        assert!(css.rules[2].declarations.is_empty());
+        assert_eq!(4, css.rules.len());
+    }
+
+    #[test]
+    fn parse_errors() {
+        let error = |source: &str| source.parse::<StyleSheet>().unwrap_err().to_string();
+        assert_eq!(
+            "unexpected '>' in a selector list at byte 4",
+            error("div > p {}")
+        );
+        assert_eq!(
+            "unexpected end of input in a selector list at byte 3",
+            error("div")
+        );
+        assert_eq!(
+            "unsupported pseudo-element 'hover' at byte 2",
+            error("a:hover {}")
+        );
+        assert_eq!(
+            "unexpected keyframe selector 'middle' at byte 15",
+            error("@keyframes x { middle {} }")
+        );
+        assert_eq!(
+            "unexpected end of input where '{' was expected at byte 12",
+            error("@keyframes x")
+        );
+        // Malformed declarations are still skipped rather than failing the stylesheet
+        assert!("div { color: ; width: 1px }".parse::<StyleSheet>().is_ok());
+    }
+
+    #[test]
+    fn astral_plane_text() {
+        let css = Parser::parse("p::before { content: \"👋🏽 𝄞\"; }".to_string());
+        assert_eq!(
+            Value::StringValue("👋🏽 𝄞".to_string()),
+            css.rules[0].declarations[0].value
+        );
+        // Errors at or after emoji are reported without slicing into them
+        let error = |source: &str| Parser::try_parse(source.to_string()).unwrap_err();
+        assert_eq!(0, error("😀").pos);
+        assert_eq!(5, error("p {} 😀").pos);
+    }
+
+    #[test]
+    fn parse_imports() {
+        let css = Parser::parse(
+            "@import \"base.css\";
+             @import url(theme.css) screen;
+             @IMPORT url( 'print.css' );
+             @import url;
+             @import \"paper.css\" print;
+             p { display: block; }"
+                .to_string(),
+        );
+        // Imports for other media are left out
+        assert_eq!(vec!["base.css", "theme.css", "print.css"], css.imports);
+        assert_eq!(1, css.rules.len());
+    }
+
+    #[test]
+    fn parse_media_rules() {
+        let css = Parser::parse(
+            "p { display: block; }
+             @media print { p { display: none; } }
+             @media screen, print {
+                 p { color: #ff0000; }
+                 @media not print { em { font-style: normal; } }
+             }
+             @media (min-width: 600px) { h1 { display: block; } }
+             @media print;
+             h2 { display: block; }"
+                .to_string(),
+        );
+        // The rules of blocks for the screen are kept in place, the others are dropped
+        let selectors: Vec<_> = css
+            .rules
+            .iter()
+            .map(|rule| rule.selectors[0].to_string())
+            .collect();
+        assert_eq!(vec!["p", "p", "em", "h1", "h2"], selectors);
+        assert!(Parser::try_parse("@media print { p > em {} }".to_string()).is_err());
+    }
+
+    #[test]
+    fn media_queries() {
+        assert!(media_matches(""));
+        assert!(media_matches("print, screen"));
+        assert!(media_matches("not print"));
+        assert!(media_matches("ONLY Screen and (color)"));
+        assert!(!media_matches("only print"));
+        assert!(!media_matches("not all and (monochrome)"));
+    }
+
+    #[test]
+    fn parse_keyframes() {
+        let css = Parser::parse(
+            "
+        p { display: block; }
+        @keyframes fade {
+            from { margin: 0px; }
+            50%, 75% { margin: 10px; }
+            to { margin: 20px; }
+        }
+        @import url;
+        h1 { display: none; }
+        "
+            .to_string(),
+        );
+
+        assert_eq!(2, css.rules.len());
+        assert_eq!(
+            vec![Declaration {
+                name: "display".into(),
+                value: Value::Keyword("none".to_string()),
+                important: false,
+            }],
+            css.rules[1].declarations
+        );
+
+        assert_eq!(1, css.keyframes.len());
+        let keyframes = &css.keyframes[0];
+        assert_eq!("fade", keyframes.name);
+        let offsets: Vec<_> = keyframes.frames.iter().map(|f| f.offsets.clone()).collect();
+        assert_eq!(vec![vec![0.0], vec![50.0, 75.0], vec![100.0]], offsets);
+        assert_eq!(
+            vec![Declaration {
+                name: "margin".into(),
+                value: Value::Length(10.0, Unit::Px),
+                important: false,
+            }],
+            keyframes.frames[1].declarations
+        );
+    }
+
+    #[test]
+    fn parse_border_shorthands() {
+        let stylesheet = Parser::parse(
+            "p { border: 2px solid red; border-left: dashed thick; border-top: 1px 2px; }"
+                .to_string(),
+        );
+        let declarations: Vec<(&str, &Value)> = stylesheet.rules[0]
+            .declarations
+            .iter()
+            .map(|d| (&*d.name, &d.value))
+            .collect();
+        assert_eq!(
+            vec![
+                ("border-width", &Value::Length(2.0, Unit::Px)),
+                ("border-style", &Value::Keyword("solid".to_string())),
+                (
+                    "border-color",
+                    &Value::ColorValue(Color {
+                        r: 255,
+                        g: 0,
+                        b: 0,
+                        a: 255
+                    })
+                ),
+                ("border-left-style", &Value::Keyword("dashed".to_string())),
+                ("border-left-width", &Value::Keyword("thick".to_string())),
+            ],
+            declarations
+        );
+    }
+
+    #[test]
+    fn parse_border_side_values() {
+        let widths = |value: &str| {
+            let stylesheet = Parser::parse(format!("p {{ border-width: {}; }}", value));
+            stylesheet.rules[0]
+                .declarations
+                .iter()
+                .map(|d| format!("{}: {}", d.name, d.value))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(vec!["border-width: 1px"], widths("1px"));
+        assert_eq!(
+            vec![
+                "border-top-width: 1px",
+                "border-right-width: 2px",
+                "border-bottom-width: 1px",
+                "border-left-width: 2px",
+            ],
+            widths("1px 2px")
+        );
+        assert_eq!(
+            vec![
+                "border-top-width: 1px",
+                "border-right-width: thin",
+                "border-bottom-width: 3px",
+                "border-left-width: thin",
+            ],
+            widths("1px thin 3px")
+        );
+        assert_eq!(
+            vec![
+                "border-top-width: 1px",
+                "border-right-width: 2px",
+                "border-bottom-width: 3px",
+                "border-left-width: 4px",
+            ],
+            widths("1px 2px 3px 4px")
+        );
+        assert!(widths("1px 2px 3px 4px 5px").is_empty());
+
+        let stylesheet = Parser::parse(
+            "p { border-style: solid none; border-color: #ff0000 #0000ff; }".to_string(),
+        );
+        let names: Vec<_> = stylesheet.rules[0]
+            .declarations
+            .iter()
+            .map(|d| format!("{}: {}", d.name, d.value))
+            .collect();
+        assert_eq!("border-right-style: none", names[1]);
+        assert_eq!("border-left-color: #0000ff", names[7]);
+    }
+
+    #[test]
+    fn parse_margin_and_padding_shorthands() {
+        let sides = |css: &str| -> Vec<String> {
+            Parser::parse(format!("p {{ {} }}", css)).rules[0]
+                .declarations
+                .iter()
+                .map(|d| format!("{}: {}", d.name, d.value))
+                .collect()
+        };
+        // One value is kept as it is
+        assert_eq!(vec!["margin: 4px"], sides("margin: 4px"));
+        assert_eq!(
+            vec![
+                "margin-top: 0",
+                "margin-right: auto",
+                "margin-bottom: 0",
+                "margin-left: auto",
+            ],
+            sides("margin: 0 auto")
+        );
+        assert_eq!(
+            vec![
+                "margin-top: 1px",
+                "margin-right: 2px",
+                "margin-bottom: 3px",
+                "margin-left: 4px",
+            ],
+            sides("margin: 1px 2px 3px 4px")
+        );
+        assert_eq!(
+            vec![
+                "padding-top: 5px",
+                "padding-right: 10px",
+                "padding-bottom: 5px",
+                "padding-left: 10px",
+            ],
+            sides("padding: 5px 10px")
+        );
+        assert!(sides("margin: 1px 2px 3px 4px 5px").is_empty());
+    }
+
+    #[test]
+    fn iterate_declarations() {
+        let stylesheet = Parser::parse(
+            "h1, p { display: block; margin: 0px; } .note { display: none; } div {}".to_string(),
+        );
+        assert_eq!(3, stylesheet.rule_count());
+        assert_eq!(&stylesheet.rules[..], stylesheet.rules());
+
+        let pairs: Vec<(String, &str)> = stylesheet
+            .declarations()
+            .map(|(Selector::Simple(selector), declaration)| {
+                let selector = match (&selector.tag_name, selector.class.first()) {
+                    (Some(tag), _) => tag.to_string(),
+                    (None, Some(class)) => format!(".{}", class),
+                    (None, None) => String::new(),
+                };
+                (selector, &*declaration.name)
+            })
+            .collect();
+        let expected = [
+            ("h1", "display"),
+            ("h1", "margin"),
+            ("p", "display"),
+            ("p", "margin"),
+            (".note", "display"),
+        ];
+        assert_eq!(
+            expected.map(|(selector, name)| (selector.to_string(), name)),
+            &pairs[..]
+        );
+        let display_rules = stylesheet
+            .declarations()
+            .filter(|(_, declaration)| declaration.name == "display")
+            .count();
+        assert_eq!(3, display_rules);
+    }
+
+    #[test]
+    fn parse_font_shorthand() {
+        let parse = |value: &str| {
+            let stylesheet = Parser::parse(format!("p {{ font: {}; color: #000000 }}", value));
+            stylesheet.rules[0]
+                .declarations
+                .iter()
+                .map(|d| (d.name.to_string(), d.value.clone()))
+                .collect::<Vec<_>>()
+        };
+        let keyword = |k: &str| Value::Keyword(k.to_string());
+        let black = (
+            "color".to_string(),
+            Value::ColorValue(Color {
+                r: 0,
+                g: 0,
+                b: 0,
+                a: 255,
+            }),
+        );
+
+        assert_eq!(
+            vec![
+                ("font-style".to_string(), keyword("italic")),
+                ("font-weight".to_string(), keyword("bold")),
+                ("font-size".to_string(), Value::Length(16.0, Unit::Px)),
+                ("line-height".to_string(), Value::Number(1.5)),
+                (
+                    "font-family".to_string(),
+                    Value::List(vec![keyword("Arial"), keyword("sans-serif")])
+                ),
+                black.clone(),
+            ],
+            parse("italic bold 16px/1.5 Arial, sans-serif")
+        );
+        assert_eq!(
+            vec![
+                ("font-style".to_string(), keyword("normal")),
+                ("font-weight".to_string(), keyword("normal")),
+                ("font-size".to_string(), Value::Length(16.0, Unit::Px)),
+                ("line-height".to_string(), keyword("normal")),
+                ("font-family".to_string(), keyword("Arial")),
+                black.clone(),
+            ],
+            parse("16px Arial")
+        );
+        assert_eq!(
+            vec![
+                ("font-style".to_string(), keyword("normal")),
+                ("font-weight".to_string(), Value::Number(700.0)),
+                ("font-size".to_string(), Value::Length(1.2, Unit::Em)),
+                ("line-height".to_string(), Value::Length(20.0, Unit::Px)),
+                (
+                    "font-family".to_string(),
+                    Value::List(vec![
+                        Value::StringValue("Helvetica Neue".to_string()),
+                        keyword("serif")
+                    ])
+                ),
+                black.clone(),
+            ],
+            parse("normal 700 1.2em / 20px \"Helvetica Neue\", serif")
+        );
+        assert_eq!(
+            Some(&("font-family".to_string(), keyword("Times New Roman"))),
+            parse("12px Times  New Roman").get(4)
+        );
+
+        // The size and family are required, and the style and weight can't repeat
+        assert_eq!(vec![black.clone()], parse("bold Arial"));
+        assert_eq!(vec![black.clone()], parse("16px"));
+        assert_eq!(vec![black.clone()], parse("bold bold 16px Arial"));
+        assert_eq!(vec![black], parse("16px Arial 2px"));
+    }
+
+    #[test]
+    fn parse_comma_separated_lists() {
+        let css = Parser::parse(
+            "p { font-family: Arial, \"Helvetica Neue\", sans-serif; }
+             h1 { font-family: Times New Roman, 'serif' }
+             h2 { transition-property: color,background; font-family: Arial; }
+             h3 { margin: 1px, 2px 3px; padding: 1px,; }"
+                .to_string(),
+        );
+        let values = |rule: &Rule| -> Vec<Value> {
+            rule.declarations.iter().map(|d| d.value.clone()).collect()
+        };
+        let keyword = |k: &str| Value::Keyword(k.to_string());
+        assert_eq!(
+            vec![Value::List(vec![
+                keyword("Arial"),
+                Value::StringValue("Helvetica Neue".to_string()),
+                keyword("sans-serif"),
+            ])],
+            values(&css.rules[0])
+        );
+        assert_eq!(
+            vec![Value::List(vec![
+                keyword("Times New Roman"),
+                Value::StringValue("serif".to_string()),
+            ])],
+            values(&css.rules[1])
+        );
+        assert_eq!(
+            vec![
+                Value::List(vec![keyword("color"), keyword("background")]),
+                keyword("Arial"),
+            ],
+            values(&css.rules[2])
+        );
+        // Items of a list are single values
+        assert_eq!(Vec::<Value>::new(), values(&css.rules[3]));
+    }
+
+    #[test]
+    fn parse_value_categories() {
+        let parse = |value: &str| {
+            let stylesheet = Parser::parse(format!("p {{ x: {}; color: #000000 }}", value));
+            let declarations = &stylesheet.rules[0].declarations;
+            match &declarations[..] {
+                [declaration, _] => Some((declaration.value.clone(), declaration.important)),
+                _ => None,
+            }
+        };
+        let keyword = |k: &str| Value::Keyword(k.to_string());
+        let red = Color {
+            r: 255,
+            g: 0,
+            b: 0,
+            a: 255,
+        };
+
+        // Single tokens
+        assert_eq!(Some((keyword("auto"), false)), parse("auto"));
+        assert_eq!(Some((Value::Length(1.5, Unit::Em), false)), parse("1.5em"));
+        assert_eq!(Some((Value::Number(2.0), false)), parse("2"));
+        assert_eq!(Some((Value::ColorValue(red), false)), parse("#ff0000"));
+        // Color keywords are colors, in any case
+        assert_eq!(Some((Value::ColorValue(red), false)), parse("red"));
+        assert_eq!(Some((Value::ColorValue(red), false)), parse("RED"));
+        assert_eq!(
+            Some((
+                Value::List(vec![Value::ColorValue(red), keyword("b")]),
+                false
+            )),
+            parse("red, b")
+        );
+        assert_eq!(
+            Some((Value::StringValue("a; b".to_string()), false)),
+            parse("\"a; b\"")
+        );
+        // `!important` is stripped, with or without space after the `!`
+        assert_eq!(Some((keyword("auto"), true)), parse("auto !important"));
+        assert_eq!(Some((Value::Number(2.0), true)), parse("2!IMPORTANT"));
+        assert_eq!(
+            Some((Value::List(vec![keyword("a"), keyword("b")]), true)),
+            parse("a, b ! important")
+        );
+        assert_eq!(
+            Some((Value::StringValue("!important".to_string()), false)),
+            parse("'!important'")
+        );
+        // A comma-separated list
+        assert_eq!(
+            Some((Value::List(vec![Value::Number(1.0), keyword("b")]), false)),
+            parse("1,b")
+        );
+        // Malformed values: a space-separated list for a single-valued property, a stray
+        // `/`, an empty list item, and a bad or misplaced `!important`
+        assert_eq!(None, parse("1px 2px"));
+        assert_eq!(None, parse("1px / 2px"));
+        assert_eq!(None, parse("a,,b"));
+        assert_eq!(None, parse("auto !default"));
+        assert_eq!(None, parse("auto !important auto"));
+        assert_eq!(None, parse("!important"));
+
+        // The longhands of a shorthand share its importance
+        let stylesheet = Parser::parse("p { border: 1px solid !important }".to_string());
+        assert!(stylesheet.rules[0].declarations.iter().all(|d| d.important));
+        assert_eq!(2, stylesheet.rules[0].declarations.len());
+    }
+
+    #[test]
+    fn parse_linear_gradients() {
+        let parse = |value: &str| {
+            let stylesheet = Parser::parse(format!("p {{ background-image: {}; }}", value));
+            stylesheet.rules[0]
+                .declarations
+                .first()
+                .map(|d| d.value.clone())
+        };
+        let red = Color {
+            r: 255,
+            g: 0,
+            b: 0,
+            a: 255,
+        };
+        let blue = Color {
+            r: 0,
+            g: 0,
+            b: 255,
+            a: 255,
+        };
+        assert_eq!(
+            Some(Value::LinearGradient(LinearGradient {
+                vertical: false,
+                start: red,
+                end: blue
+            })),
+            parse("linear-gradient(to right, #ff0000, #0000ff)")
+        );
+        assert_eq!(
+            Some(Value::LinearGradient(LinearGradient {
+                vertical: true,
+                start: blue,
+                end: red
+            })),
+            parse("linear-gradient( to top,#ff0000 , #0000ff )")
+        );
+        // Stops may be named colors
+        assert_eq!(
+            Some(Value::LinearGradient(LinearGradient {
+                vertical: false,
+                start: red,
+                end: blue
+            })),
+            parse("linear-gradient(to right, red, blue)")
+        );
+        assert_eq!(None, parse("linear-gradient(to right, red, bluish)"));
+        assert_eq!(None, parse("linear-gradient(45deg, #ff0000, #0000ff)"));
+        assert_eq!(None, parse("linear-gradient(to right, #ff0000)"));
+        assert_eq!(None, parse("radial-gradient(#ff0000, #0000ff)"));
+    }
+
+    #[test]
+    fn parse_numbers() {
+        let css = Parser::parse("p { margin-left: -20px; opacity: .5; z-index: -1 }".to_string());
+        let values: Vec<Value> = css.rules[0]
+            .declarations
+            .iter()
+            .map(|d| d.value.clone())
+            .collect();
+        assert_eq!(
+            vec![
+                Value::Length(-20.0, Unit::Px),
+                Value::Number(0.5),
+                Value::Number(-1.0)
+            ],
+            values
+        );
+    }
+
+    #[test]
+    fn parse_pseudo_elements_and_strings() {
+        let css = Parser::parse(
+            "p::before { content: \"\\\"» \"; } p.note:after { content: 'end'; }".to_string(),
+        );
+        let Selector::Simple(ref before) = css.rules[0].selectors[0];
+        let Selector::Simple(ref after) = css.rules[1].selectors[0];
+        assert_eq!(Some(PseudoElement::Before), before.pseudo_element);
+        assert_eq!(Some(PseudoElement::After), after.pseudo_element);
+        assert_eq!((0, 1, 2), css.rules[1].selectors[0].specificity());
+        assert_eq!(
+            Value::StringValue("\"» ".to_string()),
+            css.rules[0].declarations[0].value
+        );
+        assert_eq!(
+            Value::StringValue("end".to_string()),
+            css.rules[1].declarations[0].value
+        );
+
+        let css = Parser::parse("p::first-letter, p:first-line {}".to_string());
+        let pseudo_elements: Vec<_> = css.rules[0]
+            .selectors
+            .iter()
+            .map(|Selector::Simple(simple)| simple.pseudo_element)
+            .collect();
+        assert_eq!(
+            vec![
+                Some(PseudoElement::FirstLetter),
+                Some(PseudoElement::FirstLine)
+            ],
+            pseudo_elements
+        );
+    }
+
+    #[test]
+    fn display_values() {
+        let stylesheet = Parser::parse(
+            "a { margin: 1.5em; width: 50%; color: #ff000080; border-color: #00ff00;
+                 display: block; opacity: 0.5; font-family: Arial, \"Helvetica Neue\"; }"
+                .to_string(),
+        );
+        let values: Vec<String> = stylesheet
+            .declarations()
+            .map(|(_, declaration)| format!("{}: {}", declaration.name, declaration.value))
+            .collect();
+        assert_eq!(
+            vec![
+                "margin: 1.5em",
+                "width: 50%",
+                "color: #ff000080",
+                "border-color: #00ff00",
+                "display: block",
+                "opacity: 0.5",
+                "font-family: Arial, \"Helvetica Neue\"",
+            ],
+            values
+        );
+    }
 }
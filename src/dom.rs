@@ -1,15 +1,21 @@
 use std::collections::{HashMap, HashSet};
 
-use crate::dom;
+use crate::{atom::Atom, css, dom};
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct Node {
     pub node_type: NodeType,
     pub children: Vec<Node>,
 }
 
+// Nodes are written as HTML. The alternate form (`{:#}`) puts each node on its own line,
+// indented by depth, with text quoted so whitespace can be seen
 impl std::fmt::Display for Node {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if f.alternate() {
+            return self.fmt_indented(f, 0);
+        }
         match &self.node_type {
             NodeType::Text(text) => write!(f, "{}", text),
             NodeType::Element(elem) => write!(
@@ -38,7 +44,139 @@ impl std::fmt::Display for Node {
     }
 }
 
+impl Node {
+    fn fmt_indented(&self, f: &mut std::fmt::Formatter<'_>, depth: usize) -> std::fmt::Result {
+        let indent = "  ".repeat(depth);
+        let elem = match self.node_type {
+            NodeType::Text(ref text) => return writeln!(f, "{}{:?}", indent, text),
+            NodeType::Element(ref elem) => elem,
+        };
+        let mut attrs = elem.attributes.iter().collect::<Vec<_>>();
+        attrs.sort();
+        write!(f, "{}<{}", indent, elem.tag_name)?;
+        for (name, value) in attrs {
+            write!(f, " {}=\"{}\"", name, value)?;
+        }
+        if self.children.is_empty() {
+            return writeln!(f, "></{}>", elem.tag_name);
+        }
+        writeln!(f, ">")?;
+        for child in &self.children {
+            child.fmt_indented(f, depth + 1)?;
+        }
+        writeln!(f, "{}</{}>", indent, elem.tag_name)
+    }
+
+    // Iterate over all the nodes under this one, in document order, not including itself
+    pub fn descendants(&self) -> Descendants<'_> {
+        Descendants {
+            stack: self.children.iter().rev().collect(),
+        }
+    }
+
+    // The first node under this one, in document order, that `predicate` accepts
+    pub fn find<F: Fn(&Node) -> bool>(&self, predicate: F) -> Option<&Node> {
+        self.descendants().find(|node| predicate(node))
+    }
+
+    // All the nodes under this one that `predicate` accepts, in document order
+    pub fn find_all<F: Fn(&Node) -> bool>(&self, predicate: F) -> Vec<&Node> {
+        self.descendants().filter(|node| predicate(node)).collect()
+    }
+
+    // Iterate over the direct children of this node, allowing them to be modified in place
+    pub fn children_mut(&mut self) -> std::slice::IterMut<'_, Node> {
+        self.children.iter_mut()
+    }
+
+    // The text of this node and all of its descendants, in document order
+    pub fn text_content(&self) -> String {
+        match self.node_type {
+            NodeType::Text(ref text) => text.clone(),
+            NodeType::Element(_) => self.children.iter().map(Node::text_content).collect(),
+        }
+    }
+}
+
+// An iterator over the descendants of a node, in document order. It keeps the nodes still
+// to visit on a stack, so it doesn't recurse however deep the tree is
+pub struct Descendants<'a> {
+    stack: Vec<&'a Node>,
+}
+
+impl<'a> Iterator for Descendants<'a> {
+    type Item = &'a Node;
+
+    fn next(&mut self) -> Option<&'a Node> {
+        let node = self.stack.pop()?;
+        self.stack.extend(node.children.iter().rev());
+        Some(node)
+    }
+}
+
+// A node of a document, however the document is stored: `&Node` for a tree of nodes, or
+// `arena::NodeRef` for an arena. Selector matching, styling and layout borrow from
+// either, through this
+pub trait DomNode<'a>: Copy {
+    // The tag name, or `None` for a text node
+    fn tag_name(self) -> Option<&'a str>;
+
+    // The text, or `None` for an element
+    fn text(self) -> Option<&'a str>;
+
+    // The value of an attribute, or `None` if it isn't set or this is a text node
+    fn attribute(self, name: &str) -> Option<&'a str>;
+
+    fn children(self) -> impl Iterator<Item = Self> + 'a;
+
+    // Describe the node like a selector, e.g. `div#main.note`, or quote its text
+    fn label(self) -> String {
+        let Some(tag_name) = self.tag_name() else {
+            return format!("{:?}", self.text().unwrap_or_default());
+        };
+        let mut label = tag_name.to_string();
+        if let Some(id) = self.attribute("id") {
+            label = format!("{}#{}", label, id);
+        }
+        if let Some(classes) = self.attribute("class") {
+            for class in classes.split_whitespace() {
+                label = format!("{}.{}", label, class);
+            }
+        }
+        label
+    }
+}
+
+impl<'a> DomNode<'a> for &'a Node {
+    fn tag_name(self) -> Option<&'a str> {
+        match self.node_type {
+            NodeType::Element(ref elem) => Some(&elem.tag_name),
+            NodeType::Text(_) => None,
+        }
+    }
+
+    fn text(self) -> Option<&'a str> {
+        match self.node_type {
+            NodeType::Text(ref text) => Some(text),
+            NodeType::Element(_) => None,
+        }
+    }
+
+    fn attribute(self, name: &str) -> Option<&'a str> {
+        match self.node_type {
+            NodeType::Element(ref elem) => elem.attribute(name).map(String::as_str),
+            NodeType::Text(_) => None,
+        }
+    }
+
+    fn children(self) -> impl Iterator<Item = &'a Node> + 'a {
+        self.children.iter()
+    }
+}
+
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
 pub enum NodeType {
     Text(String),
     Element(ElementData),
@@ -54,25 +192,44 @@ impl std::fmt::Display for NodeType {
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct ElementData {
-    pub tag_name: String,
+    pub tag_name: Atom,
     pub attributes: AttrMap,
 }
 
 impl ElementData {
     pub fn id(&self) -> Option<&String> {
-        self.attributes.get("id")
+        self.attribute("id")
+    }
+
+    pub fn attribute(&self, name: &str) -> Option<&String> {
+        self.attributes.get(name)
     }
 
     pub fn classes(&self) -> HashSet<&str> {
-        match self.attributes.get("class") {
+        match self.attribute("class") {
             Some(class_list) => class_list.split(' ').collect(),
             None => HashSet::new(),
         }
     }
+
+    // Describe the element like a selector, e.g. `div#main.note`
+    pub fn label(&self) -> String {
+        let mut label = self.tag_name.to_string();
+        if let Some(id) = self.id() {
+            label = format!("{}#{}", label, id);
+        }
+        if let Some(classes) = self.attribute("class") {
+            for class in classes.split_whitespace() {
+                label = format!("{}.{}", label, class);
+            }
+        }
+        label
+    }
 }
 
-pub type AttrMap = HashMap<String, String>;
+pub type AttrMap = HashMap<Atom, String>;
 
 pub fn text(data: String) -> Node {
     Node {
@@ -81,22 +238,147 @@ pub fn text(data: String) -> Node {
     }
 }
 
-pub fn elem(name: String, attrs: AttrMap, children: Vec<Node>) -> Node {
+pub fn elem(name: impl Into<Atom>, attrs: AttrMap, children: Vec<Node>) -> Node {
     Node {
         node_type: NodeType::Element(ElementData {
-            tag_name: name,
+            tag_name: name.into(),
             attributes: attrs,
         }),
         children,
     }
 }
 
-pub struct Parser {
+// The nearest of `target` and its ancestors that matches a simple selector like
+// `section.main`, where `target` is a node somewhere under `root`. Elements are matched
+// as in styling, so a selector with a pseudo-element matches nothing. Returns `None` if
+// nothing matches, `target` isn't under `root`, or the selector can't be parsed
+pub fn closest<'a>(root: &'a Node, target: &Node, selector: &str) -> Option<&'a Node> {
+    let selector = css::Selector::Simple(css::parse_selector(selector).ok()?);
+    let mut path = ancestors(root, target)?;
+    // `target` itself, borrowed from `root` rather than from the caller
+    let parent = path.last().copied();
+    let target = match parent {
+        Some(parent) => parent.children.iter().find(|c| std::ptr::eq(*c, target))?,
+        None => root,
+    };
+    path.push(target);
+    path.into_iter().rev().find(|&node| selector.matches(node))
+}
+
+// The chain of nodes from `root` down to the parent of `target`. Nodes don't point to
+// their parents, so this searches down from `root` for `target` by address. Returns
+// `None` if `target` isn't under `root`
+pub(crate) fn ancestors<'a>(root: &'a Node, target: &Node) -> Option<Vec<&'a Node>> {
+    let mut ancestors = Vec::new();
+    find_ancestors(root, target, &mut ancestors).then_some(ancestors)
+}
+
+// Push the nodes from `node` down to the parent of `target` onto `ancestors`, and return
+// whether `target` was found
+fn find_ancestors<'a>(node: &'a Node, target: &Node, ancestors: &mut Vec<&'a Node>) -> bool {
+    if std::ptr::eq(node, target) {
+        return true;
+    }
+    ancestors.push(node);
+    if node
+        .children
+        .iter()
+        .any(|child| find_ancestors(child, target, ancestors))
+    {
+        return true;
+    }
+    ancestors.pop();
+    false
+}
+
+// Elements that never have contents, so they have no closing tag
+const VOID_ELEMENTS: &[&str] = &[
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "source", "track",
+    "wbr",
+];
+
+// Elements whose contents are text up to their closing tag, even if it has `<` in it
+const RAW_TEXT_ELEMENTS: &[&str] = &["script", "style"];
+
+pub struct Parser<'a> {
     pos: usize,
-    input: String,
+    input: &'a str,
+    options: ParseOptions,
+    // The name and value of each attribute of the current tag
+    attributes: Vec<(&'a str, &'a str)>,
+}
+
+// An element whose children are being parsed
+struct OpenElement<'a, H> {
+    element: H,
+    // The tag name, to match the closing tag against
+    tag_name: &'a str,
+    // Where the element's children start in the list of parsed nodes
+    children: usize,
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ParseOptions {
+    // Keep whitespace between tags as text nodes, instead of discarding it.
+    // Needed for `white-space: pre` content
+    pub preserve_whitespace: bool,
+}
+
+// Where the parser puts the nodes it parses: into a tree of `Node`s, or into the arena of
+// an `arena::Document`. An element is created as soon as its opening tag is parsed, and
+// given its children once its closing tag is
+pub(crate) trait TreeSink {
+    type Handle;
+
+    fn text(&mut self, text: &str) -> Self::Handle;
+
+    fn element<'i>(
+        &mut self,
+        tag_name: &str,
+        attributes: impl Iterator<Item = (&'i str, &'i str)>,
+    ) -> Self::Handle;
+
+    fn set_children(
+        &mut self,
+        element: &mut Self::Handle,
+        children: impl Iterator<Item = Self::Handle>,
+    );
+
+    // Whether the node is text that's all whitespace
+    fn is_blank(&self, node: &Self::Handle) -> bool;
+}
+
+// Builds the tree of `Node`s that `Parser` returns
+struct NodeSink;
+
+impl TreeSink for NodeSink {
+    type Handle = Node;
+
+    fn text(&mut self, text: &str) -> Node {
+        dom::text(text.to_string())
+    }
+
+    fn element<'i>(
+        &mut self,
+        tag_name: &str,
+        attributes: impl Iterator<Item = (&'i str, &'i str)>,
+    ) -> Node {
+        let attributes = attributes
+            .map(|(name, value)| (Atom::from(name), value.to_string()))
+            .collect();
+        dom::elem(tag_name, attributes, Vec::new())
+    }
+
+    fn set_children(&mut self, element: &mut Node, children: impl Iterator<Item = Node>) {
+        element.children = children.collect();
+    }
+
+    fn is_blank(&self, node: &Node) -> bool {
+        matches!(node.node_type, NodeType::Text(ref t) if t.trim().is_empty())
+    }
 }
 
-impl Parser {
+impl<'a> Parser<'a> {
     // Read the current character without consuming it
     fn next_char(&self) -> char {
         self.input[self.pos..].chars().next().unwrap()
@@ -114,23 +396,21 @@ impl Parser {
 
     // Return the current character, and advance self.pos to the next character
     fn consume_char(&mut self) -> char {
-        let mut iter = self.input[self.pos..].char_indices();
-        let (_, cur_char) = iter.next().unwrap();
-        let (next_pos, _) = iter.next().unwrap_or((1, ' '));
-        self.pos += next_pos;
+        let cur_char = self.input[self.pos..].chars().next().unwrap();
+        self.pos += cur_char.len_utf8();
         cur_char
     }
 
-    // Consume characters until `test` returns false
-    fn consume_while<F>(&mut self, test: F) -> String
+    // Consume characters until `test` returns false, returning them as a slice of the input
+    fn consume_while<F>(&mut self, test: F) -> &'a str
     where
         F: Fn(char) -> bool,
     {
-        let mut result = String::new();
+        let start = self.pos;
         while !self.eof() && test(self.next_char()) {
-            result.push(self.consume_char())
+            self.consume_char();
         }
-        result
+        &self.input[start..self.pos]
     }
 
     // Consume and discard zero or more whitespace characters
@@ -139,105 +419,345 @@ impl Parser {
     }
 
     // Parse a tag or attribute name
-    fn parse_tag_name(&mut self) -> String {
+    fn parse_tag_name(&mut self) -> &'a str {
         self.consume_while(|c| matches!(c, 'a'..='z' | 'A'..='Z' | '0'..='9'))
     }
 
-    // Parse a single name="value" pair.
-    fn parse_attr(&mut self) -> (String, String) {
-        let name = self.parse_tag_name();
-        assert!(self.consume_char() == '=');
-        let value = self.parse_attr_value();
-        (name, value)
+    // Consume the given character, or fail if the input has something else
+    fn expect_char(&mut self, expected: char) -> Result<(), ParseError> {
+        if self.eof() {
+            return Err(self.error(format!("expected '{}', found end of input", expected)));
+        }
+        let pos = self.pos;
+        match self.consume_char() {
+            c if c == expected => Ok(()),
+            c => Err(ParseError {
+                pos,
+                message: format!("expected '{}', found '{}'", expected, c),
+            }),
+        }
+    }
+
+    // An error at the current position
+    fn error(&self, message: String) -> ParseError {
+        ParseError {
+            pos: self.pos,
+            message,
+        }
+    }
+
+    // Parse a single name="value" pair. An attribute written without a value, like
+    // `disabled`, has an empty value
+    fn parse_attr(&mut self) -> Result<(&'a str, &'a str), ParseError> {
+        let name = self.consume_while(|c| c.is_alphanumeric() || matches!(c, '-' | '_' | ':'));
+        if name.is_empty() {
+            let c = self.next_char();
+            return Err(self.error(format!("unexpected '{}' in a tag", c)));
+        }
+        self.consume_whitespace();
+        if self.eof() || self.next_char() != '=' {
+            return Ok((name, ""));
+        }
+        self.consume_char();
+        self.consume_whitespace();
+        let value = self.parse_attr_value()?;
+        Ok((name, value))
     }
 
     // Parse q quoted value
-    fn parse_attr_value(&mut self) -> String {
-        let open_quote = self.consume_char();
-        assert!(open_quote == '"' || open_quote == '\'');
+    fn parse_attr_value(&mut self) -> Result<&'a str, ParseError> {
+        if self.eof() {
+            return Err(self.error("expected a quoted value, found end of input".to_string()));
+        }
+        let open_quote = self.next_char();
+        if open_quote != '"' && open_quote != '\'' {
+            return Err(self.error(format!("expected a quoted value, found '{}'", open_quote)));
+        }
+        self.consume_char();
         let value = self.consume_while(|c| c != open_quote);
-        assert!(self.consume_char() == open_quote);
-        value
+        self.expect_char(open_quote)?;
+        Ok(value)
     }
 
-    // Parse a list of name="value" pairs, separated by whitespace
-    fn parse_attributes(&mut self) -> dom::AttrMap {
-        let mut attributes = HashMap::new();
+    // Parse a list of name="value" pairs, separated by whitespace, up to the `>` or `/>`
+    // that ends the tag, into `self.attributes`. A stray `/` is skipped
+    fn parse_attributes(&mut self) -> Result<(), ParseError> {
+        self.attributes.clear();
         loop {
             self.consume_whitespace();
-            if self.next_char() == '>' {
+            if self.eof() || self.next_char() == '>' || self.starts_with("/>") {
                 break;
             }
-            let (name, value) = self.parse_attr();
-            attributes.insert(name, value);
-        }
-        attributes
-    }
-
-    // Parse a single node
-    fn parse_node(&mut self) -> dom::Node {
-        match self.next_char() {
-            '<' => self.parse_element(),
-            _ => self.parse_text(),
+            if self.next_char() == '/' {
+                self.consume_char();
+                continue;
+            }
+            let attribute = self.parse_attr()?;
+            self.attributes.push(attribute);
         }
+        Ok(())
     }
 
     // Parse a text node
-    fn parse_text(&mut self) -> dom::Node {
-        dom::text(self.consume_while(|c| c != '<'))
+    fn parse_text<S: TreeSink>(&mut self, sink: &mut S) -> S::Handle {
+        let text = self.consume_while(|c| c != '<');
+        sink.text(text)
     }
 
-    // Parse a single element, including its open tag, contents, and closing tag
-    fn parse_element(&mut self) -> dom::Node {
-        // Opening tag
-        assert!(self.consume_char() == '<');
+    // Parse the opening tag of an element. An element with no contents to parse, because
+    // it's void, self-closing or a raw text element, is parsed to its end and pushed onto
+    // `nodes`. Otherwise it's returned, to be closed once its children are parsed
+    fn parse_opening_tag<S: TreeSink>(
+        &mut self,
+        sink: &mut S,
+        nodes: &mut Vec<S::Handle>,
+    ) -> Result<Option<OpenElement<'a, S::Handle>>, ParseError> {
+        self.expect_char('<')?;
         let tag_name = self.parse_tag_name();
-        let attrs = self.parse_attributes();
-        assert!(self.consume_char() == '>');
+        self.parse_attributes()?;
+        let mut element = sink.element(tag_name, self.attributes.iter().copied());
+        let is_one_of =
+            |names: &[&str], input: &str| names.iter().any(|name| name.eq_ignore_ascii_case(input));
+
+        // Void elements end with their opening tag, and so does any self-closing tag
+        if self.starts_with("/>") || is_one_of(VOID_ELEMENTS, tag_name) {
+            if self.starts_with("/") {
+                self.consume_char();
+            }
+            self.expect_char('>')?;
+            nodes.push(element);
+            return Ok(None);
+        }
+        self.expect_char('>')?;
+
+        if is_one_of(RAW_TEXT_ELEMENTS, tag_name) {
+            if let Some(text) = self.parse_raw_text(tag_name)? {
+                let text = sink.text(text);
+                sink.set_children(&mut element, std::iter::once(text));
+            }
+            self.parse_closing_tag(tag_name)?;
+            nodes.push(element);
+            return Ok(None);
+        }
+        Ok(Some(OpenElement {
+            element,
+            tag_name,
+            children: nodes.len(),
+        }))
+    }
 
-        // Contents
-        let children = self.parse_nodes();
+    // Parse the closing tag of the element named `tag_name`
+    fn parse_closing_tag(&mut self, tag_name: &str) -> Result<(), ParseError> {
+        self.expect_char('<')?;
+        self.expect_char('/')?;
+        let pos = self.pos;
+        let closing_name = self.parse_tag_name();
+        // Tag names are case-insensitive, so `<p>` may be closed by `</P>`
+        if !closing_name.eq_ignore_ascii_case(tag_name) {
+            return Err(ParseError {
+                pos,
+                message: format!("expected </{}>, found </{}>", tag_name, closing_name),
+            });
+        }
+        self.consume_whitespace();
+        self.expect_char('>')
+    }
 
-        // Closing tag
-        assert!(self.consume_char() == '<');
-        assert!(self.consume_char() == '/');
-        assert!(self.parse_tag_name() == tag_name);
-        assert!(self.consume_char() == '>');
+    // Parse the contents of a raw text element, up to its closing tag, returning them
+    // unless they're empty
+    fn parse_raw_text(&mut self, name: &str) -> Result<Option<&'a str>, ParseError> {
+        // The closing tag may be in a different case, e.g. `</SCRIPT>`, and its name has to
+        // end there, so `</scripts>` doesn't close a `<script>`
+        let rest = &self.input[self.pos..];
+        let Some(len) = rest.match_indices("</").map(|(i, _)| i).find(|&i| {
+            let after = &rest[i + 2..];
+            after
+                .get(..name.len())
+                .is_some_and(|closing_name| closing_name.eq_ignore_ascii_case(name))
+                && after[name.len()..]
+                    .chars()
+                    .next()
+                    .is_some_and(|c| c == '>' || c == '/' || c.is_whitespace())
+        }) else {
+            let message = format!("expected </{}>, found end of input", name);
+            return Err(self.error(message));
+        };
+        let text = &rest[..len];
+        self.pos += len;
+        Ok(Some(text).filter(|text| !text.is_empty()))
+    }
 
-        dom::elem(tag_name, attrs, children)
+    // Skip a `<!-- comment -->`
+    fn skip_comment(&mut self) -> Result<(), ParseError> {
+        match self.input[self.pos + 4..].find("-->") {
+            Some(end) => {
+                self.pos += 4 + end + 3;
+                Ok(())
+            }
+            None => Err(self.error("unterminated comment".to_string())),
+        }
     }
 
-    // Parse a sequence of sibling nodes
-    fn parse_nodes(&mut self) -> Vec<dom::Node> {
-        let mut nodes = Vec::new();
+    // Parse a sequence of sibling nodes onto the end of `nodes`. Elements are parsed
+    // without recursion, however deeply they're nested: the elements whose children are
+    // being parsed are kept on a stack, and their children on the end of `nodes` until
+    // they're closed
+    fn parse_nodes<S: TreeSink>(
+        &mut self,
+        sink: &mut S,
+        nodes: &mut Vec<S::Handle>,
+    ) -> Result<(), ParseError> {
+        let mut open: Vec<OpenElement<'a, S::Handle>> = Vec::new();
         loop {
-            self.consume_whitespace();
+            if !self.options.preserve_whitespace {
+                self.consume_whitespace();
+            }
             if self.eof() || self.starts_with("</") {
-                break;
+                let Some(mut open_element) = open.pop() else {
+                    break;
+                };
+                let children = nodes.drain(open_element.children..);
+                sink.set_children(&mut open_element.element, children);
+                self.parse_closing_tag(open_element.tag_name)?;
+                nodes.push(open_element.element);
+                continue;
+            }
+            if self.starts_with("<!--") {
+                self.skip_comment()?;
+                continue;
+            }
+            if self.next_char() == '<' {
+                open.extend(self.parse_opening_tag(sink, nodes)?);
+            } else {
+                let text = self.parse_text(sink);
+                nodes.push(text);
             }
-            nodes.push(self.parse_node());
         }
-        nodes
+        Ok(())
     }
 
-    // Parse an HTML document and return the root element
+    // Parse an HTML document and return the root element. Panics if the document is
+    // malformed
     pub fn parse(source: String) -> dom::Node {
-        let mut nodes = Parser {
-            pos: 0,
-            input: source,
-        }
-        .parse_nodes();
+        Self::parse_with_options(source, ParseOptions::default())
+    }
+
+    // Parse an HTML document with the given options and return the root element. Panics
+    // if the document is malformed
+    pub fn parse_with_options(source: String, options: ParseOptions) -> dom::Node {
+        Self::try_parse_with_options(source, options).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    // Parse an HTML document and return the root element, or where and why it's malformed
+    pub fn try_parse(source: String) -> Result<dom::Node, ParseError> {
+        Self::try_parse_with_options(source, ParseOptions::default())
+    }
+
+    // Parse an HTML document with the given options and return the root element, or where
+    // and why it's malformed
+    pub fn try_parse_with_options(
+        source: String,
+        options: ParseOptions,
+    ) -> Result<dom::Node, ParseError> {
+        Self::parse_into(source, options, &mut NodeSink)
+    }
+
+    // Parse an HTML document into `sink`, returning its root element
+    pub(crate) fn parse_into<S: TreeSink>(
+        source: String,
+        options: ParseOptions,
+        sink: &mut S,
+    ) -> Result<S::Handle, ParseError> {
+        let mut nodes = Self::parse_top_level(source, options, sink)?;
 
         // If the document contains a root element, just return it.
         // Otherwise, create one.
         if nodes.len() == 1 {
-            nodes.swap_remove(0)
+            Ok(nodes.swap_remove(0))
         } else {
-            dom::elem("html".to_string(), HashMap::new(), nodes)
+            let mut root = sink.element("html", std::iter::empty());
+            sink.set_children(&mut root, nodes.into_iter());
+            Ok(root)
+        }
+    }
+
+    // Parse a piece of HTML and return its top-level nodes as they are, without wrapping
+    // them in a root element
+    pub fn parse_fragment(source: String) -> Result<Vec<dom::Node>, ParseError> {
+        Self::parse_fragment_with_options(source, ParseOptions::default())
+    }
+
+    // Parse a piece of HTML with the given options and return its top-level nodes
+    pub fn parse_fragment_with_options(
+        source: String,
+        options: ParseOptions,
+    ) -> Result<Vec<dom::Node>, ParseError> {
+        Self::parse_top_level(source, options, &mut NodeSink)
+    }
+
+    fn parse_top_level<S: TreeSink>(
+        source: String,
+        options: ParseOptions,
+        sink: &mut S,
+    ) -> Result<Vec<S::Handle>, ParseError> {
+        let mut parser = Parser {
+            pos: 0,
+            input: &source,
+            options,
+            attributes: Vec::new(),
+        };
+        let mut nodes = Vec::new();
+        parser.parse_nodes(sink, &mut nodes)?;
+        if !parser.eof() {
+            return Err(parser.error("unexpected closing tag".to_string()));
         }
+
+        // Whitespace outside of the top-level elements is never significant
+        nodes.retain(|node| !sink.is_blank(node));
+        Ok(nodes)
     }
 }
 
+// Parse an HTML document into its root element, or return where and why it's malformed
+pub fn parse(source: &str) -> Result<Node, ParseError> {
+    Parser::try_parse(source.to_string())
+}
+
+/// Parse an HTML document into its root element, like `Parser::parse`, but returning an
+/// error for malformed HTML instead of panicking.
+///
+/// ```
+/// use robinson::dom::{Node, NodeType};
+///
+/// let node: Node = "<p class=\"note\">Hello</p>".parse()?;
+/// assert!(matches!(node.node_type, NodeType::Element(ref e) if e.tag_name == "p"));
+/// assert!("<p>Hello</div>".parse::<Node>().is_err());
+/// # Ok::<(), robinson::dom::ParseError>(())
+/// ```
+impl std::str::FromStr for Node {
+    type Err = ParseError;
+
+    fn from_str(source: &str) -> Result<Node, ParseError> {
+        Parser::try_parse(source.to_string())
+    }
+}
+
+// Why a piece of HTML couldn't be parsed, and the byte offset in the source where
+// parsing stopped
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    pub pos: usize,
+    pub message: String,
+}
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} at byte {}", self.message, self.pos)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -276,14 +796,23 @@ mod tests {
         let node = elem(
             String::from("div"),
             HashMap::from([
-                (String::from("a"), String::from("b")),
-                (String::from("c"), String::from("d")),
+                (Atom::from("a"), String::from("b")),
+                (Atom::from("c"), String::from("d")),
             ]),
             Vec::new(),
         );
         assert_eq!("<div a=\"b\" c=\"d\"></div>", format!("{}", node));
     }
 
+    #[test]
+    fn display_pretty() {
+        let node = Parser::parse("<div id=\"a\"><p>one <b>two</b></p><br></div>".to_string());
+        assert_eq!(
+            "<div id=\"a\">\n  <p>\n    \"one \"\n    <b>\n      \"two\"\n    </b>\n  </p>\n  <br></br>\n</div>\n",
+            format!("{:#}", node)
+        );
+    }
+
     #[test]
     fn parse_simple() {
         let node = Parser::parse(
@@ -300,4 +829,195 @@ mod tests {
             format!("{}", node)
         );
     }
+
+    #[test]
+    fn find_by_predicate() {
+        let root = Parser::parse(
+            "<form><button type=\"text\" name=\"q\"></button><p>hi</p>\
+             <button type=\"submit\" name=\"go\"></button>\
+             <div><button type=\"text\" name=\"r\"></button></div></form>"
+                .to_string(),
+        );
+        let attribute = |node: &Node, name: &str| match node.node_type {
+            NodeType::Element(ref elem) => elem.attribute(name).cloned(),
+            NodeType::Text(_) => None,
+        };
+        let is_text_input = |node: &Node| attribute(node, "type").as_deref() == Some("text");
+
+        let first = root.find(is_text_input).unwrap();
+        assert_eq!(Some("q".to_string()), attribute(first, "name"));
+        let names: Vec<_> = root
+            .find_all(is_text_input)
+            .into_iter()
+            .filter_map(|node| attribute(node, "name"))
+            .collect();
+        assert_eq!(vec!["q", "r"], names);
+        assert!(root
+            .find(|node| attribute(node, "type").as_deref() == Some("file"))
+            .is_none());
+
+        // Descendants are in document order, and don't include the node itself
+        assert_eq!(6, root.descendants().count());
+        assert!(root.find(|node| std::ptr::eq(node, &root)).is_none());
+    }
+
+    #[test]
+    fn children_mut() {
+        let mut node = Parser::parse("<p>one<b>two</b>three</p>".to_string());
+        for child in node.children_mut() {
+            if let NodeType::Text(ref mut text) = child.node_type {
+                *text = text.to_uppercase();
+            }
+        }
+        assert_eq!("<p>ONE<b>two</b>THREE</p>", format!("{}", node));
+    }
+
+    #[test]
+    fn parse_preserving_whitespace() {
+        let source = "\n<pre>\n  a <b>b</b> <i>c</i></pre>\n".to_string();
+        let options = ParseOptions {
+            preserve_whitespace: true,
+        };
+        let node = Parser::parse_with_options(source.clone(), options);
+        assert_eq!("<pre>\n  a <b>b</b> <i>c</i></pre>", format!("{}", node));
+
+        let node = Parser::parse(source);
+        assert_eq!("<pre>a <b>b</b><i>c</i></pre>", format!("{}", node));
+    }
+
+    #[test]
+    fn parse_fragment_without_root() {
+        let nodes =
+            Parser::parse_fragment("<p>one</p>\n<p>two</p>\n<p>three</p>".to_string()).unwrap();
+        assert_eq!(3, nodes.len());
+        let html: Vec<String> = nodes.iter().map(|node| node.to_string()).collect();
+        assert_eq!(vec!["<p>one</p>", "<p>two</p>", "<p>three</p>"], html);
+    }
+
+    #[test]
+    fn parse_fragment_errors() {
+        let error = Parser::parse_fragment("<p>one</b>".to_string()).unwrap_err();
+        assert_eq!("expected </p>, found </b> at byte 8", error.to_string());
+        assert!(Parser::parse_fragment("<p a=b></p>".to_string()).is_err());
+        assert!(Parser::parse_fragment("<p>unclosed".to_string()).is_err());
+        assert!(Parser::parse_fragment("</p>".to_string()).is_err());
+    }
+
+    #[test]
+    fn void_elements() {
+        let node =
+            Parser::parse("<p>a<img src=\"a.png\" width=\"2\">b<br/>c<hr /></p>".to_string());
+        assert_eq!(
+            "<p>a<img src=\"a.png\" width=\"2\"></img>b<br></br>c<hr></hr></p>",
+            format!("{}", node)
+        );
+    }
+
+    #[test]
+    fn astral_plane_text() {
+        // Emoji, including one ending the input, are kept whole
+        let node = Parser::parse("<p title=\"🎉\">ok 👋🏽 <b>𝄞</b></p>😀".to_string());
+        assert_eq!("ok 👋🏽 𝄞😀", node.text_content());
+        let NodeType::Element(ref p) = node.children[0].node_type else {
+            panic!("expected an element")
+        };
+        assert_eq!("🎉", p.attribute("title").unwrap());
+        assert_eq!(
+            "unexpected '🎉' in a tag at byte 2",
+            Parser::try_parse("<p🎉".to_string())
+                .unwrap_err()
+                .to_string()
+        );
+    }
+
+    #[test]
+    fn raw_text_elements() {
+        let node = Parser::parse(
+            "<head><style>a < b, p > em { }</style><script>if (a<b) {}</script></head>".to_string(),
+        );
+        let text: Vec<_> = node
+            .children
+            .iter()
+            .map(|child| match child.children[0].node_type {
+                NodeType::Text(ref text) => &**text,
+                _ => panic!("expected text"),
+            })
+            .collect();
+        assert_eq!(vec!["a < b, p > em { }", "if (a<b) {}"], text);
+
+        // The closing tag is matched ignoring case, and only where its name ends
+        let script = |html: &str| {
+            let node = Parser::parse(html.to_string());
+            match node.children[0].node_type {
+                NodeType::Text(ref text) => text.to_string(),
+                _ => panic!("expected text"),
+            }
+        };
+        assert_eq!("x", script("<script>x</SCRIPT>"));
+        assert_eq!("a</scripts>b", script("<script>a</scripts>b</script >"));
+        assert_eq!("<p>", script("<Script><p></sCript>"));
+        assert_eq!(
+            "expected </style>, found end of input at byte 7",
+            Parser::try_parse("<style>p {}".to_string())
+                .unwrap_err()
+                .to_string()
+        );
+    }
+
+    #[test]
+    fn attributes_across_lines() {
+        let node = Parser::parse(
+            "<div>
+                <!-- a self-closing element -->
+                <widget
+                    id=\"w\"
+                    data-kind = 'round'
+                    hidden
+                    / class=\"a b\"
+                />
+                <p>text<!-- not text --></p>
+            </div>"
+                .to_string(),
+        );
+        assert_eq!(
+            "<div><widget class=\"a b\" data-kind=\"round\" hidden=\"\" id=\"w\"></widget>\
+             <p>text</p></div>",
+            format!("{}", node)
+        );
+
+        // Unexpected characters are reported, rather than looping or panicking
+        let error = Parser::parse_fragment("<p a=\"b\" \"c\"></p>".to_string()).unwrap_err();
+        assert_eq!("unexpected '\"' in a tag at byte 9", error.to_string());
+        assert!(Parser::parse_fragment("<p a=\"b\"".to_string()).is_err());
+        assert!(Parser::parse_fragment("<p><!-- open</p>".to_string()).is_err());
+    }
+
+    #[test]
+    fn closest_section() {
+        let root = parse(
+            "<section id=\"outer\"><section id=\"inner\" class=\"main\">\
+             <div><p><em>deep</em></p></div></section></section>",
+        )
+        .unwrap();
+        let inner = &root.children[0];
+        let em = &inner.children[0].children[0].children[0];
+        let id = |node: Option<&Node>| match node.map(|node| &node.node_type) {
+            Some(NodeType::Element(elem)) => elem.id().cloned(),
+            _ => None,
+        };
+
+        assert_eq!(Some("inner".to_string()), id(closest(&root, em, "section")));
+        assert_eq!(Some("outer".to_string()), id(closest(&root, em, "#outer")));
+        // The target itself is a candidate
+        assert!(std::ptr::eq(em, closest(&root, em, "em").unwrap()));
+        assert!(std::ptr::eq(inner, closest(&root, inner, ".main").unwrap()));
+
+        assert!(closest(&root, em, "article").is_none());
+        assert!(closest(&root, em, "section > p").is_none());
+        // A pseudo-element isn't the element itself
+        let p = &inner.children[0].children[0];
+        assert!(std::ptr::eq(p, closest(&root, em, "p").unwrap()));
+        assert!(closest(&root, em, "p::before").is_none());
+        assert!(closest(&root, &parse("<em></em>").unwrap(), "em").is_none());
+    }
 }
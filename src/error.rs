@@ -0,0 +1,72 @@
+// The error type for the crate as a whole, so code that goes through several stages can
+// use `?` on each of them and return one type. Each variant wraps the error of the stage
+// that failed, which `source` returns
+
+use std::io;
+
+use crate::{css, dom};
+
+#[cfg(feature = "layout")]
+use crate::layout;
+
+// Why a page couldn't be parsed, rendered or written out
+#[derive(Debug)]
+pub enum Error {
+    HtmlParse(dom::ParseError),
+    // The index of the stylesheet that failed, and why. A stylesheet parsed on its own is
+    // index 0
+    CssParse(usize, css::ParseError),
+    #[cfg(feature = "layout")]
+    Layout(layout::LayoutError),
+    Io(io::Error),
+}
+
+// Parse errors include the byte offset where parsing stopped
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::HtmlParse(e) => write!(f, "invalid HTML: {}", e),
+            Error::CssParse(_, e) => write!(f, "invalid CSS: {}", e),
+            #[cfg(feature = "layout")]
+            Error::Layout(e) => write!(f, "couldn't lay out the page: {}", e),
+            Error::Io(e) => write!(f, "I/O error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::HtmlParse(e) => Some(e),
+            Error::CssParse(_, e) => Some(e),
+            #[cfg(feature = "layout")]
+            Error::Layout(e) => Some(e),
+            Error::Io(e) => Some(e),
+        }
+    }
+}
+
+impl From<dom::ParseError> for Error {
+    fn from(error: dom::ParseError) -> Error {
+        Error::HtmlParse(error)
+    }
+}
+
+impl From<css::ParseError> for Error {
+    fn from(error: css::ParseError) -> Error {
+        Error::CssParse(0, error)
+    }
+}
+
+#[cfg(feature = "layout")]
+impl From<layout::LayoutError> for Error {
+    fn from(error: layout::LayoutError) -> Error {
+        Error::Layout(error)
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Error {
+        Error::Io(error)
+    }
+}
@@ -0,0 +1,279 @@
+// Loading pages over HTTP(S), with the `http` feature. A page's stylesheets, the ones it
+// links to with `<link rel="stylesheet">` and the ones those `@import`, are fetched too,
+// with relative URLs resolved against the URL of the document that refers to them
+
+use std::{io::Read, time::Duration};
+
+use url::Url;
+
+use crate::{
+    css,
+    dom::{self, Node, NodeType},
+};
+
+// The default limits on each response: its size in bytes, and how long fetching it can take
+pub const MAX_BYTES: u64 = 10 * 1024 * 1024;
+pub const TIMEOUT: Duration = Duration::from_secs(30);
+
+// How deeply `@import`s are followed, which also stops import cycles
+const MAX_IMPORT_DEPTH: usize = 8;
+
+// A page's HTML, and the text of all of its stylesheets in cascade order
+pub struct Page {
+    pub html: String,
+    pub css: String,
+}
+
+// Why a document couldn't be fetched
+#[derive(Debug, Clone, PartialEq)]
+pub struct FetchError {
+    pub url: String,
+    pub message: String,
+}
+
+impl std::fmt::Display for FetchError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.url, self.message)
+    }
+}
+
+impl std::error::Error for FetchError {}
+
+pub struct Fetcher {
+    agent: ureq::Agent,
+    max_bytes: u64,
+}
+
+impl Default for Fetcher {
+    fn default() -> Fetcher {
+        Fetcher::new(TIMEOUT, MAX_BYTES)
+    }
+}
+
+impl Fetcher {
+    // A fetcher giving up on responses that take longer than `timeout` or are larger than
+    // `max_bytes`
+    pub fn new(timeout: Duration, max_bytes: u64) -> Fetcher {
+        Fetcher {
+            agent: ureq::AgentBuilder::new().timeout(timeout).build(),
+            max_bytes,
+        }
+    }
+
+    // Fetch a page and its stylesheets. A stylesheet that can't be fetched fails the whole
+    // page, rather than rendering it unstyled
+    pub fn fetch_page(&self, url: &str) -> Result<Page, FetchError> {
+        let url = parse_url(url)?;
+        let html = self.fetch(&url)?;
+        let root = dom::Parser::try_parse(html.clone()).map_err(|e| FetchError {
+            url: url.to_string(),
+            message: e.to_string(),
+        })?;
+
+        let mut css = String::new();
+        for href in stylesheet_links(&root) {
+            let href = resolve(&url, &href)?;
+            self.fetch_stylesheet(&href, 0, &mut css)?;
+        }
+        Ok(Page { html, css })
+    }
+
+    // Fetch a document, given its URL as text
+    pub fn fetch_document(&self, url: &str) -> Result<String, FetchError> {
+        self.fetch(&parse_url(url)?)
+    }
+
+    // Fetch a stylesheet linked to from the document at `base`, with the stylesheets it
+    // imports before it, like `fetch_page` does for each of the page's links
+    pub fn fetch_linked_stylesheet(&self, base: &str, href: &str) -> Result<String, FetchError> {
+        let url = resolve(&parse_url(base)?, href)?;
+        let mut css = String::new();
+        self.fetch_stylesheet(&url, 0, &mut css)?;
+        Ok(css)
+    }
+
+    // Fetch a stylesheet, appending it to `css` after the stylesheets it imports
+    fn fetch_stylesheet(
+        &self,
+        url: &Url,
+        depth: usize,
+        css: &mut String,
+    ) -> Result<(), FetchError> {
+        let source = self.fetch(url)?;
+        if depth < MAX_IMPORT_DEPTH {
+            // Malformed stylesheets fail later, when the page is styled
+            let imports =
+                css::Parser::try_parse(source.clone()).map_or_else(|_| Vec::new(), |s| s.imports);
+            for import in imports {
+                self.fetch_stylesheet(&resolve(url, &import)?, depth + 1, css)?;
+            }
+        }
+        css.push_str(&source);
+        css.push('\n');
+        Ok(())
+    }
+
+    // Fetch a document as text, which must be UTF-8
+    pub fn fetch(&self, url: &Url) -> Result<String, FetchError> {
+        let error = |message: String| FetchError {
+            url: url.to_string(),
+            message,
+        };
+        let response = self
+            .agent
+            .request_url("GET", url)
+            .call()
+            .map_err(|e| match e {
+                ureq::Error::Status(code, response) => {
+                    error(format!("HTTP {} {}", code, response.status_text()))
+                }
+                ureq::Error::Transport(e) => error(e.to_string()),
+            })?;
+
+        let mut bytes = Vec::new();
+        response
+            .into_reader()
+            .take(self.max_bytes + 1)
+            .read_to_end(&mut bytes)
+            .map_err(|e| error(e.to_string()))?;
+        if bytes.len() as u64 > self.max_bytes {
+            return Err(error(format!("larger than {} bytes", self.max_bytes)));
+        }
+        String::from_utf8(bytes).map_err(|e| {
+            let at = e.utf8_error().valid_up_to();
+            error(format!("not valid UTF-8 (at byte {})", at))
+        })
+    }
+}
+
+fn parse_url(url: &str) -> Result<Url, FetchError> {
+    Url::parse(url).map_err(|e| FetchError {
+        url: url.to_string(),
+        message: e.to_string(),
+    })
+}
+
+// Resolve a possibly relative URL against the URL of the document it appears in
+fn resolve(base: &Url, url: &str) -> Result<Url, FetchError> {
+    base.join(url).map_err(|e| FetchError {
+        url: url.to_string(),
+        message: e.to_string(),
+    })
+}
+
+// The `href`s of the `<link rel="stylesheet">` elements in a document, in document order
+pub fn stylesheet_links(node: &Node) -> Vec<String> {
+    let mut links = Vec::new();
+    collect_stylesheet_links(node, &mut links);
+    links
+}
+
+fn collect_stylesheet_links(node: &Node, links: &mut Vec<String>) {
+    if let NodeType::Element(ref elem) = node.node_type {
+        let rel = elem.attribute("rel").map_or("", |rel| rel);
+        let is_stylesheet = rel
+            .split_whitespace()
+            .any(|rel| rel.eq_ignore_ascii_case("stylesheet"));
+        if let (true, true, Some(href)) = (
+            elem.tag_name == "link",
+            is_stylesheet,
+            elem.attribute("href"),
+        ) {
+            links.push(href.clone());
+        }
+    }
+    for child in &node.children {
+        collect_stylesheet_links(child, links);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        io::{BufRead, BufReader, Write},
+        net::TcpListener,
+        thread,
+    };
+
+    // Serve each of `files`, as (path, body) pairs, from a server on a free local port,
+    // until `requests` requests have been answered. Returns the server's base URL
+    fn serve(files: Vec<(&'static str, String)>, requests: usize) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        thread::spawn(move || {
+            for stream in listener.incoming().take(requests) {
+                let mut stream = stream.unwrap();
+                let mut request_line = String::new();
+                let mut reader = BufReader::new(&stream);
+                reader.read_line(&mut request_line).unwrap();
+                // Skip the headers
+                let mut line = String::new();
+                while reader.read_line(&mut line).unwrap() > 2 {
+                    line.clear();
+                }
+                let path = request_line.split_whitespace().nth(1).unwrap_or("");
+                let response = match files.iter().find(|&&(p, _)| p == path) {
+                    Some((_, body)) => format!(
+                        "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                        body.len(),
+                        body
+                    ),
+                    None => {
+                        "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+                            .to_string()
+                    }
+                };
+                stream.write_all(response.as_bytes()).unwrap();
+            }
+        });
+        base
+    }
+
+    #[test]
+    fn fetch_page_and_stylesheets() {
+        let base = serve(
+            vec![
+                (
+                    "/pages/index.html",
+                    "<html><link rel=\"stylesheet\" href=\"../css/main.css\"><p></p></html>"
+                        .to_string(),
+                ),
+                (
+                    "/css/main.css",
+                    "@import \"base.css\"; p { color: #0000ff; }".to_string(),
+                ),
+                ("/css/base.css", "p { display: block; }".to_string()),
+            ],
+            3,
+        );
+        let page = Fetcher::default()
+            .fetch_page(&format!("{}/pages/index.html", base))
+            .unwrap();
+        assert!(page.html.starts_with("<html>"));
+        // Imported stylesheets come before the ones importing them
+        assert_eq!(
+            "p { display: block; }\n@import \"base.css\"; p { color: #0000ff; }\n",
+            page.css
+        );
+        let stylesheet = css::Parser::parse(page.css);
+        let names: Vec<_> = stylesheet.declarations().map(|(_, d)| &*d.name).collect();
+        assert_eq!(vec!["display", "color"], names);
+    }
+
+    #[test]
+    fn fetch_errors() {
+        let base = serve(vec![("/big.html", "<p>".repeat(10))], 2);
+        let fetcher = Fetcher::new(TIMEOUT, 16);
+        let error = fetcher.fetch_page(&format!("{}/missing.html", base));
+        assert_eq!(
+            Err(format!("{}/missing.html: HTTP 404 Not Found", base)),
+            error.map(|_| ()).map_err(|e| e.to_string())
+        );
+        let error = fetcher.fetch_page(&format!("{}/big.html", base));
+        assert_eq!(
+            Err(format!("{}/big.html: larger than 16 bytes", base)),
+            error.map(|_| ()).map_err(|e| e.to_string())
+        );
+    }
+}
@@ -0,0 +1,233 @@
+// A C interface, for embedding the engine in applications written in other languages.
+// `include/robinson.h` declares these functions, and can be regenerated with `cbindgen`
+// (see `cbindgen.toml`).
+//
+// Strings passed in are NUL-terminated UTF-8, and the caller keeps ownership of them.
+// Buffers and strings returned are owned by the caller, who must give them back to
+// `robinson_free` or `robinson_free_string`. Functions that fail return null, and
+// `robinson_last_error` then says why. No panic crosses into the caller
+
+use std::{
+    cell::RefCell,
+    ffi::{c_char, CStr, CString},
+    panic::{self, AssertUnwindSafe},
+    ptr,
+};
+
+use crate::{pipeline, RenderOptions, Viewport};
+
+thread_local! {
+    // The message of the last call on this thread that failed
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+/// Render a page to a `width` x `height` viewport, with the default stylesheet before
+/// `css`. Returns the pixels as 8-bit RGBA values row by row, and stores how many bytes
+/// there are in `out_len`, or returns null on failure.
+///
+/// # Safety
+///
+/// `html` and `css` must be null or point to NUL-terminated strings, and `out_len` must
+/// be valid for writes: if it's null, the call fails. The buffer returned must be freed
+/// with `robinson_free`.
+#[no_mangle]
+pub unsafe extern "C" fn robinson_render(
+    html: *const c_char,
+    css: *const c_char,
+    width: u32,
+    height: u32,
+    out_len: *mut usize,
+) -> *mut u8 {
+    let result = guard(|| {
+        if out_len.is_null() {
+            return Err("out_len is null".to_string());
+        }
+        let (html, css) = (str_arg(html, "html")?, str_arg(css, "css")?);
+        let viewport = Viewport {
+            width: width as f32,
+            height: height as f32,
+        };
+        let canvas = pipeline::render(html, css, viewport).map_err(|e| e.to_string())?;
+        Ok(canvas.to_rgba().into_boxed_slice())
+    });
+    match result {
+        Some(pixels) => {
+            *out_len = pixels.len();
+            Box::into_raw(pixels) as *mut u8
+        }
+        None => ptr::null_mut(),
+    }
+}
+
+/// Free a buffer returned by `robinson_render`. Null is ignored.
+///
+/// # Safety
+///
+/// `ptr` must be null, or a buffer from `robinson_render` that hasn't been freed, with
+/// `len` the length it returned.
+#[no_mangle]
+pub unsafe extern "C" fn robinson_free(ptr: *mut u8, len: usize) {
+    if !ptr.is_null() {
+        drop(Box::from_raw(ptr::slice_from_raw_parts_mut(ptr, len)));
+    }
+}
+
+/// Lay out a page in a `width` x `height` viewport, with the default stylesheet before
+/// `css`, and return the layout snapshot as JSON: `null` if the root has
+/// `display: none`. Returns null on failure.
+///
+/// # Safety
+///
+/// `html` and `css` must be null or point to NUL-terminated strings. The string returned
+/// must be freed with `robinson_free_string`.
+#[no_mangle]
+pub unsafe extern "C" fn robinson_dump_layout(
+    html: *const c_char,
+    css: *const c_char,
+    width: u32,
+    height: u32,
+) -> *mut c_char {
+    let result = guard(|| {
+        let (html, css) = (str_arg(html, "html")?, str_arg(css, "css")?);
+        let options = RenderOptions::default();
+        let viewport = Viewport {
+            width: width as f32,
+            height: height as f32,
+        };
+        let snapshot = pipeline::stylesheet(&[css], &options)
+            .and_then(|stylesheet| {
+                pipeline::with_layout(html, &stylesheet, viewport, &options, |tree| {
+                    tree.root.to_snapshot()
+                })
+            })
+            .map_err(|e| e.to_string())?;
+        let json = serde_json::to_string(&snapshot).map_err(|e| e.to_string())?;
+        CString::new(json).map_err(|e| e.to_string())
+    });
+    result.map_or(ptr::null_mut(), CString::into_raw)
+}
+
+/// Free a string returned by `robinson_dump_layout`. Null is ignored.
+///
+/// # Safety
+///
+/// `ptr` must be null, or a string from `robinson_dump_layout` that hasn't been freed.
+#[no_mangle]
+pub unsafe extern "C" fn robinson_free_string(ptr: *mut c_char) {
+    if !ptr.is_null() {
+        drop(CString::from_raw(ptr));
+    }
+}
+
+/// The message of the last call on this thread that failed, or null if none has. The
+/// string belongs to the library, and stays valid until the next call on this thread
+/// fails.
+#[no_mangle]
+pub extern "C" fn robinson_last_error() -> *const c_char {
+    LAST_ERROR.with_borrow(|error| error.as_ref().map_or(ptr::null(), |e| e.as_ptr()))
+}
+
+// Run `f`, returning its result, or storing why it failed or panicked as the last error
+fn guard<T>(f: impl FnOnce() -> Result<T, String>) -> Option<T> {
+    let message = match panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Ok(value)) => return Some(value),
+        Ok(Err(message)) => message,
+        Err(payload) => match payload.downcast_ref::<&str>() {
+            Some(message) => format!("panic: {}", message),
+            None => match payload.downcast_ref::<String>() {
+                Some(message) => format!("panic: {}", message),
+                None => "panic".to_string(),
+            },
+        },
+    };
+    // A message can't contain NUL, so cut it off at the first one
+    let message = message.split('\0').next().unwrap_or_default();
+    let message = CString::new(message).expect("NUL was removed");
+    LAST_ERROR.set(Some(message));
+    None
+}
+
+// Borrow a string argument, which must not be null and must be UTF-8
+unsafe fn str_arg<'a>(ptr: *const c_char, name: &str) -> Result<&'a str, String> {
+    if ptr.is_null() {
+        return Err(format!("{} is null", name));
+    }
+    CStr::from_ptr(ptr)
+        .to_str()
+        .map_err(|_| format!("{} isn't valid UTF-8", name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn last_error() -> String {
+        let error = robinson_last_error();
+        assert!(!error.is_null());
+        unsafe { CStr::from_ptr(error) }
+            .to_str()
+            .unwrap()
+            .to_string()
+    }
+
+    #[test]
+    fn render_and_free() {
+        let html = CString::new("<div></div>").unwrap();
+        let css = CString::new("div { height: 1px; background: #ff0000; }").unwrap();
+        let mut len = 0;
+        unsafe {
+            let pixels = robinson_render(html.as_ptr(), css.as_ptr(), 2, 2, &mut len);
+            assert!(!pixels.is_null());
+            assert_eq!(2 * 2 * 4, len);
+            let pixels_slice = std::slice::from_raw_parts(pixels, len);
+            assert_eq!([255, 0, 0, 255], pixels_slice[4..8]);
+            assert_eq!([255, 255, 255, 255], pixels_slice[8..12]);
+            robinson_free(pixels, len);
+            robinson_free(ptr::null_mut(), 0);
+        }
+    }
+
+    #[test]
+    fn errors() {
+        let css = CString::new("").unwrap();
+        let html = CString::new("<p></div>").unwrap();
+        let mut len = 0;
+        unsafe {
+            let pixels = robinson_render(html.as_ptr(), css.as_ptr(), 2, 2, &mut len);
+            assert!(pixels.is_null());
+            assert!(last_error().starts_with("invalid HTML: expected </p>"));
+
+            let layout = robinson_dump_layout(ptr::null(), css.as_ptr(), 2, 2);
+            assert!(layout.is_null());
+            assert_eq!("html is null", last_error());
+
+            let bad_css = CString::new("div > p {}").unwrap();
+            let html = CString::new("<p></p>").unwrap();
+            let layout = robinson_dump_layout(html.as_ptr(), bad_css.as_ptr(), 2, 2);
+            assert!(layout.is_null());
+            assert!(last_error().starts_with("invalid CSS"));
+        }
+    }
+
+    #[test]
+    fn dump_layout_as_json() {
+        let html = CString::new("<div class=\"note\"></div>").unwrap();
+        let css = CString::new("div { height: 5px; }").unwrap();
+        unsafe {
+            let layout = robinson_dump_layout(html.as_ptr(), css.as_ptr(), 10, 10);
+            assert!(!layout.is_null());
+            let json: serde_json::Value =
+                serde_json::from_str(CStr::from_ptr(layout).to_str().unwrap()).unwrap();
+            assert_eq!("div", json["tag"]);
+            assert_eq!("note", json["classes"][0]);
+            robinson_free_string(layout);
+            robinson_free_string(ptr::null_mut());
+        }
+    }
+
+    #[test]
+    fn panics_are_caught() {
+        assert_eq!(None, guard::<()>(|| panic!("oops")));
+        assert_eq!("panic: oops", last_error());
+    }
+}
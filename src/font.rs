@@ -0,0 +1,115 @@
+// Font metrics used to measure text during layout. All sizes are in px.
+pub trait FontMetrics {
+    // The advance width of `text` set at `font_size`
+    fn text_width(&self, text: &str, font_size: f32) -> f32;
+
+    // The distance from the baseline to the top of the glyphs
+    fn ascent(&self, font_size: f32) -> f32;
+
+    // The distance from the baseline to the bottom of the glyphs
+    fn descent(&self, font_size: f32) -> f32;
+}
+
+// Metrics for an idealized monospace font, where every character is half an em wide.
+// This needs no font data, and makes text layout easy to predict
+#[derive(Debug, Default, Clone, Copy)]
+pub struct MonospaceMetrics;
+
+impl FontMetrics for MonospaceMetrics {
+    fn text_width(&self, text: &str, font_size: f32) -> f32 {
+        text.chars().count() as f32 * font_size * 0.5
+    }
+
+    fn ascent(&self, font_size: f32) -> f32 {
+        font_size * 0.75
+    }
+
+    fn descent(&self, font_size: f32) -> f32 {
+        font_size * 0.25
+    }
+}
+
+// The font text is painted with, embedded in the crate: DejaVu Sans Mono (see
+// fonts/LICENSE). Layout must measure text with these metrics too, so that the painted
+// glyphs fill the boxes laid out for them
+#[cfg(feature = "text")]
+pub struct EmbeddedFont {
+    font: fontdue::Font,
+}
+
+#[cfg(feature = "text")]
+impl EmbeddedFont {
+    // The shared instance, parsed on first use
+    pub fn get() -> &'static EmbeddedFont {
+        static FONT: std::sync::OnceLock<EmbeddedFont> = std::sync::OnceLock::new();
+        FONT.get_or_init(|| {
+            let data = include_bytes!("../fonts/DejaVuSansMono.ttf");
+            let font = fontdue::Font::from_bytes(&data[..], fontdue::FontSettings::default())
+                .expect("the embedded font is valid");
+            EmbeddedFont { font }
+        })
+    }
+
+    // Render one character at `font_size`, returning its metrics and its coverage bitmap
+    // (one byte per pixel, row by row)
+    pub fn rasterize(&self, c: char, font_size: f32) -> (fontdue::Metrics, Vec<u8>) {
+        self.font.rasterize(c, font_size)
+    }
+}
+
+#[cfg(feature = "text")]
+impl FontMetrics for EmbeddedFont {
+    fn text_width(&self, text: &str, font_size: f32) -> f32 {
+        text.chars()
+            .map(|c| self.font.metrics(c, font_size).advance_width)
+            .sum()
+    }
+
+    fn ascent(&self, font_size: f32) -> f32 {
+        self.font
+            .horizontal_line_metrics(font_size)
+            .map_or(font_size * 0.75, |m| m.ascent)
+    }
+
+    fn descent(&self, font_size: f32) -> f32 {
+        // fontdue measures the descent downwards from the baseline as a negative number
+        self.font
+            .horizontal_line_metrics(font_size)
+            .map_or(font_size * 0.25, |m| -m.descent)
+    }
+}
+
+// The metrics to lay out text with so that it matches the painted glyphs: the embedded
+// font's with the `text` feature, or `MonospaceMetrics` when text isn't painted
+#[cfg(feature = "text")]
+pub fn text_metrics() -> &'static dyn FontMetrics {
+    EmbeddedFont::get()
+}
+
+#[cfg(not(feature = "text"))]
+pub fn text_metrics() -> &'static dyn FontMetrics {
+    &MonospaceMetrics
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn measure_astral_plane_text() {
+        // Text is measured by scalar value: the waving hand and its skin tone modifier
+        // are two, though they're eight bytes of UTF-8
+        let text = "a👋🏽b";
+        assert_eq!(4.0 * 8.0, MonospaceMetrics.text_width(text, 16.0));
+
+        #[cfg(feature = "text")]
+        {
+            let font = EmbeddedFont::get();
+            let width = font.text_width(text, 16.0);
+            assert!(width.is_finite() && width > 0.0, "{}", width);
+            // Characters the font has no glyph for still get a box to paint
+            let (metrics, bitmap) = font.rasterize('👋', 16.0);
+            assert_eq!(metrics.width * metrics.height, bitmap.len());
+        }
+    }
+}
@@ -1,8 +1,18 @@
+use std::collections::HashSet;
+
 use crate::{
-    css::{Unit, Value},
+    css::{Color, LengthContext, Unit, Value},
+    dom::{DomNode, Node},
+    font::{FontMetrics, MonospaceMetrics},
     style::{Display, StyledNode},
 };
 
+// The size of an image whose size isn't given by its `width` and `height` attributes or
+// CSS, since images aren't decoded during layout. It also sets the aspect ratio used
+// when only one of them is given
+pub const PLACEHOLDER_WIDTH: f32 = 300.0;
+pub const PLACEHOLDER_HEIGHT: f32 = 150.0;
+
 // CSS box model. All sizes are in px.
 
 #[derive(Default, Clone, Copy)]
@@ -18,20 +28,21 @@ pub struct Dimensions {
 
 impl Dimensions {
     // The area covered by the content area plus its padding
-    fn padding_box(self) -> Rect {
+    pub fn padding_box(self) -> Rect {
         self.content.expanded_by(self.padding)
     }
     // The area covered by the content area plus padding and borders
-    fn border_box(self) -> Rect {
+    pub fn border_box(self) -> Rect {
         self.padding_box().expanded_by(self.border)
     }
-    // The ar_ea covered by the content area plus padding, borders, and margin
-    fn margin_box(self) -> Rect {
+    // The area covered by the content area plus padding, borders, and margin
+    pub fn margin_box(self) -> Rect {
         self.border_box().expanded_by(self.margin)
     }
 }
 
-#[derive(Default, Clone, Copy)]
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Rect {
     pub x: f32,
     pub y: f32,
@@ -40,7 +51,8 @@ pub struct Rect {
 }
 
 impl Rect {
-    fn expanded_by(self, edge: EdgeSizes) -> Rect {
+    // The rectangle grown outwards by the given size on each side
+    pub fn expanded_by(self, edge: EdgeSizes) -> Rect {
         Self {
             x: self.x - edge.left,
             y: self.y - edge.top,
@@ -48,6 +60,40 @@ impl Rect {
             height: self.height + edge.top + edge.bottom,
         }
     }
+
+    // The smallest rectangle containing both rectangles
+    pub fn union(self, other: Rect) -> Rect {
+        let x = self.x.min(other.x);
+        let y = self.y.min(other.y);
+        Rect {
+            x,
+            y,
+            width: (self.x + self.width).max(other.x + other.width) - x,
+            height: (self.y + self.height).max(other.y + other.height) - y,
+        }
+    }
+
+    // The area covered by both rectangles, which is empty if they don't overlap
+    pub fn intersection(self, other: Rect) -> Rect {
+        let x = self.x.max(other.x);
+        let y = self.y.max(other.y);
+        Rect {
+            x,
+            y,
+            width: ((self.x + self.width).min(other.x + other.width) - x).max(0.0),
+            height: ((self.y + self.height).min(other.y + other.height) - y).max(0.0),
+        }
+    }
+
+    // Return true if the rectangle has no area
+    pub fn is_empty(self) -> bool {
+        self.width <= 0.0 || self.height <= 0.0
+    }
+
+    // Return true if the point lies within the rectangle, including its top and left edges
+    pub fn contains(self, x: f32, y: f32) -> bool {
+        x >= self.x && x < self.x + self.width && y >= self.y && y < self.y + self.height
+    }
 }
 
 #[derive(Default, Clone, Copy)]
@@ -58,64 +104,668 @@ pub struct EdgeSizes {
     pub bottom: f32,
 }
 
-pub struct LayoutBox<'a> {
+// The result of laying out a document
+pub struct LayoutTree<'a, N = &'a Node> {
+    pub root: LayoutBox<'a, N>,
+    // The area covered by all the boxes in the document, which may extend beyond the
+    // viewport. Boxes with `position: fixed` and their descendants stay in the viewport
+    // rather than being part of the document, so they aren't included
+    pub content_size: Rect,
+}
+
+// Why a style tree couldn't be laid out
+#[derive(Debug, Clone, PartialEq)]
+pub enum LayoutError {
+    // The root has `display: none`, so there are no boxes to lay out
+    HiddenRoot,
+}
+
+impl std::fmt::Display for LayoutError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LayoutError::HiddenRoot => write!(f, "the root element has `display: none`"),
+        }
+    }
+}
+
+impl std::error::Error for LayoutError {}
+
+// Lay out a style tree within the given containing block (usually the viewport)
+pub fn layout_tree<'a, N: DomNode<'a>>(
+    node: &'a StyledNode<'a, N>,
+    containing_block: Dimensions,
+) -> Result<LayoutTree<'a, N>, LayoutError> {
+    layout_tree_with_metrics(node, containing_block, &MonospaceMetrics)
+}
+
+// Like `layout_tree`, measuring text with the given font metrics
+pub fn layout_tree_with_metrics<'a, N: DomNode<'a>>(
+    node: &'a StyledNode<'a, N>,
+    mut containing_block: Dimensions,
+    metrics: &dyn FontMetrics,
+) -> Result<LayoutTree<'a, N>, LayoutError> {
+    // The layout algorithm expects the container height to start at 0
+    containing_block.content.height = 0.0;
+
+    let mut root = build_layout_tree(node).ok_or(LayoutError::HiddenRoot)?;
+    let mut ctx = LayoutContext::new(metrics);
+    root.layout_box(containing_block, &mut ctx);
+    Ok(LayoutTree {
+        content_size: ctx.content_size.unwrap_or_default(),
+        root,
+    })
+}
+
+// Lay out again only the boxes marked with `LayoutBox::mark_dirty` and their ancestors,
+// reusing the stored results of every other box, and update the content size. Returns the
+// number of boxes laid out. The containing block and font metrics must be the same ones
+// the tree was first laid out with
+pub fn relayout_dirty<'a, N: DomNode<'a>>(
+    tree: &mut LayoutTree<'a, N>,
+    mut containing_block: Dimensions,
+    metrics: &dyn FontMetrics,
+) -> usize {
+    containing_block.content.height = 0.0;
+
+    tree.root.propagate_dirty();
+    let mut ctx = LayoutContext::new(metrics);
+    ctx.incremental = true;
+    tree.root.update_layout(containing_block, &mut ctx);
+    tree.content_size = ctx.content_size.unwrap_or_default();
+    ctx.laid_out
+}
+
+// State shared by every box during a layout pass
+struct LayoutContext<'m> {
+    metrics: &'m dyn FontMetrics,
+    // The union of the areas covered by the boxes laid out so far
+    content_size: Option<Rect>,
+    // Whether clean boxes can keep the results of the previous layout
+    incremental: bool,
+    // The number of boxes laid out so far
+    laid_out: usize,
+    // Whether the block containing the boxes being laid out has `direction: rtl`
+    rtl: bool,
+    // Whether the boxes being laid out are in a `position: fixed` box, so they don't count
+    // towards the content size
+    fixed: bool,
+}
+
+impl<'m> LayoutContext<'m> {
+    fn new(metrics: &'m dyn FontMetrics) -> Self {
+        Self {
+            metrics,
+            content_size: None,
+            incremental: false,
+            laid_out: 0,
+            rtl: false,
+            fixed: false,
+        }
+    }
+
+    // Grow the content size to cover a laid-out box. The border box is included too,
+    // since negative margins can make it stick out of the margin box
+    fn include(&mut self, d: Dimensions) {
+        self.include_area(d.margin_box().union(d.border_box()));
+    }
+
+    // Grow the content size to cover an area, unless it's in a fixed box
+    fn include_area(&mut self, area: Rect) {
+        if !self.fixed {
+            self.content_size = Some(self.content_size.map_or(area, |size| size.union(area)));
+        }
+    }
+}
+
+pub struct LayoutBox<'a, N = &'a Node> {
     pub dimensions: Dimensions,
-    pub box_type: BoxType<'a>,
-    pub children: Vec<LayoutBox<'a>>,
+    pub box_type: BoxType<'a, N>,
+    pub children: Vec<LayoutBox<'a, N>>,
+    // The lines of inline content, for anonymous blocks
+    pub line_boxes: Vec<LineBox<'a, N>>,
+    // The integer `z-index` of a positioned box; `None` for `auto` or unpositioned boxes
+    pub z_index: Option<i32>,
+    // Whether this box's descendants are painted together as a single layer
+    pub stacking_context: bool,
+    // Whether this box has a `position` other than `static`
+    pub positioned: bool,
+    // The `opacity` this box and its descendants are composited with as a group
+    pub opacity: f32,
+    // Whether this box must be laid out again by `relayout_dirty`
+    dirty: bool,
+    // The containing block this box was last laid out in
+    containing_block: Option<Dimensions>,
 }
 
-impl<'a> LayoutBox<'a> {
-    pub fn new(box_type: BoxType<'a>) -> Self {
+// One step of painting a layout, from `LayoutBox::paint_steps`
+pub enum PaintStep<'s, 'a, N = &'a Node> {
+    // Start a stacking context rooted at a box. The steps up to the matching `PopContext`
+    // paint the box and its descendants
+    PushContext(&'s LayoutBox<'a, N>),
+    PopContext,
+    // Paint a box's background and borders
+    Box(&'s LayoutBox<'a, N>),
+    // Paint the text of a box's lines
+    Text(&'s LayoutBox<'a, N>),
+}
+
+// A line of inline content within an anonymous block
+pub struct LineBox<'a, N = &'a Node> {
+    pub rect: Rect,
+    pub fragments: Vec<TextFragment<'a, N>>,
+}
+
+// A run of text on a single line, all from the same text node
+pub struct TextFragment<'a, N = &'a Node> {
+    pub text: String,
+    pub rect: Rect,
+    // The y coordinate of the baseline the text is set on
+    pub baseline: f32,
+    // The style node of the text node
+    pub style: &'a StyledNode<'a, N>,
+    // The height of the replaced element, like an image, this fragment holds instead of
+    // text. Its bottom edge sits on the baseline
+    pub replaced: Option<f32>,
+    // Child indices leading from the anonymous block to the text node's box
+    path: Vec<usize>,
+}
+
+// A word of inline content waiting to be placed on a line
+struct InlineItem<'a, N> {
+    text: String,
+    style: &'a StyledNode<'a, N>,
+    path: Vec<usize>,
+    // Whether collapsible whitespace precedes this word
+    space_before: bool,
+    // Whether this item must start a new line, after a preserved newline
+    break_before: bool,
+    // Whether the line may be broken before this item if it doesn't fit
+    wrap: bool,
+    // The size of a replaced element taking the place of text
+    replaced: Option<(f32, f32)>,
+}
+
+impl<N> InlineItem<'_, N> {
+    // The width this item takes on a line, not counting any space before it
+    fn width(&self, metrics: &dyn FontMetrics) -> f32 {
+        match self.replaced {
+            Some((width, _)) => width,
+            None => metrics.text_width(&self.text, self.style.length_context.font_size),
+        }
+    }
+
+    // Replace each tab in preserved text with spaces up to the next tab stop, where the
+    // item starts `x` px into its line. Tab stops are every `tab-size` spaces from the
+    // start of the line
+    fn expand_tabs(&mut self, x: f32, metrics: &dyn FontMetrics) {
+        if !self.text.contains('\t') {
+            return;
+        }
+        let space = metrics.text_width(" ", self.style.length_context.font_size);
+        let tab_size = tab_size(self.style);
+        let mut column = (x / space).round() as usize;
+        let mut text = String::new();
+        for c in self.text.chars() {
+            if c == '\t' {
+                let spaces = tab_size - column % tab_size;
+                text.extend(std::iter::repeat_n(' ', spaces));
+                column += spaces;
+            } else {
+                text.push(c);
+                column += 1;
+            }
+        }
+        self.text = text;
+    }
+}
+
+impl<'a, N: DomNode<'a>> LayoutBox<'a, N> {
+    pub fn new(box_type: BoxType<'a, N>) -> Self {
         Self {
             box_type,
             dimensions: Default::default(), // initially set all fields to 0.0
             children: Vec::new(),
+            line_boxes: Vec::new(),
+            z_index: None,
+            stacking_context: false,
+            positioned: false,
+            opacity: 1.0,
+            dirty: false,
+            containing_block: None,
+        }
+    }
+
+    // Flag this box for `relayout_dirty`, e.g. after replacing its style node
+    pub fn mark_dirty(&mut self) {
+        self.dirty = true;
+    }
+
+    // Mark the ancestors of dirty boxes dirty too, since their size and the position of
+    // their later children may depend on them. Returns whether this box is dirty
+    fn propagate_dirty(&mut self) -> bool {
+        for child in &mut self.children {
+            self.dirty |= child.propagate_dirty();
+        }
+        self.dirty
+    }
+
+    // Lay out a box, unless incremental layout can reuse its previous layout. A clean box
+    // whose containing block only moved vertically is just shifted into place
+    fn update_layout(&mut self, containing_block: Dimensions, ctx: &mut LayoutContext) {
+        let (old, new) = match self.containing_block {
+            Some(old) if ctx.incremental && !self.dirty => (old.content, containing_block.content),
+            _ => return self.layout_box(containing_block, ctx),
+        };
+        if old.x != new.x || old.width != new.width {
+            return self.layout_box(containing_block, ctx);
+        }
+
+        // Block layout places boxes below the content already in their containing block
+        let dy = (new.y + new.height) - (old.y + old.height);
+        if dy != 0.0 {
+            self.translate(dy);
         }
+        self.containing_block = Some(containing_block);
+        self.include_previous(ctx);
+    }
+
+    // Grow the content size to cover a box kept from the previous layout, along with its
+    // descendants and their text, the way laying them out would have
+    fn include_previous(&self, ctx: &mut LayoutContext) {
+        let outer_fixed = ctx.fixed;
+        ctx.fixed |= self.get_style_node().is_some_and(is_fixed);
+        for fragment in self.line_boxes.iter().flat_map(|line| &line.fragments) {
+            ctx.include_area(fragment.rect);
+        }
+        for child in &self.children {
+            child.include_previous(ctx);
+        }
+        if !matches!(self.box_type, BoxType::InlineNode(_)) {
+            ctx.include(self.dimensions);
+        }
+        ctx.fixed = outer_fixed;
+    }
+
+    // Move this box and everything inside it down by `dy`
+    fn translate(&mut self, dy: f32) {
+        self.dimensions.content.y += dy;
+        if let Some(containing_block) = &mut self.containing_block {
+            containing_block.content.y += dy;
+        }
+        for line in &mut self.line_boxes {
+            line.rect.y += dy;
+            for fragment in &mut line.fragments {
+                fragment.rect.y += dy;
+                fragment.baseline += dy;
+            }
+        }
+        for child in &mut self.children {
+            child.translate(dy);
+        }
+    }
+
+    // All the boxes in this subtree in the order they should be painted, treating this box
+    // as the root stacking context
+    pub fn paint_order(&self) -> impl Iterator<Item = &LayoutBox<'a, N>> {
+        self.paint_layers().map(|(layout_box, _)| layout_box)
     }
 
-    // Lay out a box and its descendants
+    // Like `paint_order`, along with the area each box is clipped to by the padding boxes
+    // of its `overflow: hidden` ancestors, if any
+    pub fn paint_layers(&self) -> impl Iterator<Item = (&LayoutBox<'a, N>, Option<Rect>)> {
+        self.paint_steps().filter_map(|(step, clip)| match step {
+            PaintStep::Box(layout_box) => Some((layout_box, clip)),
+            _ => None,
+        })
+    }
+
+    // Every step of painting this subtree in order, treating this box as the root stacking
+    // context, with the area each step is clipped to
+    pub fn paint_steps(&self) -> impl Iterator<Item = (PaintStep<'_, 'a, N>, Option<Rect>)> {
+        let mut steps = Vec::new();
+        self.stack(None, &mut steps);
+        steps.into_iter()
+    }
+
+    // Paint a stacking context: its root, then child contexts with negative z-index, then
+    // the backgrounds of the in-flow boxes, then their text, then child contexts with zero
+    // or positive z-index. Positioned boxes with `z-index: auto` are painted like contexts
+    // with z-index 0
+    fn stack<'s>(
+        &'s self,
+        clip: Option<Rect>,
+        steps: &mut Vec<(PaintStep<'s, 'a, N>, Option<Rect>)>,
+    ) {
+        steps.push((PaintStep::PushContext(self), clip));
+        steps.push((PaintStep::Box(self), clip));
+
+        let mut flow = Vec::new();
+        let mut contexts = Vec::new();
+        let children_clip = self.children_clip(clip);
+        for child in &self.children {
+            child.collect_layers(children_clip, &mut flow, &mut contexts);
+        }
+        // The sort is stable, so boxes with equal z-index stay in tree order
+        contexts.sort_by_key(|(context, _)| context.z_index.unwrap_or(0));
+
+        let (negative, positive): (Vec<_>, Vec<_>) = contexts
+            .into_iter()
+            .partition(|(context, _)| context.z_index.unwrap_or(0) < 0);
+        for (context, clip) in negative {
+            context.stack(clip, steps);
+        }
+        for &(layout_box, clip) in &flow {
+            steps.push((PaintStep::Box(layout_box), clip));
+        }
+        // Text goes over the backgrounds of every box in flow, not just its own
+        let with_lines = std::iter::once((self, clip))
+            .chain(flow)
+            .filter(|(layout_box, _)| !layout_box.line_boxes.is_empty());
+        for (layout_box, clip) in with_lines {
+            steps.push((PaintStep::Text(layout_box), clip));
+        }
+        for (context, clip) in positive {
+            context.stack(clip, steps);
+        }
+        steps.push((PaintStep::PopContext, clip));
+    }
+
+    // Split a subtree into boxes painted in flow, and nested stacking contexts
+    fn collect_layers<'s>(
+        &'s self,
+        clip: Option<Rect>,
+        flow: &mut Vec<(&'s LayoutBox<'a, N>, Option<Rect>)>,
+        contexts: &mut Vec<(&'s LayoutBox<'a, N>, Option<Rect>)>,
+    ) {
+        if self.stacking_context || self.positioned {
+            contexts.push((self, clip));
+            return;
+        }
+        flow.push((self, clip));
+        let children_clip = self.children_clip(clip);
+        for child in &self.children {
+            child.collect_layers(children_clip, flow, contexts);
+        }
+    }
+
+    // The clip applied to this box's descendants
+    fn children_clip(&self, clip: Option<Rect>) -> Option<Rect> {
+        if !self.clips_overflow() {
+            return clip;
+        }
+        let padding_box = self.dimensions.padding_box();
+        Some(clip.map_or(padding_box, |clip| clip.intersection(padding_box)))
+    }
+
+    // Lay out a box and its descendants, measuring text with `MonospaceMetrics`
     pub fn layout(&mut self, containing_block: Dimensions) {
+        self.layout_with_metrics(containing_block, &MonospaceMetrics)
+    }
+
+    // Lay out a box and its descendants, measuring text with the given font metrics
+    pub fn layout_with_metrics(&mut self, containing_block: Dimensions, metrics: &dyn FontMetrics) {
+        self.layout_box(containing_block, &mut LayoutContext::new(metrics));
+    }
+
+    fn layout_box(&mut self, containing_block: Dimensions, ctx: &mut LayoutContext) {
+        let fixed = self.get_style_node().is_some_and(is_fixed);
+        let inside_fixed = ctx.fixed || fixed;
+        let outer_fixed = std::mem::replace(&mut ctx.fixed, inside_fixed);
         match self.box_type {
-            BoxType::BlockNode(_) => self.layout_block(containing_block),
-            BoxType::InlineNode(_) => {}  // TODO
-            BoxType::AnonymousBlock => {} // TODO
+            BoxType::BlockNode(_) => self.layout_block(containing_block, ctx),
+            BoxType::FlexContainer(_) => self.layout_flex(containing_block, ctx),
+            BoxType::AnonymousBlock(_) => self.layout_anonymous(containing_block, ctx),
+            BoxType::Marker(_) => self.layout_marker(containing_block, ctx),
+            // Inline boxes are positioned by the line layout of their anonymous block
+            BoxType::InlineNode(_) => {
+                ctx.fixed = outer_fixed;
+                return;
+            }
         }
+        self.containing_block = Some(containing_block);
+        self.dirty = false;
+        trace!(
+            "{}: x={} y={} width={} height={}",
+            self.box_type,
+            fmt_px(self.dimensions.content.x),
+            fmt_px(self.dimensions.content.y),
+            fmt_px(self.dimensions.content.width),
+            fmt_px(self.dimensions.content.height)
+        );
+        ctx.laid_out += 1;
+        ctx.include(self.dimensions);
+        ctx.fixed = outer_fixed;
     }
 
-    fn layout_block(&mut self, containing_block: Dimensions) {
+    fn layout_block(&mut self, containing_block: Dimensions, ctx: &mut LayoutContext) {
         // Child width can depend on parent width, so we need to calculate
         // this box's width before laying out its children
-        self.calculate_block_width(containing_block);
+        self.calculate_block_width(containing_block, ctx);
 
         // Determine where the box is located within its container
         self.calculate_block_position(containing_block);
 
-        // Recursively lay out the children of this box
-        self.layout_block_children();
+        // Recursively lay out the children of this box, in its direction
+        let rtl = std::mem::replace(&mut ctx.rtl, is_rtl(self.style_node()));
+        self.layout_block_children(ctx);
+        ctx.rtl = rtl;
 
         // Parent height can depend on child height, so `calculate_block_height`
         // must be called *after* the children are laid out
         self.calculate_block_height();
     }
 
-    fn calculate_block_width(&mut self, containing_block: Dimensions) {
-        let style = self.get_style_node();
+    fn layout_flex(&mut self, containing_block: Dimensions, ctx: &mut LayoutContext) {
+        // A flex container is sized and positioned within its parent like a block box
+        self.calculate_block_width(containing_block, ctx);
+        self.calculate_block_position(containing_block);
+
+        // Its children are laid out as flex items in a single row
+        self.layout_flex_items(ctx);
+
+        self.calculate_block_height();
+    }
+
+    // Lay out the children of a flex container along the main (horizontal) axis
+    fn layout_flex_items(&mut self, ctx: &mut LayoutContext) {
+        let style = self.style_node();
+        let container = self.dimensions;
+
+        // margin, border, and padding have initial value 0. Auto margins are treated as 0
+        let zero = Value::Length(0.0, Unit::Px);
+
+        // Determine each item's hypothetical main size: its specified width, or the width
+        // of its content laid out without any line breaks if auto
+        let mut bases = Vec::new();
+        let mut grow = Vec::new();
+        let mut shrink = Vec::new();
+        let mut edges = 0.0;
+        for child in &mut self.children {
+            let base = child.intrinsic_widths(ctx.metrics).1 - child.horizontal_edges();
+            bases.push(base);
+
+            // An anonymous item around text has no edges, and the initial flex factors
+            let Some(s) = child.get_style_node() else {
+                grow.push(0.0);
+                shrink.push(1.0);
+                continue;
+            };
+            let d = &mut child.dimensions;
+
+            d.margin.left = s
+                .lookup_length("margin-left", "margin", &zero)
+                .resolve_percentage(container.content.width)
+                .to_px();
+            d.margin.right = s
+                .lookup_length("margin-right", "margin", &zero)
+                .resolve_percentage(container.content.width)
+                .to_px();
+            d.border.left = border_width(s, "left");
+            d.border.right = border_width(s, "right");
+            d.padding.left = s
+                .lookup_length("padding-left", "padding", &zero)
+                .resolve_percentage(container.content.width)
+                .to_px();
+            d.padding.right = s
+                .lookup_length("padding-right", "padding", &zero)
+                .resolve_percentage(container.content.width)
+                .to_px();
+
+            edges += d.margin.left
+                + d.margin.right
+                + d.border.left
+                + d.border.right
+                + d.padding.left
+                + d.padding.right;
+
+            // `flex-grow` has initial value 0, `flex-shrink` has initial value 1
+            grow.push(
+                s.value("flex-grow")
+                    .and_then(|v| v.to_number())
+                    .unwrap_or(0.0),
+            );
+            shrink.push(
+                s.value("flex-shrink")
+                    .and_then(|v| v.to_number())
+                    .unwrap_or(1.0),
+            );
+        }
+
+        // Distribute the free space according to `flex-grow`, or take away the overflow
+        // in proportion to `flex-shrink` weighted by each item's base size
+        let free = container.content.width - edges - bases.iter().sum::<f32>();
+        let total_grow: f32 = grow.iter().sum();
+        let total_shrink: f32 = shrink.iter().zip(&bases).map(|(s, b)| s * b).sum();
+        let mut sizes = bases.clone();
+        if free > 0.0 && total_grow > 0.0 {
+            for (size, g) in sizes.iter_mut().zip(&grow) {
+                *size += free * g / total_grow;
+            }
+        } else if free < 0.0 && total_shrink > 0.0 {
+            for ((size, s), b) in sizes.iter_mut().zip(&shrink).zip(&bases) {
+                *size = (*size + free * s * b / total_shrink).max(0.0);
+            }
+        }
+
+        // Whatever space is left over is distributed by `justify-content`
+        let leftover = container.content.width - edges - sizes.iter().sum::<f32>();
+        let count = self.children.len();
+        let (mut cursor, gap) = match style.value("justify-content") {
+            Some(Value::Keyword(s)) if s == "center" => (leftover / 2.0, 0.0),
+            Some(Value::Keyword(s)) if s == "space-between" && count > 1 && leftover > 0.0 => {
+                (0.0, leftover / (count - 1) as f32)
+            }
+            _ => (0.0, 0.0), // flex-start
+        };
+
+        // Place each item and lay out its contents
+        let mut cross_size: f32 = 0.0;
+        for (child, size) in self.children.iter_mut().zip(sizes) {
+            child.dimensions.content.width = size;
+
+            let mut line = container;
+            line.content.x += cursor;
+            line.content.height = 0.0;
+            match child.box_type {
+                BoxType::AnonymousBlock(_) => {
+                    line.content.width = size;
+                    child.layout_anonymous(line, ctx);
+                }
+                BoxType::FlexContainer(_) => {
+                    child.calculate_block_position(line);
+                    child.layout_flex_items(ctx);
+                    child.calculate_block_height();
+                }
+                _ => {
+                    child.calculate_block_position(line);
+                    child.layout_block_children(ctx);
+                    child.calculate_block_height();
+                }
+            }
+
+            let margin_box = child.dimensions.margin_box();
+            cursor += margin_box.width + gap;
+            cross_size = cross_size.max(margin_box.height);
+        }
+
+        // Stretch items with an auto height to the tallest item
+        for child in &mut self.children {
+            let height = child.get_style_node().and_then(|s| s.length("height"));
+            if let Some(Value::Length(_, Unit::Px)) = height {
+                ctx.include(child.dimensions);
+                continue;
+            }
+            let d = &mut child.dimensions;
+            d.content.height += cross_size - d.margin_box().height;
+            ctx.include(*d);
+        }
+
+        self.dimensions.content.height = cross_size;
+    }
+
+    // The used margins of an overconstrained box are adjusted at the end of the line, which
+    // is on the right unless the containing block is right-to-left. Floats are placed
+    // against their own side instead, though content doesn't flow around them yet
+    fn calculate_block_width(&mut self, containing_block: Dimensions, ctx: &LayoutContext) {
+        let style = self.style_node();
+        let rtl = match style.value("float") {
+            Some(Value::Keyword(ref side)) if side == "left" || side == "right" => side == "right",
+            _ => ctx.rtl,
+        };
+
+        // Percentages are resolved against the width of the containing block before
+        // solving for auto values
+        let cb_width = containing_block.content.width;
 
         // `width` has initial value `auto`
         let auto = Value::Keyword("auto".to_string());
-        let mut width = style.value("width").unwrap_or_else(|| auto.clone());
+        let mut width = match style.length("width") {
+            Some(width) => width.resolve_percentage(cb_width),
+            None => auto.clone(),
+        };
+
+        // A replaced element's content has its own size
+        if let (true, Some((w, _))) = (width == auto, replaced_size(style)) {
+            width = Value::Length(w, Unit::Px);
+        }
 
         // margin, border, and padding have initial value 0.
         let zero = Value::Length(0.0, Unit::Px);
+        let edge = |name: &str, fallback_name: &str| {
+            style
+                .lookup_length(name, fallback_name, &zero)
+                .resolve_percentage(cb_width)
+        };
 
-        let mut margin_left = style.lookup("margin-left", "margin", &zero);
-        let mut margin_right = style.lookup("margin-right", "margin", &zero);
+        let mut margin_left = edge("margin-left", "margin");
+        let mut margin_right = edge("margin-right", "margin");
 
-        let border_left = style.lookup("border-left-width", "border", &zero);
-        let border_right = style.lookup("border-right-width", "border", &zero);
+        let border_left = Value::Length(border_width(style, "left"), Unit::Px);
+        let border_right = Value::Length(border_width(style, "right"), Unit::Px);
 
-        let padding_left = style.lookup("padding-left", "padding", &zero);
-        let padding_right = style.lookup("padding-right", "padding", &zero);
+        let padding_left = edge("padding-left", "padding");
+        let padding_right = edge("padding-right", "padding");
+
+        // Floats and absolutely positioned boxes shrink to fit their content, filling the
+        // available width only if their content is wider
+        if width == auto && shrinks_to_fit(style) {
+            let available = cb_width
+                - [
+                    &margin_left,
+                    &margin_right,
+                    &border_left,
+                    &border_right,
+                    &padding_left,
+                    &padding_right,
+                ]
+                .iter()
+                .map(|v| v.to_px())
+                .sum::<f32>();
+            let (min, max) = self.intrinsic_widths(ctx.metrics);
+            let edges = self.horizontal_edges();
+            let fit = available.max(min - edges).min(max - edges);
+            width = Value::Length(fit.max(0.0), Unit::Px);
+        }
 
         let total: f32 = [
             &margin_left,
@@ -143,7 +793,10 @@ impl<'a> LayoutBox<'a> {
         // Calculate: auto => px
         let underflow = containing_block.content.width - total;
         match (width == auto, margin_left == auto, margin_right == auto) {
-            // If the values are overconstrained, calculate margin_right
+            // If the values are overconstrained, calculate the margin at the end of the line
+            (false, false, false) if rtl => {
+                margin_left = Value::Length(margin_left.to_px() + underflow, Unit::Px);
+            }
             (false, false, false) => {
                 margin_right = Value::Length(margin_right.to_px() + underflow, Unit::Px);
             }
@@ -168,8 +821,11 @@ impl<'a> LayoutBox<'a> {
                 if underflow >= 0.0 {
                     // Expand with to fill the underflow
                     width = Value::Length(underflow, Unit::Px);
+                } else if rtl {
+                    // Width can't be negative. Adjust the end margin instead
+                    width = Value::Length(0.0, Unit::Px);
+                    margin_left = Value::Length(margin_left.to_px() + underflow, Unit::Px);
                 } else {
-                    // Width can't be negative. Adjust the right margin instead
                     width = Value::Length(0.0, Unit::Px);
                     margin_right = Value::Length(margin_right.to_px() + underflow, Unit::Px);
                 }
@@ -196,21 +852,29 @@ impl<'a> LayoutBox<'a> {
     }
 
     fn calculate_block_position(&mut self, containing_block: Dimensions) {
-        let style = self.get_style_node();
+        let style = self.style_node();
         let d = &mut self.dimensions;
 
         // margin, border, and padding have initial value 0
         let zero = Value::Length(0.0, Unit::Px);
 
+        // Vertical percentages also refer to the width of the containing block
+        let edge = |name: &str, fallback_name: &str| {
+            style
+                .lookup_length(name, fallback_name, &zero)
+                .resolve_percentage(containing_block.content.width)
+                .to_px()
+        };
+
         // If margin-top or margin-bottom is `auto`, the used value is zero
-        d.margin.top = style.lookup("margin-top", "margin", &zero).to_px();
-        d.margin.bottom = style.lookup("margin-bottom", "margin", &zero).to_px();
+        d.margin.top = edge("margin-top", "margin");
+        d.margin.bottom = edge("margin-bottom", "margin");
 
-        d.border.top = style.lookup("border-top-width", "border", &zero).to_px();
-        d.border.bottom = style.lookup("border-bottom-width", "border", &zero).to_px();
+        d.border.top = border_width(style, "top");
+        d.border.bottom = border_width(style, "bottom");
 
-        d.padding.top = style.lookup("padding-top", "padding", &zero).to_px();
-        d.padding.bottom = style.lookup("padding-bottom", "padding", &zero).to_px();
+        d.padding.top = edge("padding-top", "padding");
+        d.padding.bottom = edge("padding-bottom", "padding");
 
         d.content.x = containing_block.content.x + d.margin.left + d.border.left + d.padding.left;
 
@@ -222,77 +886,1886 @@ impl<'a> LayoutBox<'a> {
             + d.padding.top;
     }
 
-    fn layout_block_children(&mut self) {
+    fn layout_block_children(&mut self, ctx: &mut LayoutContext) {
         let d = &mut self.dimensions;
+        d.content.height = 0.0;
         for child in &mut self.children {
-            child.layout(*d);
+            child.update_layout(*d, ctx);
+            // Markers sit outside the flow
+            if let BoxType::Marker(_) = child.box_type {
+                continue;
+            }
             // Track the height so each child is laid out below the previous content
             d.content.height += child.dimensions.margin_box().height;
         }
     }
 
+    fn layout_anonymous(&mut self, containing_block: Dimensions, ctx: &mut LayoutContext) {
+        // An anonymous block has no edges, and fills its container below the previous content
+        let d = &mut self.dimensions;
+        d.content.x = containing_block.content.x;
+        d.content.y = containing_block.content.y + containing_block.content.height;
+        d.content.width = containing_block.content.width;
+
+        self.layout_lines(ctx);
+        self.dimensions.content.height = self.line_boxes.iter().map(|line| line.rect.height).sum();
+    }
+
+    fn layout_marker(&mut self, containing_block: Dimensions, ctx: &mut LayoutContext) {
+        // The marker ends half an em to the left of the list item's first line
+        let width = self.inline_intrinsic_widths(ctx.metrics).1;
+        let gap = self.length_context().font_size * 0.5;
+        let d = &mut self.dimensions;
+        d.content.width = width;
+        d.content.x = containing_block.content.x - gap - width;
+        d.content.y = containing_block.content.y;
+
+        self.layout_lines(ctx);
+        self.dimensions.content.height = self.line_boxes.iter().map(|line| line.rect.height).sum();
+    }
+
+    // Break the inline content of this box into line boxes, then size and position
+    // the inline boxes to match
+    fn layout_lines(&mut self, ctx: &mut LayoutContext) {
+        let mut items = Vec::new();
+        let mut pending_space = false;
+        for (i, child) in self.children.iter().enumerate() {
+            child.collect_inline_items(vec![i], &mut pending_space, &mut items);
+        }
+
+        let available_width = self.dimensions.content.width;
+        let mut lines = Vec::new();
+        let mut fragments: Vec<TextFragment<'a, N>> = Vec::new();
+        let mut x = 0.0;
+        for mut item in items {
+            let font_size = item.style.length_context.font_size;
+            item.expand_tabs(if item.break_before { 0.0 } else { x }, ctx.metrics);
+            let width = item.width(ctx.metrics);
+            let mut space = if item.space_before && !fragments.is_empty() {
+                ctx.metrics.text_width(" ", font_size)
+            } else {
+                0.0
+            };
+
+            // Break the line before a word that doesn't fit, unless it's the first on its line
+            let overflows = !fragments.is_empty() && x + space + width > available_width;
+            if item.break_before || (item.wrap && overflows) {
+                lines.push(std::mem::take(&mut fragments));
+                x = 0.0;
+                space = 0.0;
+            }
+
+            // Consecutive words from the same text node share a fragment
+            match fragments.last_mut() {
+                Some(last) if last.path == item.path => {
+                    if space > 0.0 {
+                        last.text.push(' ');
+                    }
+                    last.text.push_str(&item.text);
+                    last.rect.width += space + width;
+                }
+                _ => fragments.push(TextFragment {
+                    text: item.text,
+                    rect: Rect {
+                        x: x + space,
+                        y: 0.0,
+                        width,
+                        height: 0.0,
+                    },
+                    baseline: 0.0,
+                    style: item.style,
+                    replaced: item.replaced.map(|(_, height)| height),
+                    path: item.path,
+                }),
+            }
+            x += space + width;
+        }
+        if !fragments.is_empty() {
+            lines.push(fragments);
+        }
+
+        let d = self.dimensions;
+        let mut y = d.content.y;
+        self.line_boxes = lines
+            .into_iter()
+            .map(|fragments| {
+                let line = self.place_line(fragments, d.content.x, y, ctx.metrics);
+                y += line.rect.height;
+                line
+            })
+            .collect();
+
+        // Each inline box covers the fragments generated by its descendants
+        let placed: Vec<(Vec<usize>, Rect)> = self
+            .line_boxes
+            .iter()
+            .flat_map(|line| line.fragments.iter())
+            .map(|fragment| (fragment.path.clone(), fragment.rect))
+            .collect();
+        let mut sized = HashSet::new();
+        for (path, rect) in placed {
+            // Text overflowing its line still counts towards the content size
+            ctx.include_area(rect);
+            for depth in 1..=path.len() {
+                let inline_box = self.descendant_mut(&path[..depth]);
+                inline_box.dimensions.content = if sized.insert(path[..depth].to_vec()) {
+                    rect
+                } else {
+                    inline_box.dimensions.content.union(rect)
+                };
+            }
+        }
+    }
+
+    // Align the fragments of one line along the baseline, and horizontally by `text-align`
+    fn place_line(
+        &self,
+        mut fragments: Vec<TextFragment<'a, N>>,
+        x: f32,
+        y: f32,
+        metrics: &dyn FontMetrics,
+    ) -> LineBox<'a, N> {
+        let font_size = |f: &TextFragment<'a, N>| f.style.length_context.font_size;
+        let text = || fragments.iter().filter(|f| f.replaced.is_none());
+        let ascent = text()
+            .map(|f| metrics.ascent(font_size(f)))
+            .fold(0.0, f32::max);
+        let descent = text()
+            .map(|f| metrics.descent(font_size(f)))
+            .fold(0.0, f32::max);
+
+        // The line is as tall as the tallest `line-height` of its text, with `normal`
+        // fitting the glyphs exactly. The leading is split evenly above and below the glyphs
+        let mut height = text()
+            .map(|f| {
+                let size = font_size(f);
+                line_height(f.style).unwrap_or(metrics.ascent(size) + metrics.descent(size))
+            })
+            .fold(0.0, f32::max);
+
+        // Replaced elements sit on the baseline, and the line grows upward to fit them
+        let above = (height - ascent - descent) / 2.0 + ascent;
+        let top = fragments
+            .iter()
+            .filter_map(|f| f.replaced)
+            .fold(above, f32::max);
+        height += top - above;
+        let baseline = y + top;
+
+        let width = fragments
+            .last()
+            .map_or(0.0, |last| last.rect.x + last.rect.width);
+        let free = (self.dimensions.content.width - width).max(0.0);
+        let rtl = is_rtl(self.inline_style());
+        let offset = match self.text_align().as_deref() {
+            Some("center") => free / 2.0,
+            Some("right") => free,
+            Some("left") => 0.0,
+            Some("end") if !rtl => free,
+            None | Some("start") if rtl => free,
+            _ => 0.0, // start of a left-to-right line
+        };
+
+        for fragment in &mut fragments {
+            // Right-to-left lines run from the right edge of the content leftward
+            if rtl {
+                fragment.rect.x = width - fragment.rect.x - fragment.rect.width;
+            }
+            fragment.rect.x += x + offset;
+            fragment.baseline = baseline;
+            if let Some(height) = fragment.replaced {
+                fragment.rect.y = baseline - height;
+                fragment.rect.height = height;
+                continue;
+            }
+            let size = font_size(fragment);
+            fragment.rect.y = baseline - metrics.ascent(size);
+            fragment.rect.height = metrics.ascent(size) + metrics.descent(size);
+        }
+
+        LineBox {
+            rect: Rect {
+                x,
+                y,
+                width: self.dimensions.content.width,
+                height,
+            },
+            fragments,
+        }
+    }
+
+    // The style of the block containing this box's inline content
+    fn inline_style(&self) -> &'a StyledNode<'a, N> {
+        match self.box_type {
+            BoxType::AnonymousBlock(node) => node,
+            _ => self.style_node(),
+        }
+    }
+
+    // The computed `text-align` of the block containing this box's inline content
+    fn text_align(&self) -> Option<String> {
+        match self.inline_style().value("text-align") {
+            Some(Value::Keyword(s)) => Some(s),
+            _ => None,
+        }
+    }
+
+    // Flatten the text under this inline box into words, in document order
+    fn collect_inline_items(
+        &self,
+        path: Vec<usize>,
+        pending_space: &mut bool,
+        items: &mut Vec<InlineItem<'a, N>>,
+    ) {
+        let style = match self.box_type {
+            BoxType::InlineNode(node) => node,
+            _ => return, // block-level boxes inside inline boxes aren't supported
+        };
+        match style.text() {
+            // Preserved text is only broken at newlines, keeping all other whitespace
+            Some(text) if white_space(style) == "pre" => {
+                for (i, segment) in text.split('\n').enumerate() {
+                    items.push(InlineItem {
+                        text: segment.to_string(),
+                        style,
+                        path: path.clone(),
+                        space_before: false,
+                        break_before: i > 0,
+                        wrap: false,
+                        replaced: None,
+                    });
+                }
+                *pending_space = false;
+            }
+            // Otherwise, whitespace collapses to single spaces between words
+            Some(text) => {
+                let wrap = white_space(style) != "nowrap";
+                let mut words = text.split_whitespace().peekable();
+                *pending_space |= text.starts_with(char::is_whitespace);
+                while let Some(word) = words.next() {
+                    items.push(InlineItem {
+                        text: word.to_string(),
+                        style,
+                        path: path.clone(),
+                        space_before: *pending_space,
+                        break_before: false,
+                        wrap,
+                        replaced: None,
+                    });
+                    *pending_space = words.peek().is_some() || text.ends_with(char::is_whitespace);
+                }
+            }
+            // A replaced element is placed on the line like a single word
+            None if replaced_size(style).is_some() => {
+                items.push(InlineItem {
+                    text: String::new(),
+                    style,
+                    path,
+                    space_before: *pending_space,
+                    break_before: false,
+                    wrap: white_space(style) == "normal",
+                    replaced: replaced_size(style),
+                });
+                *pending_space = false;
+            }
+            None => {
+                for (i, child) in self.children.iter().enumerate() {
+                    let mut child_path = path.clone();
+                    child_path.push(i);
+                    child.collect_inline_items(child_path, pending_space, items);
+                }
+            }
+        }
+    }
+
+    // The (min-content, max-content) widths of this box's margin box: the narrowest it can
+    // be without its content overflowing, and the width its content takes with no wrapping
+    pub fn intrinsic_widths(&self, metrics: &dyn FontMetrics) -> (f32, f32) {
+        let style = match self.box_type {
+            BoxType::AnonymousBlock(_) => return self.inline_intrinsic_widths(metrics),
+            // Markers are outside their list item
+            BoxType::Marker(_) => return (0.0, 0.0),
+            BoxType::BlockNode(style)
+            | BoxType::FlexContainer(style)
+            | BoxType::InlineNode(style) => style,
+        };
+        let edges = self.horizontal_edges();
+        if let Some(Value::Length(width, Unit::Px)) = style.length("width") {
+            return (width + edges, width + edges);
+        }
+        if let Some((width, _)) = replaced_size(style) {
+            return (width + edges, width + edges);
+        }
+
+        let children = self
+            .children
+            .iter()
+            .map(|child| child.intrinsic_widths(metrics));
+        let (min, max) = match self.box_type {
+            // Flex items sit side by side on a single line
+            BoxType::FlexContainer(_) => {
+                children.fold((0.0, 0.0), |(min, max), (a, b)| (min + a, max + b))
+            }
+            // Block children are stacked
+            _ => children.fold((0.0, 0.0), |(min, max): (f32, f32), (a, b)| {
+                (min.max(a), max.max(b))
+            }),
+        };
+        (min + edges, max + edges)
+    }
+
+    // Intrinsic widths of inline content: the widest run of text that can't be broken,
+    // and the widest line when only forced line breaks are taken
+    fn inline_intrinsic_widths(&self, metrics: &dyn FontMetrics) -> (f32, f32) {
+        let mut items = Vec::new();
+        let mut pending_space = false;
+        for (i, child) in self.children.iter().enumerate() {
+            child.collect_inline_items(vec![i], &mut pending_space, &mut items);
+        }
+
+        let (mut min, mut max): (f32, f32) = (0.0, 0.0);
+        let (mut unit, mut line): (f32, f32) = (0.0, 0.0);
+        for (i, item) in items.iter_mut().enumerate() {
+            let font_size = item.style.length_context.font_size;
+            item.expand_tabs(if item.break_before { 0.0 } else { line }, metrics);
+            let space = if item.space_before && i > 0 && !item.break_before {
+                metrics.text_width(" ", font_size)
+            } else {
+                0.0
+            };
+            if item.break_before {
+                max = max.max(line);
+                line = 0.0;
+            }
+            if item.break_before || (item.space_before && item.wrap) {
+                min = min.max(unit);
+                unit = 0.0;
+            } else {
+                unit += space;
+            }
+            let width = item.width(metrics);
+            unit += width;
+            line += space + width;
+        }
+        (min.max(unit), max.max(line))
+    }
+
+    // The total width of the horizontal margins, borders, and padding of this box.
+    // Percentages count as 0, since the containing block width isn't known yet
+    fn horizontal_edges(&self) -> f32 {
+        let style = match self.box_type {
+            BoxType::AnonymousBlock(_) | BoxType::Marker(_) => return 0.0,
+            BoxType::BlockNode(style)
+            | BoxType::FlexContainer(style)
+            | BoxType::InlineNode(style) => style,
+        };
+        let zero = Value::Length(0.0, Unit::Px);
+        ["left", "right"]
+            .iter()
+            .map(|side| {
+                style
+                    .lookup_length(&format!("margin-{}", side), "margin", &zero)
+                    .to_px()
+                    + style
+                        .lookup_length(&format!("padding-{}", side), "padding", &zero)
+                        .to_px()
+                    + border_width(style, side)
+            })
+            .sum()
+    }
+
+    // Follow a path of child indices down from this box
+    fn descendant_mut(&mut self, path: &[usize]) -> &mut LayoutBox<'a, N> {
+        path.iter()
+            .fold(self, |layout_box, &i| &mut layout_box.children[i])
+    }
+
     fn calculate_block_height(&mut self) {
         // If the height is set to an explicit length, use that exact length
         // Otherwise, just keep the value set by `layout_block_children`
-        if let Some(Value::Length(h, Unit::Px)) = self.get_style_node().value("height") {
+        // A replaced element is as tall as its content
+        if let Some(Value::Length(h, Unit::Px)) = self.style_node().length("height") {
+            self.dimensions.content.height = h;
+        } else if let Some((_, h)) = replaced_size(self.style_node()) {
             self.dimensions.content.height = h;
         }
     }
 
     // Where a new inline child should go
-    fn get_inline_container(&mut self) -> &mut LayoutBox<'a> {
+    fn get_inline_container(&mut self) -> &mut LayoutBox<'a, N> {
         match self.box_type {
-            BoxType::InlineNode(_) | BoxType::AnonymousBlock => self,
-            BoxType::BlockNode(_) => {
+            BoxType::InlineNode(_) | BoxType::AnonymousBlock(_) | BoxType::Marker(_) => self,
+            BoxType::BlockNode(node) | BoxType::FlexContainer(node) => {
                 // If we've just generated an anonymous block box, keep using it.
                 // Otherwise, create a new one
                 match self.children.last() {
                     Some(&LayoutBox {
-                        box_type: BoxType::AnonymousBlock,
+                        box_type: BoxType::AnonymousBlock(_),
                         ..
                     }) => {}
-                    _ => self.children.push(LayoutBox::new(BoxType::AnonymousBlock)),
+                    _ => self
+                        .children
+                        .push(LayoutBox::new(BoxType::AnonymousBlock(node))),
                 }
                 self.children.last_mut().unwrap()
             }
         }
     }
 
-    fn get_style_node(&self) -> &'a StyledNode<'a> {
+    // The border box of this box relative to a viewport scrolled to `scroll` (x, y) px
+    // from the top left of the document
+    pub fn bounding_rect(&self, scroll: (f32, f32)) -> Rect {
+        let border_box = self.dimensions.border_box();
+        Rect {
+            x: border_box.x - scroll.0,
+            y: border_box.y - scroll.1,
+            ..border_box
+        }
+    }
+
+    // Return the topmost box whose border box contains the point, if any: the last one
+    // painted there, which is the deepest unless stacking order puts another box on top
+    pub fn hit_test(&self, x: f32, y: f32) -> Option<&LayoutBox<'a, N>> {
+        // Descendants of an `overflow: hidden` box can only be hit inside its padding box
+        self.paint_layers()
+            .filter(|(layout_box, clip)| {
+                layout_box.dimensions.border_box().contains(x, y)
+                    && clip.is_none_or(|clip| clip.contains(x, y))
+            })
+            .last()
+            .map(|(layout_box, _)| layout_box)
+    }
+
+    // Return the chain of boxes from this box down to the box `hit_test` finds. The result
+    // is empty if nothing was hit
+    pub fn hit_test_path(&self, x: f32, y: f32) -> Vec<&LayoutBox<'a, N>> {
+        match self.hit_test(x, y) {
+            Some(target) => self.path_to(target).unwrap_or_default(),
+            None => Vec::new(),
+        }
+    }
+
+    // The chain of boxes from this box down to `target`, if it's in this subtree
+    fn path_to(&self, target: &LayoutBox<'a, N>) -> Option<Vec<&LayoutBox<'a, N>>> {
+        if std::ptr::eq(self, target) {
+            return Some(vec![self]);
+        }
+        self.children.iter().find_map(|child| {
+            let mut path = child.path_to(target)?;
+            path.insert(0, self);
+            Some(path)
+        })
+    }
+
+    // The font sizes that relative lengths in this box resolve against. Anonymous blocks
+    // use those of the block that generated them
+    pub fn length_context(&self) -> LengthContext {
         match self.box_type {
-            BoxType::BlockNode(node) => node,
-            BoxType::InlineNode(node) => node,
-            BoxType::AnonymousBlock => panic!("Anonymous block box has no style node"),
+            BoxType::BlockNode(node)
+            | BoxType::FlexContainer(node)
+            | BoxType::InlineNode(node)
+            | BoxType::AnonymousBlock(node)
+            | BoxType::Marker(node) => node.length_context,
+        }
+    }
+
+    // Return true if this box clips its descendants to its padding box. Scrolling isn't
+    // implemented, so `scroll` and `auto` clip like `hidden`
+    fn clips_overflow(&self) -> bool {
+        self.overflow() != "visible"
+    }
+
+    // The computed `overflow` of this box: `visible`, `hidden`, `scroll` or `auto`
+    fn overflow(&self) -> String {
+        let value = match self.box_type {
+            BoxType::AnonymousBlock(_) | BoxType::Marker(_) => None,
+            _ => self.style_node().value("overflow"),
+        };
+        match value {
+            Some(Value::Keyword(s)) if matches!(&*s, "hidden" | "scroll" | "auto") => s,
+            _ => "visible".to_string(),
+        }
+    }
+
+    // Whether this box should have scrollbars: it has `overflow: scroll`, or `auto` and
+    // content that doesn't fit in its padding box
+    pub fn is_scrollable(&self) -> bool {
+        let padding_box = self.dimensions.padding_box();
+        match &*self.overflow() {
+            "scroll" => true,
+            "auto" => self.scroll_size() != (padding_box.width, padding_box.height),
+            _ => false,
+        }
+    }
+
+    // The size of the area this box's content covers, measured from the top left corner
+    // of its padding box. It's never smaller than the padding box, and larger when the
+    // content overflows, e.g. out of a box with a fixed height
+    pub fn scroll_size(&self) -> (f32, f32) {
+        let padding_box = self.dimensions.padding_box();
+        let area = match self.content_area() {
+            Some(area) => padding_box.union(area),
+            None => padding_box,
+        };
+        (
+            area.x + area.width - padding_box.x,
+            area.y + area.height - padding_box.y,
+        )
+    }
+
+    // The area covered by this box's lines and descendants, not counting what's inside
+    // descendants that clip their own overflow
+    fn content_area(&self) -> Option<Rect> {
+        let lines = self
+            .line_boxes
+            .iter()
+            .flat_map(|line| &line.fragments)
+            .map(|fragment| fragment.rect);
+        let children = self.children.iter().flat_map(|child| {
+            let inner = match child.clips_overflow() {
+                true => None,
+                false => child.content_area(),
+            };
+            std::iter::once(child.dimensions.margin_box()).chain(inner)
+        });
+        lines.chain(children).reduce(Rect::union)
+    }
+
+    // Return the style node this box was generated from, or `None` for anonymous boxes
+    pub fn get_style_node(&self) -> Option<&'a StyledNode<'a, N>> {
+        match self.box_type {
+            BoxType::BlockNode(node) => Some(node),
+            BoxType::FlexContainer(node) => Some(node),
+            BoxType::InlineNode(node) => Some(node),
+            BoxType::Marker(node) => Some(node),
+            BoxType::AnonymousBlock(_) => None,
+        }
+    }
+
+    // The tag name of the element this box was generated from, or `None` for text and
+    // anonymous boxes
+    pub fn tag_name(&self) -> Option<&'a str> {
+        self.get_style_node()?.tag_name()
+    }
+
+    // Return the color value of property `name`, or `None` if it isn't set to a color
+    // or this box is anonymous
+    pub fn color(&self, name: &str) -> Option<Color> {
+        match self.get_style_node()?.value(name) {
+            Some(Value::ColorValue(color)) => Some(color),
+            _ => None,
         }
     }
+
+    fn style_node(&self) -> &'a StyledNode<'a, N> {
+        self.get_style_node()
+            .expect("Anonymous block box has no style node")
+    }
+}
+
+// Return the used border width of one side of a box. A side without a visible
+// `border-style` (the initial value is `none`) has no border, whatever its width
+fn border_width<N>(style: &StyledNode<'_, N>, side: &str) -> f32 {
+    let none = Value::Keyword("none".to_string());
+    match style.lookup(&format!("border-{}-style", side), "border-style", &none) {
+        Value::Keyword(ref s) if s == "none" || s == "hidden" => return 0.0,
+        _ => {}
+    }
+
+    // `border-width` has initial value `medium`
+    style
+        .value(&format!("border-{}-width", side))
+        .or_else(|| style.value("border-width"))
+        .unwrap_or_else(|| Value::Keyword("medium".to_string()))
+        .border_width()
+        .resolve(&style.length_context)
+        .to_px()
+}
+
+// Whether a node's computed `direction` is `rtl`
+fn is_rtl<N>(style: &StyledNode<'_, N>) -> bool {
+    matches!(style.value("direction"), Some(Value::Keyword(ref d)) if d == "rtl")
+}
+
+// Whether a node has `position: fixed`
+fn is_fixed<N>(style: &StyledNode<'_, N>) -> bool {
+    matches!(style.value("position"), Some(Value::Keyword(ref p)) if p == "fixed")
+}
+
+// Return true if an auto width of the node is shrink-to-fit: for floats, and boxes with
+// `position: absolute` or `fixed`
+fn shrinks_to_fit<N>(style: &StyledNode<'_, N>) -> bool {
+    let float = matches!(style.value("float"),
+        Some(Value::Keyword(ref f)) if f == "left" || f == "right");
+    let absolute = matches!(style.value("position"),
+        Some(Value::Keyword(ref p)) if p == "absolute" || p == "fixed");
+    float || absolute
+}
+
+// The content size of a replaced element, which is only `img` for now: its CSS `width`
+// and `height` if they're lengths, or else its `width` and `height` attributes. A missing
+// dimension keeps the placeholder's aspect ratio, and with neither it's the size of the
+// placeholder. Returns `None` for other nodes
+fn replaced_size<'a, N: DomNode<'a>>(style: &StyledNode<'a, N>) -> Option<(f32, f32)> {
+    if style.tag_name() != Some("img") {
+        return None;
+    }
+    let dimension = |name: &str| match style.length(name) {
+        Some(Value::Length(px, Unit::Px)) => Some(px),
+        _ => style
+            .attribute(name)
+            .and_then(|value| value.trim().trim_end_matches("px").parse::<f32>().ok()),
+    };
+    let ratio = PLACEHOLDER_WIDTH / PLACEHOLDER_HEIGHT;
+    Some(match (dimension("width"), dimension("height")) {
+        (Some(width), Some(height)) => (width, height),
+        (Some(width), None) => (width, width / ratio),
+        (None, Some(height)) => (height * ratio, height),
+        (None, None) => (PLACEHOLDER_WIDTH, PLACEHOLDER_HEIGHT),
+    })
 }
 
-pub enum BoxType<'a> {
-    BlockNode(&'a StyledNode<'a>),
-    InlineNode(&'a StyledNode<'a>),
-    AnonymousBlock,
+// Return the computed `white-space` of a node: `normal`, `nowrap`, or `pre`
+fn white_space<N>(style: &StyledNode<'_, N>) -> String {
+    match style.value("white-space") {
+        Some(Value::Keyword(s)) if s == "nowrap" || s == "pre" => s,
+        _ => "normal".to_string(),
+    }
 }
 
-// Build the tree of LayoutBoxes, but don't perform any layout calculations yet
-pub fn build_layout_tree<'a>(style_node: &'a StyledNode<'a>) -> LayoutBox<'a> {
+// The computed `tab-size` of a node: how many spaces apart tab stops are. Defaults to 8
+fn tab_size<N>(style: &StyledNode<'_, N>) -> usize {
+    match style.value("tab-size") {
+        Some(Value::Number(n)) if n >= 1.0 => n as usize,
+        _ => 8,
+    }
+}
+
+// Return the used `line-height` of a node in px, or `None` for `normal`. A unitless
+// number is a multiple of the font size
+fn line_height<N>(style: &StyledNode<'_, N>) -> Option<f32> {
+    match style.length("line-height")? {
+        Value::Number(n) => Some(n * style.length_context.font_size),
+        Value::Length(h, Unit::Px) => Some(h),
+        _ => None,
+    }
+}
+
+impl<'a, N: DomNode<'a>> std::fmt::Display for LayoutBox<'a, N> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.fmt_indented(f, 0)
+    }
+}
+
+impl<'a, N: DomNode<'a>> LayoutBox<'a, N> {
+    // Print this box and its descendants, one box per line, indented by depth
+    fn fmt_indented(&self, f: &mut std::fmt::Formatter<'_>, depth: usize) -> std::fmt::Result {
+        let d = &self.dimensions;
+        writeln!(
+            f,
+            "{}{} content=({},{} {}x{}) padding=({}) border=({}) margin=({})",
+            "  ".repeat(depth),
+            self.box_type,
+            fmt_px(d.content.x),
+            fmt_px(d.content.y),
+            fmt_px(d.content.width),
+            fmt_px(d.content.height),
+            d.padding,
+            d.border,
+            d.margin
+        )?;
+        for child in &self.children {
+            child.fmt_indented(f, depth + 1)?;
+        }
+        Ok(())
+    }
+}
+
+// Format a length with at most two decimal places, dropping trailing zeros
+fn fmt_px(px: f32) -> String {
+    let s = format!("{:.2}", px);
+    let s = s.trim_end_matches('0').trim_end_matches('.');
+    match s {
+        "-0" => "0".to_string(),
+        _ => s.to_string(),
+    }
+}
+
+// Edges are printed in CSS order: top, right, bottom, left
+impl std::fmt::Display for EdgeSizes {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{},{},{},{}",
+            fmt_px(self.top),
+            fmt_px(self.right),
+            fmt_px(self.bottom),
+            fmt_px(self.left)
+        )
+    }
+}
+
+pub enum BoxType<'a, N = &'a Node> {
+    BlockNode(&'a StyledNode<'a, N>),
+    FlexContainer(&'a StyledNode<'a, N>),
+    InlineNode(&'a StyledNode<'a, N>),
+    // An anonymous block refers to the style node of the block that generated it
+    AnonymousBlock(&'a StyledNode<'a, N>),
+    // The marker of a list item, placed to the left of its content. It refers to the
+    // marker text, which it holds as its only inline child
+    Marker(&'a StyledNode<'a, N>),
+}
+
+impl<'a, N: DomNode<'a>> std::fmt::Display for BoxType<'a, N> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BoxType::BlockNode(node) => write!(f, "BlockNode({})", node.label()),
+            BoxType::FlexContainer(node) => write!(f, "FlexContainer({})", node.label()),
+            BoxType::InlineNode(node) => write!(f, "InlineNode({})", node.label()),
+            BoxType::AnonymousBlock(_) => write!(f, "AnonymousBlock"),
+            BoxType::Marker(node) => write!(f, "Marker({})", node.label()),
+        }
+    }
+}
+
+// Build the tree of LayoutBoxes, but don't perform any layout calculations yet.
+// Returns `None` if the root has `display: none`
+pub fn build_layout_tree<'a, N: DomNode<'a>>(
+    style_node: &'a StyledNode<'a, N>,
+) -> Option<LayoutBox<'a, N>> {
     // Create the root box
-    let mut root = LayoutBox::new(match style_node.display() {
-        Display::Block => BoxType::BlockNode(style_node),
-        Display::Inline => BoxType::InlineNode(style_node),
-        Display::None => panic!("Root not has display: none"),
-    });
+    let box_type = match style_node.display() {
+        Display::Block | Display::ListItem => BoxType::BlockNode(style_node),
+        Display::Flex => BoxType::FlexContainer(style_node),
+        // The root element is always block-level
+        Display::Inline => BoxType::BlockNode(style_node),
+        Display::None => return None,
+    };
+    Some(build_box(box_type, style_node))
+}
+
+// Create a box of the given type, along with its descendant boxes
+fn build_box<'a, N: DomNode<'a>>(
+    box_type: BoxType<'a, N>,
+    style_node: &'a StyledNode<'a, N>,
+) -> LayoutBox<'a, N> {
+    let mut root = LayoutBox::new(box_type);
+    let is_flex = matches!(root.box_type, BoxType::FlexContainer(_));
+
+    // A positioned box with an integer `z-index` establishes a stacking context
+    let positioned = matches!(style_node.value("position"),
+        Some(Value::Keyword(ref p)) if p != "static");
+    root.positioned = positioned;
+    if let (true, Some(Value::Number(z))) = (positioned, style_node.value("z-index")) {
+        root.z_index = Some(z as i32);
+        root.stacking_context = true;
+    }
+
+    // So does any box with an `opacity` less than 1
+    if let Some(Value::Number(opacity)) = style_node.value("opacity") {
+        root.opacity = opacity.clamp(0.0, 1.0);
+        root.stacking_context |= root.opacity < 1.0;
+    }
+
+    // A list item's marker comes before its content
+    if let Some(ref marker) = style_node.marker {
+        let mut marker_box = LayoutBox::new(BoxType::Marker(marker));
+        marker_box
+            .children
+            .push(LayoutBox::new(BoxType::InlineNode(marker)));
+        root.children.push(marker_box);
+    }
 
-    // Create the descendant boxes
     for child in &style_node.children {
         match child.display() {
-            Display::Block => root.children.push(build_layout_tree(child)),
+            // Inline elements in a flex container are blockified into flex items, while
+            // runs of text are wrapped in anonymous flex items. Blank text is dropped
+            Display::Inline if is_flex => match child.text() {
+                Some(text) if text.trim().is_empty() => {}
+                Some(_) => root
+                    .get_inline_container()
+                    .children
+                    .push(build_box(BoxType::InlineNode(child), child)),
+                None => root
+                    .children
+                    .push(build_box(BoxType::BlockNode(child), child)),
+            },
+            Display::Block | Display::ListItem => root
+                .children
+                .push(build_box(BoxType::BlockNode(child), child)),
+            Display::Flex => root
+                .children
+                .push(build_box(BoxType::FlexContainer(child), child)),
             Display::Inline => root
                 .get_inline_container()
                 .children
-                .push(build_layout_tree(style_node)),
+                .push(build_box(BoxType::InlineNode(child), child)),
             Display::None => {} // Skip nodes with `display: none;`
         }
     }
 
     root
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        arena::Document,
+        css,
+        dom::{self, ParseOptions},
+        style::style_tree,
+    };
+
+    #[test]
+    fn box_model_rects() {
+        let edges = |left, right, top, bottom| EdgeSizes {
+            left,
+            right,
+            top,
+            bottom,
+        };
+        let rect = |x, y, width, height| Rect {
+            x,
+            y,
+            width,
+            height,
+        };
+        let d = Dimensions {
+            content: rect(20.0, 30.0, 100.0, 50.0),
+            padding: edges(1.0, 2.0, 3.0, 4.0),
+            border: edges(5.0, 0.0, 0.5, 1.5),
+            margin: edges(-2.0, 10.0, 0.0, 8.0),
+        };
+        assert_eq!(rect(19.0, 27.0, 103.0, 57.0), d.padding_box());
+        assert_eq!(rect(14.0, 26.5, 108.0, 59.0), d.border_box());
+        assert_eq!(rect(16.0, 26.5, 116.0, 67.0), d.margin_box());
+        assert_eq!(
+            d.border_box(),
+            d.content.expanded_by(d.padding).expanded_by(d.border)
+        );
+        assert_eq!(d.content, d.content.expanded_by(Default::default()));
+    }
+
+    // Lay out `html` styled by `css` inside a viewport of the given width
+    fn layout_with<F>(html: &str, css: &str, width: f32, check: F)
+    where
+        F: FnOnce(&LayoutBox),
+    {
+        let root = dom::Parser::parse(html.to_string());
+        let stylesheet = css::Parser::parse(css.to_string());
+        let style_root = style_tree(&root, &stylesheet);
+        let mut layout_root = build_layout_tree(&style_root).unwrap();
+        let mut viewport: Dimensions = Default::default();
+        viewport.content.width = width;
+        layout_root.layout(viewport);
+        check(&layout_root);
+    }
+
+    fn widths(layout_box: &LayoutBox) -> Vec<f32> {
+        layout_box
+            .children
+            .iter()
+            .map(|child| child.dimensions.content.width)
+            .collect()
+    }
+
+    #[test]
+    fn flex_grow_distributes_free_space() {
+        layout_with(
+            "<div><p></p><p></p><p class=\"double\"></p></div>",
+            "div { display: flex; width: 400px; }
+             p { flex-grow: 1; }
+             p.double { flex-grow: 2; }",
+            800.0,
+            |root| assert_eq!(vec![100.0, 100.0, 200.0], widths(root)),
+        );
+    }
+
+    #[test]
+    fn flex_items_take_content_width() {
+        layout_with(
+            "<div><p>four</p><p class=\"grow\">x</p></div>",
+            "div { display: flex; width: 200px; }
+             p { padding: 5px; }
+             p.grow { flex-grow: 1; }",
+            800.0,
+            // "four" is 32px wide with the default 16px monospace metrics
+            |root| assert_eq!(vec![32.0, 200.0 - 32.0 - 20.0], widths(root)),
+        );
+    }
+
+    #[test]
+    fn intrinsic_widths() {
+        layout_with(
+            "<div><section><p>short words</p></section>\
+             <p>an extraordinarily-long-token <span>here</span></p>\
+             <p class=\"fixed\">wide content is ignored</p></div>",
+            "div, section, p { display: block; }
+             section { padding: 4px; }
+             p { margin: 2px; }
+             p.fixed { width: 50px; }",
+            800.0,
+            |root| {
+                let metrics = MonospaceMetrics;
+                // Each character is 8px wide
+                assert_eq!((52.0, 100.0), root.children[0].intrinsic_widths(&metrics));
+                assert_eq!((212.0, 276.0), root.children[1].intrinsic_widths(&metrics));
+                assert_eq!((54.0, 54.0), root.children[2].intrinsic_widths(&metrics));
+                assert_eq!((212.0, 276.0), root.intrinsic_widths(&metrics));
+            },
+        );
+    }
+
+    #[test]
+    fn flex_justify_space_between() {
+        layout_with(
+            "<div><p></p><p></p><p></p></div>",
+            "div { display: flex; width: 400px; justify-content: space-between; }
+             p { width: 50px; height: 10px; }",
+            800.0,
+            |root| {
+                let xs: Vec<f32> = root
+                    .children
+                    .iter()
+                    .map(|child| child.dimensions.content.x)
+                    .collect();
+                assert_eq!(vec![0.0, 175.0, 350.0], xs);
+            },
+        );
+    }
+
+    #[test]
+    fn flex_fixed_and_growing_items() {
+        layout_with(
+            "<div><p class=\"fixed\"></p><p></p><p></p></div>",
+            "div { display: flex; width: 400px; }
+             p { flex-grow: 1; }
+             p.fixed { width: 100px; flex-grow: 0; }",
+            800.0,
+            |root| assert_eq!(vec![100.0, 150.0, 150.0], widths(root)),
+        );
+    }
+
+    #[test]
+    fn flex_shrink_and_stretch() {
+        layout_with(
+            "<div><p class=\"tall\"></p><p></p></div>",
+            "div { display: flex; width: 300px; }
+             p { width: 200px; }
+             p.tall { height: 50px; flex-shrink: 2; }",
+            800.0,
+            |root| {
+                // 100px of overflow, taken 2:1 from the equally-sized items
+                assert_eq!(vec![200.0 - 200.0 / 3.0, 200.0 - 100.0 / 3.0], widths(root));
+                assert_eq!(50.0, root.children[1].dimensions.content.height);
+                assert_eq!(50.0, root.dimensions.content.height);
+            },
+        );
+    }
+
+    // Monospace metrics with glyphs a whole em wide
+    struct WideMetrics;
+
+    impl FontMetrics for WideMetrics {
+        fn text_width(&self, text: &str, font_size: f32) -> f32 {
+            text.chars().count() as f32 * font_size
+        }
+
+        fn ascent(&self, font_size: f32) -> f32 {
+            MonospaceMetrics.ascent(font_size)
+        }
+
+        fn descent(&self, font_size: f32) -> f32 {
+            MonospaceMetrics.descent(font_size)
+        }
+    }
+
+    #[test]
+    fn relayout_dirty_matches_full_layout() {
+        let root = dom::Parser::parse(
+            "<div><section><p class=\"deep\">an-unbreakable-word</p></section>\
+             <aside><p></p><p>text</p></aside></div>"
+                .to_string(),
+        );
+        let before = css::Parser::parse(
+            "div, section, aside, p { display: block; padding: 2px; } p.deep { height: 10px; }"
+                .to_string(),
+        );
+        let after = css::Parser::parse(
+            "div, section, aside, p { display: block; padding: 2px; } p.deep { height: 30px; }"
+                .to_string(),
+        );
+        let (before, after) = (style_tree(&root, &before), style_tree(&root, &after));
+        let mut viewport: Dimensions = Default::default();
+        viewport.content.width = 200.0;
+
+        let mut tree = layout_tree_with_metrics(&before, viewport, &WideMetrics).unwrap();
+        let deep = &mut tree.root.children[0].children[0];
+        deep.box_type = BoxType::BlockNode(&after.children[0].children[0]);
+        deep.mark_dirty();
+        let laid_out = relayout_dirty(&mut tree, viewport, &WideMetrics);
+
+        // Only div, section, and p.deep are laid out again; aside is moved down
+        assert_eq!(3, laid_out);
+        let full = layout_tree_with_metrics(&after, viewport, &WideMetrics).unwrap();
+        assert_eq!(full.root.to_string(), tree.root.to_string());
+        let text = &tree.root.children[1].children[1].children[0].line_boxes[0];
+        assert_eq!(48.0, text.fragments[0].rect.y);
+
+        // The word in p.deep is measured with the same metrics, overflowing the viewport
+        // to 310px, and the content size follows the new height
+        assert_eq!(full.content_size, tree.content_size);
+        let size = tree.content_size;
+        assert_eq!(
+            (0.0, 0.0, 310.0, 70.0),
+            (size.x, size.y, size.width, size.height)
+        );
+    }
+
+    #[test]
+    fn percentage_padding_and_margins() {
+        layout_with(
+            "<div><p class=\"padded\"></p><p class=\"shifted\"></p></div>",
+            "div { display: block; width: 500px; }
+             p { display: block; }
+             p.padded { padding: 10%; }
+             p.shifted { width: 100px; margin-left: 25%; margin-right: auto; }",
+            800.0,
+            |root| {
+                let padded = root.children[0].dimensions;
+                assert_eq!("50,50,50,50", padded.padding.to_string());
+                assert_eq!(400.0, padded.content.width);
+
+                let shifted = root.children[1].dimensions;
+                assert_eq!(125.0, shifted.content.x);
+                assert_eq!(275.0, shifted.margin.right);
+            },
+        );
+    }
+
+    #[test]
+    fn margin_and_padding_shorthands() {
+        layout_with(
+            "<div><p class=\"centered\"></p><p class=\"sides\"></p></div>",
+            "div { display: block; width: 500px; }
+             p { display: block; }
+             p.centered { width: 100px; margin: 0 auto; padding: 5px 10px; }
+             p.sides { margin: 1px 2px 3px 4px; }",
+            800.0,
+            |root| {
+                let centered = root.children[0].dimensions;
+                assert_eq!("0,190,0,190", centered.margin.to_string());
+                assert_eq!("5,10,5,10", centered.padding.to_string());
+                assert_eq!(200.0, centered.content.x);
+
+                let sides = root.children[1].dimensions;
+                assert_eq!("1,2,3,4", sides.margin.to_string());
+                assert_eq!(494.0, sides.content.width);
+            },
+        );
+    }
+
+    #[test]
+    fn hit_test_nested_boxes() {
+        layout_with(
+            "<div><p></p></div>",
+            "div { display: block; padding: 10px; height: 100px; }
+             p { display: block; padding: 5px; width: 50px; height: 20px; }",
+            800.0,
+            |root| {
+                let child = &root.children[0];
+
+                // Inside the child's padding
+                let hit = root.hit_test(12.0, 12.0).unwrap();
+                assert!(std::ptr::eq(child, hit));
+                let path = root.hit_test_path(12.0, 12.0);
+                assert_eq!(2, path.len());
+                assert!(std::ptr::eq(root, path[0]));
+
+                // Inside the parent but outside the child
+                let hit = root.hit_test(100.0, 100.0).unwrap();
+                assert!(std::ptr::eq(root, hit));
+
+                // Outside everything
+                assert!(root.hit_test(900.0, 10.0).is_none());
+                assert!(root.hit_test_path(900.0, 10.0).is_empty());
+            },
+        );
+    }
+
+    #[test]
+    fn bounding_rect_with_scroll() {
+        layout_with(
+            "<div><p></p></div>",
+            "div { display: block; padding: 10px; }
+             p { display: block; margin: 5px; border-width: 1px; border-style: solid;
+                 width: 50px; height: 20px; }",
+            800.0,
+            |root| {
+                let p = &root.children[0];
+                let rect = |x, y| Rect {
+                    x,
+                    y,
+                    width: 52.0,
+                    height: 22.0,
+                };
+                assert_eq!(rect(15.0, 15.0), p.bounding_rect((0.0, 0.0)));
+                assert_eq!(rect(5.0, -85.0), p.bounding_rect((10.0, 100.0)));
+            },
+        );
+    }
+
+    #[test]
+    fn hit_test_follows_stacking_order() {
+        layout_with(
+            "<div><p class=\"raised\"></p><p class=\"flow\"></p><p class=\"last\"></p></div>",
+            "div, p { display: block; height: 20px; }
+             p.raised { position: relative; z-index: 1; }
+             p.flow { margin-top: -10px; }
+             p.last { position: relative; margin-top: -5px; }",
+            100.0,
+            |root| {
+                let (raised, flow, last) =
+                    (&root.children[0], &root.children[1], &root.children[2]);
+                // The raised paragraph is painted over the later one in flow
+                assert!(std::ptr::eq(raised, root.hit_test(5.0, 15.0).unwrap()));
+                assert!(std::ptr::eq(flow, root.hit_test(5.0, 22.0).unwrap()));
+                // A positioned box is painted over boxes in flow
+                assert!(std::ptr::eq(last, root.hit_test(5.0, 27.0).unwrap()));
+                let path = root.hit_test_path(5.0, 15.0);
+                assert_eq!(2, path.len());
+                assert!(std::ptr::eq(raised, path[1]));
+            },
+        );
+    }
+
+    #[test]
+    fn hit_test_respects_overflow_hidden() {
+        layout_with(
+            "<div><p></p></div>",
+            "div { display: block; width: 100px; height: 100px; overflow: hidden; }
+             p { display: block; width: 300px; height: 20px; }",
+            800.0,
+            |root| {
+                assert!(std::ptr::eq(
+                    &root.children[0],
+                    root.hit_test(50.0, 10.0).unwrap()
+                ));
+                assert!(root.hit_test(200.0, 10.0).is_none());
+            },
+        );
+    }
+
+    #[test]
+    fn display_layout_tree() {
+        layout_with(
+            "<div id=\"main\"><p class=\"note big\">text</p>hello</div>",
+            "div { display: block; padding: 8px; }
+             p { display: block; margin-bottom: 16px; height: 10.333px; }",
+            800.0,
+            |root| {
+                assert_eq!(
+                    "BlockNode(div#main) content=(8,8 784x42.33) padding=(8,8,8,8) border=(0,0,0,0) margin=(0,0,0,0)
+  BlockNode(p.note.big) content=(8,8 784x10.33) padding=(0,0,0,0) border=(0,0,0,0) margin=(0,0,16,0)
+    AnonymousBlock content=(8,8 784x16) padding=(0,0,0,0) border=(0,0,0,0) margin=(0,0,0,0)
+      InlineNode(\"text\") content=(8,8 32x16) padding=(0,0,0,0) border=(0,0,0,0) margin=(0,0,0,0)
+  AnonymousBlock content=(8,34.33 784x16) padding=(0,0,0,0) border=(0,0,0,0) margin=(0,0,0,0)
+    InlineNode(\"hello\") content=(8,34.33 40x16) padding=(0,0,0,0) border=(0,0,0,0) margin=(0,0,0,0)
+",
+                    root.to_string()
+                );
+            },
+        );
+    }
+
+    fn paint_labels(root: &LayoutBox) -> Vec<String> {
+        root.paint_order()
+            .filter(|layout_box| matches!(layout_box.box_type, BoxType::BlockNode(_)))
+            .map(|layout_box| layout_box.box_type.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn z_index_paints_above_later_siblings() {
+        layout_with(
+            "<div><p class=\"a\"></p><p class=\"b\"></p></div>",
+            "div, p { display: block; }
+             p { height: 20px; }
+             p.a { position: relative; z-index: 1; }
+             p.b { margin-top: -10px; }",
+            800.0,
+            |root| {
+                assert_eq!(Some(1), root.children[0].z_index);
+                assert_eq!(None, root.children[1].z_index);
+                assert_eq!(
+                    vec!["BlockNode(div)", "BlockNode(p.b)", "BlockNode(p.a)"],
+                    paint_labels(root)
+                );
+            },
+        );
+    }
+
+    #[test]
+    fn nested_stacking_contexts() {
+        layout_with(
+            "<div><p class=\"a\"><p class=\"top\"></p></p>\
+             <p class=\"b\"><p class=\"below\"></p></p></div>",
+            "div, p { display: block; position: relative; }
+             p.a { z-index: 1; }
+             p.top { z-index: 100; }
+             p.b { z-index: 2; }
+             p.below { z-index: -1; }",
+            800.0,
+            |root| {
+                // p.top stays within p.a, below p.b; p.below is painted under p.b's content
+                // but above everything outside p.b
+                assert_eq!(
+                    vec![
+                        "BlockNode(div)",
+                        "BlockNode(p.a)",
+                        "BlockNode(p.top)",
+                        "BlockNode(p.b)",
+                        "BlockNode(p.below)"
+                    ],
+                    paint_labels(root)
+                );
+            },
+        );
+    }
+
+    #[test]
+    fn em_and_rem_lengths() {
+        layout_with(
+            "<div class=\"outer\"><div class=\"inner\"><p></p></div>text</div>",
+            "div { display: block; margin: 1em; }
+             .outer { font-size: 20px; }
+             .inner { font-size: 0.5em; }
+             p { display: block; padding-left: 2rem; height: 1.5em; }",
+            800.0,
+            |root| {
+                assert_eq!(20.0, root.dimensions.margin.left);
+                let inner = &root.children[0];
+                assert_eq!(10.0, inner.dimensions.margin.top);
+                let p = &inner.children[0];
+                assert_eq!(40.0, p.dimensions.padding.left);
+                assert_eq!(15.0, p.dimensions.content.height);
+
+                // The anonymous block wrapping the text uses the outer div's font size
+                let anonymous = &root.children[1];
+                assert_eq!(20.0, anonymous.length_context().font_size);
+                assert_eq!(20.0, anonymous.length_context().root_font_size);
+            },
+        );
+    }
+
+    #[test]
+    fn color_accessor() {
+        layout_with(
+            "<div><p>text</p></div>",
+            "div { display: block; background: #336699; }
+             p { display: block; }",
+            800.0,
+            |root| {
+                assert_eq!(
+                    Some(Color {
+                        r: 0x33,
+                        g: 0x66,
+                        b: 0x99,
+                        a: 0xff,
+                    }),
+                    root.color("background")
+                );
+                assert_eq!(None, root.children[0].color("background"));
+
+                let anonymous = &root.children[0].children[0];
+                assert!(anonymous.get_style_node().is_none());
+                assert_eq!(None, anonymous.color("background"));
+            },
+        );
+    }
+
+    #[test]
+    fn tag_name_accessor() {
+        layout_with(
+            "<div><p>text</p></div>",
+            "div, p { display: block; }",
+            800.0,
+            |root| {
+                let paragraph = &root.children[0];
+                assert_eq!(Some("div"), root.tag_name());
+                assert_eq!(Some("p"), paragraph.tag_name());
+                // The anonymous block, and the text inside it
+                assert_eq!(None, paragraph.children[0].tag_name());
+                assert_eq!(None, paragraph.children[0].children[0].tag_name());
+            },
+        );
+    }
+
+    #[test]
+    fn border_widths_per_side() {
+        layout_with(
+            "<div><p></p></div>",
+            "div { display: block; }
+             p { display: block; border-style: solid; border-width: 1px 2px 3px 4px; }",
+            800.0,
+            |root| {
+                let border = &root.children[0].dimensions.border;
+                assert_eq!(
+                    (1.0, 2.0, 3.0, 4.0),
+                    (border.top, border.right, border.bottom, border.left)
+                );
+                assert_eq!(794.0, root.children[0].dimensions.content.width);
+            },
+        );
+    }
+
+    #[test]
+    fn border_width_keywords_and_styles() {
+        layout_with(
+            "<div><p class=\"solid\"></p><p class=\"unstyled\"></p><p class=\"left\"></p></div>",
+            "div { display: block; }
+             p { display: block; border-width: thick; }
+             p.solid { border-style: solid; }
+             p.left { border-left-style: dotted; }",
+            800.0,
+            |root| {
+                let solid = &root.children[0].dimensions;
+                assert_eq!(5.0, solid.border.top);
+                assert_eq!(5.0, solid.border.left);
+                assert_eq!(790.0, solid.content.width);
+
+                let unstyled = &root.children[1].dimensions;
+                assert_eq!(0.0, unstyled.border.top);
+                assert_eq!(0.0, unstyled.border.left);
+
+                let left = &root.children[2].dimensions;
+                assert_eq!(5.0, left.border.left);
+                assert_eq!(0.0, left.border.right);
+                assert_eq!(0.0, left.border.top);
+            },
+        );
+    }
+
+    // Collect the text and rect of each fragment, line by line
+    fn lines(anonymous: &LayoutBox) -> Vec<Vec<(String, Rect)>> {
+        anonymous
+            .line_boxes
+            .iter()
+            .map(|line| {
+                line.fragments
+                    .iter()
+                    .map(|f| (f.text.clone(), f.rect))
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn line_breaking() {
+        // Each character is 8px wide at the default font size
+        layout_with(
+            "<div>aaa bbb <span>ccc </span>dd</div>",
+            "div { display: block; width: 80px; }",
+            800.0,
+            |root| {
+                let anonymous = &root.children[0];
+                let lines = lines(anonymous);
+                assert_eq!(2, lines.len());
+                assert_eq!("aaa bbb", lines[0][0].0);
+                assert_eq!(56.0, lines[0][0].1.width);
+                assert_eq!("ccc", lines[1][0].0);
+                assert_eq!((0.0, 16.0), (lines[1][0].1.x, lines[1][0].1.y));
+                assert_eq!("dd", lines[1][1].0);
+                assert_eq!(32.0, lines[1][1].1.x);
+                assert_eq!(32.0, anonymous.dimensions.content.height);
+
+                // The span is sized to its text
+                let span = &anonymous.children[1].dimensions.content;
+                assert_eq!(
+                    (0.0, 16.0, 24.0, 16.0),
+                    (span.x, span.y, span.width, span.height)
+                );
+            },
+        );
+    }
+
+    #[test]
+    fn text_align() {
+        layout_with(
+            "<div><p class=\"center\">hi there</p><p class=\"right\">hi</p><p>hi</p></div>",
+            "div { display: block; width: 400px; }
+             p { display: block; }
+             p.center { text-align: center; }
+             p.right { text-align: right; }",
+            800.0,
+            |root| {
+                let x = |i: usize| lines(&root.children[i].children[0])[0][0].1.x;
+                assert_eq!((400.0 - 64.0) / 2.0, x(0));
+                assert_eq!(400.0 - 16.0, x(1));
+                assert_eq!(0.0, x(2));
+            },
+        );
+    }
+
+    #[test]
+    fn text_align_is_inherited() {
+        layout_with(
+            "<div><p>hi</p></div>",
+            "div { display: block; width: 400px; text-align: right; }
+             p { display: block; }",
+            800.0,
+            |root| assert_eq!(384.0, lines(&root.children[0].children[0])[0][0].1.x),
+        );
+    }
+
+    #[test]
+    fn rtl_inline_layout() {
+        layout_with(
+            "<div><p><span>ab</span><span>cde</span></p><p class=\"left\">ab</p></div>",
+            "div { display: block; width: 100px; direction: rtl; }
+             p { display: block; }
+             p.left { text-align: left; }",
+            800.0,
+            |root| {
+                // The first box is at the right edge, and the second to its left
+                let anonymous = &root.children[0].children[0];
+                let spans: Vec<(String, f32)> = lines(anonymous)[0]
+                    .iter()
+                    .map(|(text, rect)| (text.clone(), rect.x))
+                    .collect();
+                assert_eq!(
+                    vec![("ab".to_string(), 84.0), ("cde".to_string(), 60.0)],
+                    spans
+                );
+                assert_eq!(84.0, anonymous.children[0].dimensions.content.x);
+                assert_eq!(60.0, anonymous.children[1].dimensions.content.x);
+
+                // `text-align` still overrides the default alignment
+                assert_eq!(0.0, lines(&root.children[1].children[0])[0][0].1.x);
+            },
+        );
+    }
+
+    #[test]
+    fn rtl_block_is_placed_against_the_right_edge() {
+        layout_with(
+            "<div><p></p></div>",
+            "div { display: block; width: 200px; direction: rtl; }
+             p { display: block; width: 50px; margin-right: 10px; }",
+            800.0,
+            |root| {
+                let d = root.children[0].dimensions;
+                assert_eq!((140.0, 10.0), (d.margin.left, d.margin.right));
+                assert_eq!(140.0, d.content.x);
+            },
+        );
+    }
+
+    #[test]
+    fn floats_shrink_to_fit() {
+        layout_with(
+            "<div>
+                <p class=\"left\">Hi</p>
+                <p class=\"right\">Hello</p>
+                <p class=\"absolute\">aaa bbb ccc</p>
+                <p class=\"wide\">aaa bbb ccc dddddddd</p>
+                <p>Hi</p>
+            </div>",
+            "div { display: block; width: 100px; }
+             p { display: block; padding: 2px; }
+             .left { float: left; }
+             .right { float: right; }
+             .absolute { position: absolute; }
+             .wide { float: left; margin-left: 10px; }",
+            800.0,
+            |root| {
+                let widths: Vec<f32> = root
+                    .children
+                    .iter()
+                    .map(|child| child.dimensions.content.width)
+                    .collect();
+                // The wide float is limited to the available width, and only wraps at its
+                // longest word. A box that isn't floated fills its container
+                assert_eq!(vec![16.0, 40.0, 88.0, 86.0, 96.0], widths);
+
+                // Floats are placed against their side of the container
+                let right = root.children[1].dimensions;
+                assert_eq!(100.0, right.border_box().x + right.border_box().width);
+                assert_eq!(2.0, root.children[0].dimensions.content.x);
+            },
+        );
+    }
+
+    #[test]
+    fn containers_hold_their_floats() {
+        layout_with(
+            "<div><section><p></p></section></div>",
+            "div { display: block; }
+             section { display: block; overflow: hidden; }
+             p { display: block; float: left; width: 20px; height: 50px; }",
+            800.0,
+            |root| {
+                let section = root.children[0].dimensions;
+                assert_eq!(50.0, section.content.height);
+                assert_eq!(50.0, root.dimensions.content.height);
+            },
+        );
+    }
+
+    #[test]
+    fn line_height_sizes_line_boxes() {
+        layout_with(
+            "<div><p class=\"double\">aaa bbb</p><p class=\"fixed\">aaa</p></div>",
+            "div { display: block; width: 30px; }
+             p { display: block; font-size: 10px; }
+             p.double { line-height: 2; }
+             p.fixed { line-height: 4px; }",
+            800.0,
+            |root| {
+                let anonymous = &root.children[0].children[0];
+                let heights: Vec<f32> =
+                    anonymous.line_boxes.iter().map(|l| l.rect.height).collect();
+                assert_eq!(vec![20.0, 20.0], heights);
+                assert_eq!(40.0, anonymous.dimensions.content.height);
+                // The glyphs are centered within the line
+                assert_eq!(5.0, lines(anonymous)[0][0].1.y);
+                assert_eq!(25.0, lines(anonymous)[1][0].1.y);
+
+                // A line can be shorter than its content
+                let anonymous = &root.children[1].children[0];
+                assert_eq!(4.0, anonymous.line_boxes[0].rect.height);
+                assert_eq!(
+                    -3.0,
+                    lines(anonymous)[0][0].1.y - anonymous.dimensions.content.y
+                );
+            },
+        );
+    }
+
+    #[test]
+    fn line_height_forms() {
+        layout_with(
+            "<div>
+                <p class=\"fixed\">aaa bbb ccc</p>
+                <p class=\"number\"><span>aaa</span></p>
+                <p class=\"percent\"><span>aaa</span></p>
+            </div>",
+            "div { display: block; width: 40px; }
+             p { display: block; }
+             span { font-size: 10px; }
+             p.fixed { line-height: 20px; }
+             p.number { line-height: 2; }
+             p.percent { line-height: 150%; }",
+            800.0,
+            |root| {
+                // Three lines at 20px each
+                let anonymous = &root.children[0].children[0];
+                assert_eq!(3, anonymous.line_boxes.len());
+                assert_eq!(60.0, anonymous.dimensions.content.height);
+
+                // A number is inherited as-is, and multiplies the child's own font size
+                let anonymous = &root.children[1].children[0];
+                assert_eq!(20.0, anonymous.line_boxes[0].rect.height);
+
+                // A percentage is resolved against the parent's font size, then inherited
+                let anonymous = &root.children[2].children[0];
+                assert_eq!(24.0, anonymous.line_boxes[0].rect.height);
+            },
+        );
+    }
+
+    #[test]
+    fn generated_content() {
+        layout_with(
+            "<div><p>text</p></div>",
+            "div, p { display: block; }
+             p::before { content: \"» \"; }
+             p::after { content: \"!\"; color: #ff0000; }",
+            800.0,
+            |root| {
+                let paragraph = &root.children[0];
+                let text: Vec<Vec<String>> = lines(&paragraph.children[0])
+                    .into_iter()
+                    .map(|line| line.into_iter().map(|(text, _)| text).collect())
+                    .collect();
+                assert_eq!(vec![vec!["»", "text", "!"]], text);
+                assert!(paragraph.children[0].children[0].color("color").is_none());
+                assert!(paragraph.children[0].children[2].color("color").is_some());
+            },
+        );
+    }
+
+    #[test]
+    fn list_item_markers() {
+        layout_with(
+            "<div><ul><li>one</li><li>two</li></ul><ol><li>a</li><li>b</li></ol></div>",
+            "div, ul, ol { display: block; padding-left: 40px; }
+             li { display: list-item; }
+             ol { list-style-type: decimal; }",
+            800.0,
+            |root| {
+                let item = &root.children[0].children[0];
+                assert_eq!(
+                    "BlockNode(li) content=(80,0 720x16) padding=(0,0,0,0) border=(0,0,0,0) margin=(0,0,0,0)
+  Marker(\"•\") content=(64,0 8x16) padding=(0,0,0,0) border=(0,0,0,0) margin=(0,0,0,0)
+    InlineNode(\"•\") content=(64,0 8x16) padding=(0,0,0,0) border=(0,0,0,0) margin=(0,0,0,0)
+  AnonymousBlock content=(80,0 720x16) padding=(0,0,0,0) border=(0,0,0,0) margin=(0,0,0,0)
+    InlineNode(\"one\") content=(80,0 24x16) padding=(0,0,0,0) border=(0,0,0,0) margin=(0,0,0,0)
+",
+                    item.to_string()
+                );
+
+                // Ordered list items are numbered
+                let second = &root.children[1].children[1];
+                assert_eq!("2.", lines(&second.children[0])[0][0].0);
+                assert_eq!(56.0, second.children[0].dimensions.content.x);
+            },
+        );
+    }
+
+    #[test]
+    fn white_space_nowrap() {
+        layout_with(
+            "<div><p>a long sentence that would wrap</p></div>",
+            "div { display: block; width: 80px; white-space: nowrap; }
+             p { display: block; }",
+            800.0,
+            |root| {
+                let anonymous = &root.children[0].children[0];
+                let lines = lines(anonymous);
+                assert_eq!(1, lines.len());
+                assert_eq!("a long sentence that would wrap", lines[0][0].0);
+                assert!(lines[0][0].1.width > 80.0);
+            },
+        );
+    }
+
+    #[test]
+    fn white_space_pre() {
+        let root = dom::Parser::parse_with_options(
+            "<pre>  one\n    two  three\nfour</pre>".to_string(),
+            ParseOptions {
+                preserve_whitespace: true,
+            },
+        );
+        let stylesheet = css::Parser::parse(
+            "pre { display: block; width: 40px; white-space: pre; }".to_string(),
+        );
+        let style_root = style_tree(&root, &stylesheet);
+        let mut layout_root = build_layout_tree(&style_root).unwrap();
+        layout_root.layout(Default::default());
+
+        let lines = lines(&layout_root.children[0]);
+        let text: Vec<&str> = lines.iter().map(|line| &*line[0].0).collect();
+        assert_eq!(vec!["  one", "    two  three", "four"], text);
+        assert_eq!(14.0 * 8.0, lines[1][0].1.width);
+    }
+
+    #[test]
+    fn tab_stops_in_pre() {
+        let root = dom::Parser::parse_with_options(
+            "<pre>a\tb\n\tc<b>xy\tz</b>\n12345678\td</pre>".to_string(),
+            ParseOptions {
+                preserve_whitespace: true,
+            },
+        );
+        let stylesheet = css::Parser::parse(
+            "pre { display: block; white-space: pre; } b { tab-size: 4; }".to_string(),
+        );
+        let style_root = style_tree(&root, &stylesheet);
+        let mut layout_root = build_layout_tree(&style_root).unwrap();
+        layout_root.layout(Default::default());
+
+        // Characters are 8px wide, so tab stops are every 64px, or 32px with `tab-size: 4`.
+        // The text after `</b>` starts with a newline, leaving an empty fragment at the end
+        // of the second line
+        let lines = lines(&layout_root.children[0]);
+        let fragments: Vec<Vec<(&str, f32)>> = lines
+            .iter()
+            .map(|line| line.iter().map(|(text, rect)| (&**text, rect.x)).collect())
+            .collect();
+        assert_eq!(
+            vec![
+                vec![("a       b", 0.0)],
+                vec![("        c", 0.0), ("xy z", 72.0), ("", 104.0)],
+                vec![("12345678        d", 0.0)],
+            ],
+            fragments
+        );
+        assert_eq!(17.0 * 8.0, lines[2][0].1.width);
+    }
+
+    #[test]
+    fn content_size_covers_all_boxes() {
+        let root = dom::Parser::parse(
+            "<div><p class=\"tall\"></p><p class=\"shifted\"></p></div>".to_string(),
+        );
+        let stylesheet = css::Parser::parse(
+            "div { display: block; }
+             p { display: block; }
+             p.tall { height: 1000px; }
+             p.shifted { margin-left: -20px; height: 10px; }"
+                .to_string(),
+        );
+        let style_root = style_tree(&root, &stylesheet);
+        let mut viewport: Dimensions = Default::default();
+        viewport.content.width = 800.0;
+        viewport.content.height = 600.0;
+
+        let layout = layout_tree(&style_root, viewport).unwrap();
+        let size = layout.content_size;
+        assert_eq!((-20.0, 0.0), (size.x, size.y));
+        assert_eq!((820.0, 1010.0), (size.width, size.height));
+        assert_eq!(1010.0, layout.root.dimensions.content.height);
+    }
+
+    #[test]
+    fn content_size_leaves_out_fixed_boxes() {
+        let root = dom::Parser::parse("<div><p></p><nav><p>menu</p></nav></div>".to_string());
+        let stylesheet = css::Parser::parse(
+            "div, p { display: block; }
+             p { height: 100px; }
+             nav { display: block; position: fixed; margin-left: 2000px; width: 3000px; height: 0px; }"
+                .to_string(),
+        );
+        let style_root = style_tree(&root, &stylesheet);
+        let mut viewport: Dimensions = Default::default();
+        viewport.content.width = 800.0;
+
+        // The fixed box and its paragraph overflow far outside the page, but don't make it bigger
+        let layout = layout_tree(&style_root, viewport).unwrap();
+        let nav = &layout.root.children[1];
+        assert_eq!(2000.0, nav.dimensions.content.x);
+        assert_eq!((0.0, 0.0), (layout.content_size.x, layout.content_size.y));
+        assert_eq!(
+            (800.0, 100.0),
+            (layout.content_size.width, layout.content_size.height)
+        );
+    }
+
+    #[test]
+    fn scroll_size_of_overflowing_content() {
+        layout_with(
+            "<div><section class=\"scroll\"><p></p></section><section><p></p></section></div>",
+            "div { display: block; }
+             section { display: block; width: 100px; height: 50px; padding: 5px; }
+             .scroll { overflow: auto; }
+             p { display: block; height: 200px; }",
+            800.0,
+            |root| {
+                // The box keeps its height, and reports the height of its content
+                let scroll = &root.children[0];
+                assert_eq!(50.0, scroll.dimensions.content.height);
+                assert_eq!((110.0, 205.0), scroll.scroll_size());
+                assert!(scroll.is_scrollable());
+
+                // Overflow that isn't scrolled is reported too
+                let visible = &root.children[1];
+                assert_eq!((110.0, 205.0), visible.scroll_size());
+                assert!(!visible.is_scrollable());
+
+                // Content that fits takes up the padding box
+                let p = &scroll.children[0];
+                assert_eq!((100.0, 200.0), p.scroll_size());
+                assert!(!p.is_scrollable());
+            },
+        );
+    }
+
+    #[test]
+    fn hidden_root_has_no_layout() {
+        let root = dom::Parser::parse("<div><p>text</p></div>".to_string());
+        let stylesheet = css::Parser::parse("div { display: none; }".to_string());
+        let style_root = style_tree(&root, &stylesheet);
+        assert!(build_layout_tree(&style_root).is_none());
+        let error = layout_tree(&style_root, Default::default()).err().unwrap();
+        assert_eq!(LayoutError::HiddenRoot, error);
+        assert_eq!("the root element has `display: none`", error.to_string());
+    }
+
+    #[test]
+    fn image_size_from_attributes() {
+        let rect = |x, y, width, height| Rect {
+            x,
+            y,
+            width,
+            height,
+        };
+        layout_with(
+            "<div>ab<img src=\"a.png\" width=\"120\" height=\"80\">c</div>",
+            "div { display: block; }",
+            800.0,
+            |root| {
+                // The image sits on the baseline, and the line grows to fit it
+                let anonymous = &root.children[0];
+                let lines = lines(anonymous);
+                assert_eq!(rect(16.0, 0.0, 120.0, 80.0), lines[0][1].1);
+                assert_eq!(("c".to_string(), rect(136.0, 68.0, 8.0, 16.0)), lines[0][2]);
+                assert_eq!(84.0, anonymous.dimensions.content.height);
+                assert_eq!(
+                    rect(16.0, 0.0, 120.0, 80.0),
+                    anonymous.children[1].dimensions.content
+                );
+            },
+        );
+
+        // CSS sizes override the attributes, and a block-level image is sized like one
+        layout_with(
+            "<div><img width=\"120\" height=\"80\"><img></div>",
+            "div { display: block; }
+             img { display: block; height: 40px; }",
+            800.0,
+            |root| {
+                let d = root.children[0].dimensions;
+                assert_eq!((120.0, 40.0), (d.content.width, d.content.height));
+                // Without a width, it keeps the placeholder's aspect ratio
+                let d = root.children[1].dimensions;
+                assert_eq!((80.0, 40.0), (d.content.width, d.content.height));
+                assert_eq!(80.0, root.dimensions.content.height);
+            },
+        );
+    }
+
+    #[test]
+    fn image_aspect_ratio() {
+        layout_with(
+            "<div><img width=\"120\"><img height=\"30\"><img></div>",
+            "div { display: block; }",
+            800.0,
+            |root| {
+                let sizes: Vec<(f32, f32)> = root.children[0]
+                    .children
+                    .iter()
+                    .map(|img| (img.dimensions.content.width, img.dimensions.content.height))
+                    .collect();
+                assert_eq!(vec![(120.0, 60.0), (60.0, 30.0), (300.0, 150.0)], sizes);
+            },
+        );
+    }
+
+    #[test]
+    fn lay_out_an_arena_document() {
+        let html = "<div class=\"note\"><p id=\"intro\">Hello <em>world</em></p>\
+                    <ol><li>one</li><li>two</li></ol><img src=\"a.png\" width=\"40\"></div>";
+        let stylesheet = css::Parser::parse(format!(
+            "{} p::first-letter {{ font-size: 2em; }} .note::after {{ content: \"!\"; }}",
+            crate::style::DEFAULT_CSS
+        ));
+        let mut viewport: Dimensions = Default::default();
+        viewport.content.width = 300.0;
+
+        // The document is styled and laid out by borrowing its nodes from the arena
+        let document = Document::parse(html).unwrap();
+        let style_root = style_tree(document.root(), &stylesheet);
+        let tree = layout_tree(&style_root, viewport).unwrap();
+
+        // Its layout is the same as the tree of nodes parsed from the same HTML
+        let root = dom::Parser::parse(html.to_string());
+        let style_root = style_tree(&root, &stylesheet);
+        let expected = layout_tree(&style_root, viewport).unwrap();
+        assert_eq!(expected.root.to_string(), tree.root.to_string());
+        assert_eq!(expected.content_size, tree.content_size);
+        assert!(tree.root.to_string().contains("Marker(\"2.\")"));
+    }
+}
@@ -1,4 +1,113 @@
+//! A toy web rendering engine. A page goes through each module in turn: `dom` and `css`
+//! parse the HTML and CSS, `style` matches the stylesheet to the document, `layout` sizes
+//! and places a box for each element, and `paint` turns the boxes into a display list and
+//! then pixels. The types and functions for each stage are re-exported here, and `render`
+//! does all of it at once.
+//!
+//! ```
+//! # #[cfg(feature = "paint")]
+//! # fn main() -> Result<(), robinson::Error> {
+//! use robinson::{
+//!     build_display_list, layout_tree, paint, style_tree, Dimensions, LayoutBox, Node,
+//!     StyleSheet, StyledNode,
+//! };
+//!
+//! let root: Node = robinson::dom::parse("<div><p>Hello</p></div>")?;
+//! let stylesheet: StyleSheet = robinson::css::parse(
+//!     "div { display: block; padding: 4px; background: #ff0000; } p { display: block; }",
+//! )?;
+//! let style_root: StyledNode = style_tree(&root, &stylesheet);
+//!
+//! let mut viewport = Dimensions::default();
+//! viewport.content.width = 80.0;
+//! viewport.content.height = 40.0;
+//! let tree = layout_tree(&style_root, viewport)?;
+//! let layout_root: &LayoutBox = &tree.root;
+//! assert_eq!(72.0, layout_root.children[0].dimensions.content.width);
+//!
+//! let display_list = build_display_list(layout_root);
+//! let canvas = paint(&display_list, viewport.content);
+//! assert_eq!(255, canvas.pixel(0, 0).r);
+//! # Ok(())
+//! # }
+//! # #[cfg(not(feature = "paint"))]
+//! # fn main() {}
+//! ```
+
+// Log a debug-level event with the `trace` feature. Without it, the arguments are still
+// type-checked, but in a branch that never runs, so they're never evaluated
+#[cfg(feature = "trace")]
+#[allow(unused_macros)] // with only the `parse` feature
+macro_rules! trace {
+    ($($arg:tt)*) => {
+        log::debug!($($arg)*)
+    };
+}
+#[cfg(not(feature = "trace"))]
+#[allow(unused_macros)]
+macro_rules! trace {
+    ($($arg:tt)*) => {
+        if false {
+            let _ = format_args!($($arg)*);
+        }
+    };
+}
+
+#[cfg(feature = "parse")]
+pub mod arena;
+#[cfg(feature = "layout")]
+pub mod ascii;
+#[cfg(feature = "parse")]
+pub mod atom;
+#[cfg(feature = "parse")]
 pub mod css;
+#[cfg(feature = "parse")]
 pub mod dom;
+#[cfg(feature = "parse")]
+mod error;
+#[cfg(feature = "http")]
+pub mod fetch;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+#[cfg(feature = "layout")]
+pub mod font;
+#[cfg(feature = "layout")]
 pub mod layout;
+#[cfg(feature = "paint")]
+pub mod paint;
+#[cfg(feature = "paint")]
+pub mod pipeline;
+#[cfg(feature = "layout")]
+pub mod snapshot;
+#[cfg(feature = "style")]
 pub mod style;
+#[cfg(feature = "paint")]
+pub mod wasm;
+#[cfg(feature = "paint")]
+pub mod window;
+
+// The main types and entry points of each stage
+#[cfg(feature = "parse")]
+pub use atom::Atom;
+#[cfg(feature = "parse")]
+pub use css::{Color, StyleSheet, Value};
+#[cfg(feature = "parse")]
+pub use dom::Node;
+#[cfg(feature = "parse")]
+pub use error::Error;
+#[cfg(feature = "layout")]
+pub use layout::{layout_tree, layout_tree_with_metrics, Dimensions, LayoutBox, LayoutTree, Rect};
+#[cfg(feature = "paint")]
+pub use paint::{
+    build_display_list, build_display_list_for_viewport, paint, to_svg, Canvas, DisplayCommand,
+    DisplayList,
+};
+#[cfg(feature = "paint")]
+pub use pipeline::{
+    render, render_to_display_list, render_with_options, PipelineStats, RenderOptions, Rendering,
+    Viewport,
+};
+#[cfg(feature = "style")]
+pub use style::{extract_stylesheets, style_tree, StyledNode};
+#[cfg(feature = "window")]
+pub use window::preview;
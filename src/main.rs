@@ -1,29 +1,1254 @@
-use std::collections::HashMap;
+use std::{
+    env, fs,
+    io::{self, IsTerminal, Read, Write},
+    path::{Path, PathBuf},
+    process, thread,
+    time::{Duration, Instant, SystemTime},
+};
 
-use robinson::dom::{elem, text};
+use robinson::{
+    ascii, dom, font::MonospaceMetrics, layout, paint, pipeline, snapshot::LayoutSnapshot, style,
+    PipelineStats, RenderOptions, Viewport,
+};
+
+const USAGE: &str = "\
+usage: robinson <input.html> [options]
+
+The input may be `-` to read the HTML from stdin, or an http(s) URL to fetch the page and
+the stylesheets it links to (with the `http` feature).
+
+options:
+  --css <file>           a stylesheet to apply, or `-` for stdin. May be repeated, with
+                         later stylesheets winning ties
+  --no-default-css       don't apply the default styles, like `display: block` for `div`
+  --no-document-css      ignore the page's `<style>` elements and linked stylesheets
+  -o, --output <file>    where to write the image, or `-` for stdout (the default)
+  -f, --format <format>  png, ppm, svg, txt (a drawing in text) or json (the layout).
+                         By default, from the output file's extension, or txt for stdout
+  --width <px>           the width of the viewport (default: 800)
+  --height <px>          the height of the viewport and the image (default: 600)
+  --full-height          make the image as tall as the page, if it's taller
+  --window               show the page in a window instead of writing an image
+  --timing               print how long each stage took, and what it worked on
+  --watch                render again whenever an input file changes, until interrupted
+  --dump-dom             print the parsed DOM tree and stop
+  --dump-style           print the style tree and stop
+  --dump-layout          print the layout tree and stop
+  --dump-display-list    print the display list and stop
+  -h, --help             print this help
+  -V, --version          print the version
+
+exit codes: 1 for bad arguments, 2 for malformed HTML or CSS or other unusable input, and
+3 when a file can't be read or written or a page can't be fetched";
+
+// What the command line asks for
+#[derive(Debug, PartialEq)]
+enum Command {
+    Render(Options),
+    Help,
+    Version,
+}
+
+#[derive(Debug, PartialEq)]
+struct Options {
+    html_path: String,
+    css_paths: Vec<String>,
+    // Whether to apply `style::DEFAULT_CSS` before the other stylesheets
+    default_css: bool,
+    // Whether to apply the page's own stylesheets, before `--css`
+    document_css: bool,
+    // The file to write the output to, or `None` for stdout
+    output: Option<String>,
+    format: String,
+    window: bool,
+    dump: Option<Dump>,
+    // The size of the viewport, in px
+    width: f32,
+    height: f32,
+    full_height: bool,
+    timing: bool,
+    watch: bool,
+}
+
+// How often watch mode checks whether the inputs have changed
+const WATCH_INTERVAL: Duration = Duration::from_millis(250);
+
+// A pipeline stage to print instead of rendering the page
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Dump {
+    Dom,
+    Style,
+    Layout,
+    DisplayList,
+}
 
 fn main() {
-    let node = elem(
-        String::from("div"),
-        HashMap::new(),
-        vec![
-            elem(
-                String::from("h1"),
-                HashMap::new(),
-                vec![text(String::from("h1 text"))],
-            ),
-            elem(
-                String::from("h2"),
-                HashMap::new(),
-                vec![text(String::from("h2 text"))],
+    if let Err(e) = run(env::args().skip(1), &mut io::stdin(), &mut io::stdout()) {
+        eprintln!("robinson: {}", e);
+        process::exit(e.exit_code());
+    }
+}
+
+// Why the command failed, which decides the exit code
+#[derive(Debug)]
+enum CliError {
+    // Bad arguments
+    Usage(String),
+    // Malformed HTML or CSS, at a line and column of the file it came from
+    Parse {
+        path: String,
+        line: usize,
+        column: usize,
+        // "HTML" or "CSS"
+        kind: &'static str,
+        message: String,
+    },
+    // An input that can't be used, like a file that isn't UTF-8
+    Input {
+        path: String,
+        message: String,
+    },
+    // A page that couldn't be fetched, or wasn't built to be
+    Fetch {
+        url: String,
+        message: String,
+    },
+    // A file, or stdin or stdout, that couldn't be read or written
+    Io {
+        path: String,
+        error: io::Error,
+    },
+}
+
+impl CliError {
+    fn exit_code(&self) -> i32 {
+        match self {
+            CliError::Usage(_) => 1,
+            CliError::Parse { .. } | CliError::Input { .. } => 2,
+            CliError::Fetch { .. } | CliError::Io { .. } => 3,
+        }
+    }
+
+    fn io(path: &str, error: io::Error) -> CliError {
+        CliError::Io {
+            path: path.to_string(),
+            error,
+        }
+    }
+
+    // A parse error in the HTML, or in one of the stylesheets given to the pipeline, or
+    // another error from it
+    fn parse(html: &CssSource, css: &[CssSource], error: robinson::Error) -> CliError {
+        let (source, kind, pos, message) = match error {
+            robinson::Error::HtmlParse(e) => (html, "HTML", e.pos, e.message),
+            robinson::Error::CssParse(i, e) => (&css[i], "CSS", e.pos, e.message),
+            robinson::Error::Layout(e) => {
+                return CliError::Input {
+                    path: html.name.clone(),
+                    message: e.to_string(),
+                }
+            }
+            robinson::Error::Io(e) => return CliError::io(&html.name, e),
+        };
+        let (line, column) = source.line_and_column(pos);
+        CliError::Parse {
+            path: source.name.clone(),
+            line,
+            column,
+            kind,
+            message,
+        }
+    }
+}
+
+impl std::fmt::Display for CliError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CliError::Usage(message) => write!(f, "{} (see --help)", message),
+            CliError::Parse {
+                path,
+                line,
+                column,
+                kind,
+                message,
+            } => write!(
+                f,
+                "{}:{}:{}: invalid {}: {}",
+                path, line, column, kind, message
             ),
-            elem(
-                String::from("h3"),
-                HashMap::new(),
-                vec![text(String::from("h3 text"))],
+            CliError::Input { path, message } => write!(f, "{}: {}", path, message),
+            CliError::Fetch { url, message } => write!(f, "{}: {}", url, message),
+            CliError::Io { path, error } => write!(f, "{}: {}", path, error),
+        }
+    }
+}
+
+// An input's text, and where it came from for error messages
+struct CssSource {
+    name: String,
+    text: String,
+    // The line and column the text starts at in the file it's in, which for a `<style>`
+    // element is the HTML file
+    start: (usize, usize),
+}
+
+impl CssSource {
+    fn new(name: &str, text: String) -> CssSource {
+        CssSource {
+            name: name.to_string(),
+            text,
+            start: (1, 1),
+        }
+    }
+
+    // The line and column of a byte offset in the text, in the file it's in
+    fn line_and_column(&self, pos: usize) -> (usize, usize) {
+        let (line, column) = line_and_column(&self.text, pos);
+        match line {
+            1 => (self.start.0, self.start.1 + column - 1),
+            _ => (self.start.0 + line - 1, column),
+        }
+    }
+}
+
+// The 1-based line and column of a byte offset in some text, counting columns in chars
+fn line_and_column(text: &str, pos: usize) -> (usize, usize) {
+    let mut pos = pos.min(text.len());
+    while !text.is_char_boundary(pos) {
+        pos -= 1;
+    }
+    let before = &text[..pos];
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    (
+        before.matches('\n').count() + 1,
+        before[line_start..].chars().count() + 1,
+    )
+}
+
+// Run the command line, not including the program name
+fn run<I: IntoIterator<Item = String>>(
+    args: I,
+    stdin: &mut dyn Read,
+    stdout: &mut dyn Write,
+) -> Result<(), CliError> {
+    let result = match parse_args(args).map_err(CliError::Usage)? {
+        Command::Help => writeln!(stdout, "{}", USAGE),
+        Command::Version => writeln!(stdout, "robinson {}", env!("CARGO_PKG_VERSION")),
+        Command::Render(options) if options.watch => watch(&options),
+        Command::Render(options) => return render(&options, stdin, stdout),
+    };
+    result.map_err(|e| CliError::io("<stdout>", e))
+}
+
+// Read the command-line arguments, not including the program name
+fn parse_args<I: IntoIterator<Item = String>>(args: I) -> Result<Command, String> {
+    let mut html_path = None;
+    let mut css_paths = Vec::new();
+    let mut default_css = true;
+    let mut document_css = true;
+    let mut output = None;
+    let mut format = None;
+    let mut window = false;
+    let (mut width, mut height) = (800.0, 600.0);
+    let mut full_height = false;
+    let mut timing = false;
+    let mut watch = false;
+    let mut dumps = Vec::new();
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(|| format!("{} needs a value", arg));
+        match &*arg {
+            "-h" | "--help" => return Ok(Command::Help),
+            "-V" | "--version" => return Ok(Command::Version),
+            "--css" => css_paths.push(value()?),
+            "--no-default-css" => default_css = false,
+            "--no-document-css" => document_css = false,
+            "-o" | "--output" => output = Some(value()?),
+            "-f" | "--format" => format = Some(value()?),
+            "--width" => width = parse_size(&arg, &value()?)?,
+            "--height" => height = parse_size(&arg, &value()?)?,
+            "--full-height" => full_height = true,
+            "--window" => window = true,
+            "--timing" => timing = true,
+            "--watch" => watch = true,
+            "--dump-dom" => dumps.push((arg, Dump::Dom)),
+            "--dump-style" => dumps.push((arg, Dump::Style)),
+            "--dump-layout" => dumps.push((arg, Dump::Layout)),
+            "--dump-display-list" => dumps.push((arg, Dump::DisplayList)),
+            _ if arg.starts_with('-') && arg != "-" => {
+                return Err(format!("unknown option: {}", arg))
+            }
+            _ if html_path.is_none() => html_path = Some(arg),
+            _ => return Err(format!("unexpected argument: {}", arg)),
+        }
+    }
+    let html_path = html_path.ok_or("missing the input HTML file")?;
+    let output = output.filter(|output| output != "-");
+    let format = match (format, &output) {
+        (Some(format), _) => format,
+        (None, None) => "txt".to_string(),
+        (None, Some(output)) => match Path::new(output).extension() {
+            Some(extension) => extension.to_string_lossy().to_ascii_lowercase(),
+            None => return Err(format!("can't tell the format of {}; use --format", output)),
+        },
+    };
+    if !matches!(&*format, "png" | "ppm" | "svg" | "txt" | "json") {
+        return Err(format!("unknown output format: {}", format));
+    }
+    if format == "json" && !cfg!(feature = "serde") {
+        return Err("json output needs the serde feature".to_string());
+    }
+    let from_stdin = css_paths.iter().filter(|&path| path == "-").count();
+    if html_path == "-" && from_stdin > 0 {
+        return Err("the HTML and the CSS can't both be read from stdin".to_string());
+    }
+    if from_stdin > 1 {
+        return Err("only one stylesheet can be read from stdin".to_string());
+    }
+    if let [(ref first, _), (ref second, _), ..] = dumps[..] {
+        return Err(format!("{} and {} can't be used together", first, second));
+    }
+    if watch {
+        if std::iter::once(&html_path)
+            .chain(&css_paths)
+            .any(|path| path == "-" || is_url(path))
+        {
+            return Err("--watch needs its inputs to be files".to_string());
+        }
+        if window {
+            return Err("--watch and --window can't be used together".to_string());
+        }
+    }
+    Ok(Command::Render(Options {
+        html_path,
+        css_paths,
+        default_css,
+        document_css,
+        output,
+        format,
+        window,
+        dump: dumps.first().map(|&(_, dump)| dump),
+        width,
+        height,
+        full_height,
+        timing,
+        watch,
+    }))
+}
+
+// Parse the value of a size option, which must be a positive number of px
+fn parse_size(option: &str, value: &str) -> Result<f32, String> {
+    match value.trim_end_matches("px").parse::<f32>() {
+        Ok(px) if px.is_finite() && px > 0.0 => Ok(px),
+        _ => Err(format!(
+            "{} must be a positive number of px, not '{}'",
+            option, value
+        )),
+    }
+}
+
+// Render a page as the options ask, reading any input named `-` from `stdin`. Dumps of
+// pipeline stages and timings are written to `stdout`
+fn render(options: &Options, stdin: &mut dyn Read, stdout: &mut dyn Write) -> Result<(), CliError> {
+    let html = match options.html_path {
+        ref url if is_url(url) => CssSource::new(url, fetch_html(url)?),
+        ref path => CssSource::new(input_name(path), read_input(path, stdin)?),
+    };
+
+    // The page's own stylesheets come first, then `--css`. The default styles come before
+    // both, if they're applied
+    let mut css_sources = Vec::new();
+    if options.document_css {
+        css_sources.extend(document_css(&options.html_path, &html)?);
+    }
+    for path in &options.css_paths {
+        css_sources.push(CssSource::new(input_name(path), read_input(path, stdin)?));
+    }
+    let css: Vec<&str> = css_sources.iter().map(|source| &*source.text).collect();
+    let parse_error = |e| CliError::parse(&html, &css_sources, e);
+    let html = &html.text;
+
+    if let Some(stage) = options.dump {
+        let text = dump(stage, html, &css, options).map_err(parse_error)?;
+        return write!(stdout, "{}", text).map_err(|e| CliError::io("<stdout>", e));
+    }
+    if options.window {
+        let size = (options.width, options.height);
+        return preview(html, &css, render_options(options), size).map_err(|e| match e {
+            robinson::Error::Io(e) => CliError::io("<window>", e),
+            e => parse_error(e),
+        });
+    }
+
+    // Only the raster formats are painted; the others are made straight from the layout
+    let (viewport, render_options) = (viewport(options), render_options(options));
+    let output = output_path(options);
+    let (bytes, stats) = match &*options.format {
+        "png" | "ppm" => {
+            let (rendering, stats) = match options.timing {
+                true => {
+                    let (rendering, stats) =
+                        pipeline::render_with_stats(html, &css, viewport, &render_options)
+                            .map_err(parse_error)?;
+                    (rendering, Some(stats))
+                }
+                false => (
+                    pipeline::render_with_options(html, &css, viewport, &render_options)
+                        .map_err(parse_error)?,
+                    None,
+                ),
+            };
+            let bytes = match &*options.format {
+                "png" => png_bytes(&rendering.canvas).map_err(|e| CliError::io(&output, e))?,
+                _ => {
+                    let mut bytes = Vec::new();
+                    rendering
+                        .canvas
+                        .write_ppm(&mut bytes)
+                        .expect("writing to a Vec");
+                    bytes
+                }
+            };
+            (bytes, stats)
+        }
+        "svg" => {
+            let (svg, mut stats) = timed_layout(
+                html,
+                &css,
+                viewport,
+                &render_options,
+                options.timing,
+                |tree| {
+                    let bounds = pipeline::paint_bounds(tree, viewport, &render_options);
+                    let display_list = paint::build_display_list_for_viewport(&tree.root, bounds);
+                    (paint::to_svg(&display_list, bounds), display_list.len())
+                },
+            )
+            .map_err(parse_error)?;
+            // Nothing is drawn in the viewport if the root has `display: none`
+            let (svg, commands) = svg.unwrap_or_else(|| {
+                let bounds = layout::Rect {
+                    x: 0.0,
+                    y: 0.0,
+                    width: viewport.width,
+                    height: viewport.height,
+                };
+                (paint::to_svg(&Vec::new(), bounds), 0)
+            });
+            if let Some(ref mut stats) = stats {
+                stats.commands = commands;
+            }
+            (svg.into_bytes(), stats)
+        }
+        // A cell of the drawing is one character of 16px monospace text
+        "txt" => {
+            let cols = (options.width / ascii::CELL_WIDTH).ceil() as usize;
+            let monospace = RenderOptions {
+                metrics: &MonospaceMetrics,
+                ..render_options
+            };
+            let (text, stats) =
+                timed_layout(html, &css, viewport, &monospace, options.timing, |tree| {
+                    ascii::render_text(&tree.root, cols)
+                })
+                .map_err(parse_error)?;
+            (text.unwrap_or_default().into_bytes(), stats)
+        }
+        _ => {
+            let (snapshot, stats) = timed_layout(
+                html,
+                &css,
+                viewport,
+                &render_options,
+                options.timing,
+                |tree| tree.root.to_snapshot(),
+            )
+            .map_err(parse_error)?;
+            (snapshot_json(snapshot)?, stats)
+        }
+    };
+
+    // Stats go to stderr when stdout has the output
+    let Some(ref path) = options.output else {
+        if matches!(&*options.format, "png" | "ppm") && io::stdout().is_terminal() {
+            eprintln!(
+                "robinson: warning: writing {} to a terminal",
+                options.format
+            );
+        }
+        stdout
+            .write_all(&bytes)
+            .map_err(|e| CliError::io("<stdout>", e))?;
+        if let Some(stats) = stats {
+            eprint!("{}", stats);
+        }
+        return Ok(());
+    };
+    // Write to a temporary file first, so the output is never left half-written
+    let temp = format!("{}.tmp", path);
+    fs::write(&temp, bytes)
+        .and_then(|()| fs::rename(&temp, path))
+        .map_err(|e| CliError::io(path, e))?;
+    match stats {
+        Some(stats) => write!(stdout, "{}", stats).map_err(|e| CliError::io("<stdout>", e)),
+        None => Ok(()),
+    }
+}
+
+// How the output is named in messages
+fn output_path(options: &Options) -> String {
+    match options.output {
+        Some(ref path) => path.clone(),
+        None => "<stdout>".to_string(),
+    }
+}
+
+// Run the pipeline up to a stage, and print that stage's result
+fn dump(
+    stage: Dump,
+    html: &str,
+    css: &[&str],
+    options: &Options,
+) -> Result<String, robinson::Error> {
+    let (viewport, render_options) = (viewport(options), render_options(options));
+    let root_node = dom::Parser::try_parse(html.to_string())?;
+    if stage == Dump::Dom {
+        return Ok(format!("{:#}", root_node));
+    }
+    let stylesheet = pipeline::stylesheet(css, &render_options)?;
+    let style_root = style::style_tree(&root_node, &stylesheet);
+    if stage == Dump::Style {
+        return Ok(style_root.to_string());
+    }
+    if stage == Dump::Layout {
+        let layout = with_layout(html, css, viewport, &render_options, |tree| {
+            tree.root.to_string()
+        });
+        // Nothing is laid out if the root has `display: none`
+        return Ok(layout?.unwrap_or_default());
+    }
+    // The display list covers the viewport, or with `--full-height` the whole page
+    let display_list = pipeline::render_to_display_list(html, css, viewport, &render_options)?;
+    Ok(display_list
+        .iter()
+        .map(|command| format!("{}\n", command))
+        .collect())
+}
+
+// Lay out the page, and make an output from the layout. Returns `None` if the root has
+// `display: none`
+fn with_layout<T>(
+    html: &str,
+    css: &[&str],
+    viewport: Viewport,
+    options: &RenderOptions,
+    output: impl FnOnce(&layout::LayoutTree) -> T,
+) -> Result<Option<T>, robinson::Error> {
+    let stylesheet = pipeline::stylesheet(css, options)?;
+    pipeline::with_layout(html, &stylesheet, viewport, options, output)
+}
+
+// Like `with_layout`, also measuring each stage if `timing` is set
+fn timed_layout<T>(
+    html: &str,
+    css: &[&str],
+    viewport: Viewport,
+    options: &RenderOptions,
+    timing: bool,
+    output: impl FnOnce(&layout::LayoutTree) -> T,
+) -> Result<(Option<T>, Option<PipelineStats>), robinson::Error> {
+    if !timing {
+        return Ok((with_layout(html, css, viewport, options, output)?, None));
+    }
+    let (output, stats) = pipeline::with_layout_and_stats(html, css, viewport, options, output)?;
+    Ok((output, Some(stats)))
+}
+
+fn viewport(options: &Options) -> Viewport {
+    Viewport {
+        width: options.width,
+        height: options.height,
+    }
+}
+
+fn render_options(options: &Options) -> RenderOptions {
+    RenderOptions {
+        default_css: options.default_css,
+        full_height: options.full_height,
+        ..RenderOptions::default()
+    }
+}
+
+// Render the page, and again each time an input file changes, until the process is
+// interrupted. A failed render is reported, and the next change is waited for as usual
+fn watch(options: &Options) -> ! {
+    let paths = std::iter::once(&options.html_path).chain(&options.css_paths);
+    let mut inputs = Inputs::new(paths.map(PathBuf::from).collect());
+    loop {
+        if let Some(status) = watch_step(options, &mut inputs, &mut io::stdout()) {
+            println!("{}", status);
+        }
+        thread::sleep(WATCH_INTERVAL);
+    }
+}
+
+// Render the page if any input has changed since the last step, returning a status line
+fn watch_step(options: &Options, inputs: &mut Inputs, stdout: &mut dyn Write) -> Option<String> {
+    if !inputs.changed() {
+        return None;
+    }
+    let start = Instant::now();
+    let status = match render(options, &mut io::empty(), stdout) {
+        Ok(()) => {
+            let ms = start.elapsed().as_secs_f64() * 1000.0;
+            format!("rendered {} in {:.1} ms", output_path(options), ms)
+        }
+        Err(e) => format!("error: {}", e),
+    };
+    Some(status)
+}
+
+// The modification times of a set of files, as last seen
+struct Inputs {
+    paths: Vec<PathBuf>,
+    // `None` for files that couldn't be read, and before the first check
+    mtimes: Vec<Option<SystemTime>>,
+    checked: bool,
+}
+
+impl Inputs {
+    fn new(paths: Vec<PathBuf>) -> Inputs {
+        let mtimes = vec![None; paths.len()];
+        Inputs {
+            paths,
+            mtimes,
+            checked: false,
+        }
+    }
+
+    // Whether any of the files has been modified, created or removed since the last check.
+    // Everything counts as changed on the first check
+    fn changed(&mut self) -> bool {
+        let mtimes: Vec<_> = self
+            .paths
+            .iter()
+            .map(|path| fs::metadata(path).and_then(|m| m.modified()).ok())
+            .collect();
+        let changed = !self.checked || mtimes != self.mtimes;
+        self.mtimes = mtimes;
+        self.checked = true;
+        changed
+    }
+}
+
+// The stylesheets of the page at `page`: its `<style>` elements, and the stylesheets it
+// links to, read relative to the page's file or fetched relative to its URL
+fn document_css(page: &str, html: &CssSource) -> Result<Vec<CssSource>, CliError> {
+    let root = dom::Parser::try_parse(html.text.clone())
+        .map_err(|e| CliError::parse(html, &[], robinson::Error::HtmlParse(e)))?;
+    // The first link that can't be loaded is reported, rather than skipped
+    let mut error = None;
+    let mut resolver = |href: &str| match read_link(page, href) {
+        Ok(text) => Some(text),
+        Err(e) => {
+            error.get_or_insert(e);
+            None
+        }
+    };
+    let sheets = style::document_stylesheets(&root, &mut resolver);
+    if let Some(error) = error {
+        return Err(error);
+    }
+
+    // `<style>` contents are found in the HTML in order, to point errors at the right line
+    let mut searched = 0;
+    let sources = sheets.into_iter().map(|(href, text)| match href {
+        Some(href) => CssSource::new(&link_name(page, &href), text),
+        None => {
+            let start = html.text[searched..].find(&text).map(|i| searched + i);
+            searched = start.unwrap_or(searched);
+            CssSource {
+                name: html.name.clone(),
+                start: line_and_column(&html.text, start.unwrap_or(0)),
+                text,
+            }
+        }
+    });
+    Ok(sources.collect())
+}
+
+// Where a stylesheet linked from a page is, as a path or URL
+fn link_name(page: &str, href: &str) -> String {
+    match Path::new(page).parent() {
+        Some(dir) if !is_url(page) && !is_url(href) && page != "-" => {
+            dir.join(href).to_string_lossy().into_owned()
+        }
+        _ => href.to_string(),
+    }
+}
+
+// Load a stylesheet linked from a page
+fn read_link(page: &str, href: &str) -> Result<String, CliError> {
+    match (is_url(page), is_url(href)) {
+        (true, _) => fetch_stylesheet(page, href),
+        (false, true) => fetch_stylesheet(href, href),
+        (false, false) => read_file(&link_name(page, href)),
+    }
+}
+
+// How an input is named in errors
+fn input_name(path: &str) -> &str {
+    match path {
+        "-" => "<stdin>",
+        _ => path,
+    }
+}
+
+// Read a UTF-8 text file, or all of `stdin` if the path is `-`
+fn read_input(path: &str, stdin: &mut dyn Read) -> Result<String, CliError> {
+    if path != "-" {
+        return read_file(path);
+    }
+    let mut bytes = Vec::new();
+    stdin
+        .read_to_end(&mut bytes)
+        .map_err(|e| CliError::io("<stdin>", e))?;
+    utf8("<stdin>", bytes)
+}
+
+// Read a UTF-8 text file
+fn read_file(path: &str) -> Result<String, CliError> {
+    utf8(path, fs::read(path).map_err(|e| CliError::io(path, e))?)
+}
+
+fn utf8(name: &str, bytes: Vec<u8>) -> Result<String, CliError> {
+    String::from_utf8(bytes).map_err(|e| CliError::Input {
+        path: name.to_string(),
+        message: format!("not valid UTF-8 (at byte {})", e.utf8_error().valid_up_to()),
+    })
+}
+
+// Whether an input names a page on the web rather than a file
+fn is_url(input: &str) -> bool {
+    let scheme = input.split_once("://").map(|(scheme, _)| scheme);
+    matches!(scheme, Some(s) if s.eq_ignore_ascii_case("http") || s.eq_ignore_ascii_case("https"))
+}
+
+// Fetch a page's HTML
+#[cfg(feature = "http")]
+fn fetch_html(url: &str) -> Result<String, CliError> {
+    robinson::fetch::Fetcher::default()
+        .fetch_document(url)
+        .map_err(fetch_error)
+}
+
+// Fetch a stylesheet linked from the page at `base`
+#[cfg(feature = "http")]
+fn fetch_stylesheet(base: &str, href: &str) -> Result<String, CliError> {
+    robinson::fetch::Fetcher::default()
+        .fetch_linked_stylesheet(base, href)
+        .map_err(fetch_error)
+}
+
+#[cfg(feature = "http")]
+fn fetch_error(e: robinson::fetch::FetchError) -> CliError {
+    CliError::Fetch {
+        url: e.url,
+        message: e.message,
+    }
+}
+
+#[cfg(not(feature = "http"))]
+fn fetch_html(url: &str) -> Result<String, CliError> {
+    Err(CliError::Fetch {
+        url: url.to_string(),
+        message: "built without http support".to_string(),
+    })
+}
+
+#[cfg(not(feature = "http"))]
+fn fetch_stylesheet(_base: &str, href: &str) -> Result<String, CliError> {
+    fetch_html(href)
+}
+
+#[cfg(feature = "png")]
+fn png_bytes(canvas: &paint::Canvas) -> io::Result<Vec<u8>> {
+    canvas.to_png_bytes()
+}
+
+#[cfg(not(feature = "png"))]
+fn png_bytes(_canvas: &paint::Canvas) -> io::Result<Vec<u8>> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "built without PNG support",
+    ))
+}
+
+// The layout as JSON, or `null` if the root has `display: none`
+#[cfg(feature = "serde")]
+fn snapshot_json(snapshot: Option<LayoutSnapshot>) -> Result<Vec<u8>, CliError> {
+    let mut json = serde_json::to_vec_pretty(&snapshot).expect("snapshots serialize");
+    json.push(b'\n');
+    Ok(json)
+}
+
+#[cfg(not(feature = "serde"))]
+fn snapshot_json(_snapshot: Option<LayoutSnapshot>) -> Result<Vec<u8>, CliError> {
+    Err(CliError::Usage(
+        "json output needs the serde feature".to_string(),
+    ))
+}
+
+// Show the page in a window of the given size, which can be resized
+#[cfg(feature = "window")]
+fn preview(
+    html: &str,
+    css: &[&str],
+    options: RenderOptions,
+    (width, height): (f32, f32),
+) -> Result<(), robinson::Error> {
+    let size = (width.ceil() as usize, height.ceil() as usize);
+    robinson::preview(html, css, options, size)
+}
+
+#[cfg(not(feature = "window"))]
+fn preview(
+    _html: &str,
+    _css: &[&str],
+    _options: RenderOptions,
+    _size: (f32, f32),
+) -> Result<(), robinson::Error> {
+    let error = io::Error::other("built without window support");
+    Err(robinson::Error::Io(error))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &str) -> Result<Command, String> {
+        parse_args(args.split_whitespace().map(String::from))
+    }
+
+    #[test]
+    fn parse_arguments() {
+        assert_eq!(
+            Ok(Command::Render(Options {
+                html_path: "page.html".to_string(),
+                css_paths: vec!["style.css".to_string()],
+                default_css: true,
+                document_css: true,
+                output: Some("page.svg".to_string()),
+                format: "svg".to_string(),
+                window: false,
+                dump: None,
+                width: 800.0,
+                height: 600.0,
+                full_height: false,
+                timing: false,
+                watch: false,
+            })),
+            args("page.html --css style.css -o page.svg --format svg")
+        );
+        assert_eq!(Ok(Command::Help), args("page.html --help"));
+        assert_eq!(Ok(Command::Version), args("-V"));
+
+        assert_eq!(
+            Err("unknown option: --bogus".to_string()),
+            args("page.html --bogus")
+        );
+        let Ok(Command::Render(options)) =
+            args("page.html --css a.css --css b.css --no-default-css")
+        else {
+            panic!("expected a render command")
+        };
+        assert_eq!(vec!["a.css", "b.css"], options.css_paths);
+        assert!(!options.default_css);
+        assert!(options.document_css);
+        assert_eq!(
+            Err("only one stylesheet can be read from stdin".to_string()),
+            args("page.html --css - --css -")
+        );
+
+        // Without `--format`, the output file's extension says, or it's text on stdout
+        let format = |command: &str| match args(command) {
+            Ok(Command::Render(options)) => (options.output, options.format),
+            result => panic!("unexpected {:?}", result),
+        };
+        assert_eq!((None, "txt".to_string()), format("page.html"));
+        assert_eq!((None, "svg".to_string()), format("page.html -o - -f svg"));
+        assert_eq!(
+            (Some("shot.PPM".to_string()), "ppm".to_string()),
+            format("page.html -o shot.PPM")
+        );
+        assert_eq!(
+            Err("unknown output format: gif".to_string()),
+            args("page.html -o page.gif")
+        );
+        assert_eq!(
+            Err("unknown output format: gif".to_string()),
+            args("page.html -f gif")
+        );
+        assert_eq!(
+            Err("--css needs a value".to_string()),
+            args("page.html --css")
+        );
+        assert_eq!(Err("unexpected argument: b".to_string()), args("a b"));
+        assert_eq!(Err("missing the input HTML file".to_string()), args(""));
+    }
+
+    #[test]
+    fn render_files() {
+        let dir = env::temp_dir().join(format!("robinson-cli-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = |name: &str| dir.join(name).to_str().unwrap().to_string();
+        fs::write(path("page.html"), "<div></div>").unwrap();
+        fs::write(
+            path("style.css"),
+            "div { display: block; height: 10px; background: #ff0000; }",
+        )
+        .unwrap();
+
+        let mut options = Options {
+            html_path: path("page.html"),
+            css_paths: vec![path("style.css")],
+            default_css: true,
+            document_css: true,
+            output: Some(path("page.svg")),
+            format: "svg".to_string(),
+            window: false,
+            dump: None,
+            width: 800.0,
+            height: 600.0,
+            full_height: false,
+            timing: false,
+            watch: false,
+        };
+        render(&options, &mut io::empty(), &mut io::sink()).unwrap();
+        let svg = fs::read_to_string(path("page.svg")).unwrap();
+        assert!(
+            svg.contains("<rect x=\"0\" y=\"0\" width=\"800\" height=\"10\" fill=\"#ff0000\"/>")
+        );
+
+        // With `--timing`, a table of the stages follows the run. The default styles are
+        // left out so that only the one rule is counted
+        options.timing = true;
+        options.default_css = false;
+        let mut stdout = Vec::new();
+        render(&options, &mut io::empty(), &mut stdout).unwrap();
+        let table = String::from_utf8(stdout).unwrap();
+        assert!(table.starts_with("stage"), "{}", table);
+        assert!(table.contains("bytes, 1 rules"), "{}", table);
+        assert!(table.contains("1 boxes"), "{}", table);
+        // Only raster formats are painted to pixels, and text is made from the layout
+        assert!(table.contains("1 commands, 0 pixels"), "{}", table);
+        options.format = "txt".to_string();
+        options.output = Some(path("page.txt"));
+        let mut stdout = Vec::new();
+        render(&options, &mut io::empty(), &mut stdout).unwrap();
+        let table = String::from_utf8(stdout).unwrap();
+        assert!(table.contains("0 commands, 0 pixels"), "{}", table);
+        options.format = "ppm".to_string();
+        options.output = Some(path("page.ppm"));
+        let mut stdout = Vec::new();
+        render(&options, &mut io::empty(), &mut stdout).unwrap();
+        let table = String::from_utf8(stdout).unwrap();
+        assert!(table.contains("1 commands, 480000 pixels"), "{}", table);
+        options.format = "svg".to_string();
+        options.output = Some(path("page.svg"));
+        options.timing = false;
+        options.default_css = true;
+
+        options.css_paths = vec![path("missing.css")];
+        let error = render(&options, &mut io::empty(), &mut io::sink()).unwrap_err();
+        assert_eq!(3, error.exit_code());
+        assert!(error
+            .to_string()
+            .starts_with(&format!("{}: ", path("missing.css"))));
+
+        // Parse errors point at the line and column in the file they're in
+        fs::write(path("style.css"), "div {\n  color: red;\n}\np > {}").unwrap();
+        options.css_paths = vec![path("style.css")];
+        let error = render(&options, &mut io::empty(), &mut io::sink()).unwrap_err();
+        assert_eq!(2, error.exit_code());
+        assert_eq!(
+            format!(
+                "{}:4:3: invalid CSS: unexpected '>' in a selector list",
+                path("style.css")
             ),
-        ],
-    );
+            error.to_string()
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn urls() {
+        assert!(is_url("https://example.com"));
+        assert!(is_url("HTTP://example.com/page.html"));
+        assert!(!is_url("page.html"));
+        assert!(!is_url("ftp://example.com"));
+        assert!(!is_url("-"));
+
+        #[cfg(not(feature = "http"))]
+        {
+            let Command::Render(options) = args("https://example.com -f svg").unwrap() else {
+                panic!("expected a render command")
+            };
+            let error = render(&options, &mut io::empty(), &mut io::sink()).unwrap_err();
+            assert_eq!(
+                "https://example.com: built without http support",
+                error.to_string()
+            );
+        }
+    }
+
+    #[test]
+    fn read_from_stdin() {
+        let dir = env::temp_dir().join(format!("robinson-stdin-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = |name: &str| dir.join(name).to_str().unwrap().to_string();
+        let html = "<div></div>";
+        let css = "div { display: block; height: 10px; background: #ff0000; }";
+        fs::write(path("page.html"), html).unwrap();
+        fs::write(path("style.css"), css).unwrap();
+        let render_svg = |html_path: &str, css_path: &str, output: &str, stdin: &str| {
+            let options = Options {
+                html_path: html_path.to_string(),
+                css_paths: vec![css_path.to_string()],
+                default_css: true,
+                document_css: true,
+                output: Some(path(output)),
+                format: "svg".to_string(),
+                window: false,
+                dump: None,
+                width: 800.0,
+                height: 600.0,
+                full_height: false,
+                timing: false,
+                watch: false,
+            };
+            render(&options, &mut stdin.as_bytes(), &mut io::sink())?;
+            Ok::<_, CliError>(fs::read_to_string(path(output)).unwrap())
+        };
+
+        // Either input can come from stdin, with the same result as reading files
+        let from_files = render_svg(&path("page.html"), &path("style.css"), "files.svg", "");
+        let html_from_stdin = render_svg("-", &path("style.css"), "html.svg", html);
+        let css_from_stdin = render_svg(&path("page.html"), "-", "css.svg", css);
+        let from_files = from_files.unwrap();
+        assert!(from_files.contains("#ff0000"));
+        assert_eq!(from_files, html_from_stdin.unwrap());
+        assert_eq!(from_files, css_from_stdin.unwrap());
+
+        let options = Options {
+            html_path: "-".to_string(),
+            css_paths: Vec::new(),
+            default_css: true,
+            document_css: true,
+            output: Some(path("bad.svg")),
+            format: "svg".to_string(),
+            window: false,
+            dump: None,
+            width: 800.0,
+            height: 600.0,
+            full_height: false,
+            timing: false,
+            watch: false,
+        };
+        let error = render(&options, &mut &b"<div>\xff</div>"[..], &mut io::sink()).unwrap_err();
+        assert_eq!("<stdin>: not valid UTF-8 (at byte 5)", error.to_string());
+        let error = render(&options, &mut &b"<div>\n<p></div>"[..], &mut io::sink()).unwrap_err();
+        assert!(error.to_string().starts_with("<stdin>:2:"), "{}", error);
+        assert_eq!(
+            Err("the HTML and the CSS can't both be read from stdin".to_string()),
+            args("- --css -")
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn dump_stages() {
+        let dir = env::temp_dir().join(format!("robinson-dump-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let css_path = dir.join("style.css").to_str().unwrap().to_string();
+        let css = "div { display: block; height: 10px; background: #ff0000; }";
+        fs::write(&css_path, css).unwrap();
+        let dump = |flag: &str| {
+            let options = match args(&format!("- --css {} {}", css_path, flag)).unwrap() {
+                Command::Render(options) => options,
+                command => panic!("unexpected {:?}", command),
+            };
+            let mut stdout = Vec::new();
+            render(
+                &options,
+                &mut &b"<div class=\"note\">hi</div>"[..],
+                &mut stdout,
+            )
+            .unwrap();
+            String::from_utf8(stdout).unwrap()
+        };
+
+        let dom = dump("--dump-dom");
+        assert!(dom.starts_with("<div class=\"note\""), "{}", dom);
+        assert!(dom.contains("\"hi\""), "{}", dom);
+        let style = dump("--dump-style");
+        assert!(style.starts_with("div.note {"), "{}", style);
+        assert!(style.contains("display: block;"), "{}", style);
+        let layout = dump("--dump-layout");
+        assert!(layout.starts_with("BlockNode(div.note)"), "{}", layout);
+        let display_list = dump("--dump-display-list");
+        assert!(
+            display_list.starts_with("solid_color color=#ff0000ff rect.x=0 rect.y=0"),
+            "{}",
+            display_list
+        );
+
+        assert_eq!(
+            Err("--dump-dom and --dump-layout can't be used together".to_string()),
+            args("page.html --dump-dom --dump-layout")
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn viewport_size() {
+        let dir = env::temp_dir().join(format!("robinson-viewport-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = |name: &str| dir.join(name).to_str().unwrap().to_string();
+        fs::write(path("page.html"), "<div><p></p></div>").unwrap();
+        fs::write(
+            path("style.css"),
+            "div { display: block; height: 1000px; }
+             p { display: block; width: 50%; height: 10px; }",
+        )
+        .unwrap();
+        let render = |options: &str| {
+            let command = format!(
+                "{} --css {} {}",
+                path("page.html"),
+                path("style.css"),
+                options
+            );
+            let Command::Render(options) = args(&command).unwrap() else {
+                panic!("expected a render command")
+            };
+            let mut stdout = Vec::new();
+            render(&options, &mut io::empty(), &mut stdout).unwrap();
+            String::from_utf8(stdout).unwrap()
+        };
+
+        // Percentages are of the viewport width
+        let narrow = render("--width 320 --dump-layout");
+        let wide = render("--width 1024 --dump-layout");
+        assert!(
+            narrow.contains("BlockNode(p) content=(0,0 160x10)"),
+            "{}",
+            narrow
+        );
+        assert!(
+            wide.contains("BlockNode(p) content=(0,0 512x10)"),
+            "{}",
+            wide
+        );
+
+        // The image is cut off at the viewport height unless it grows to fit the page
+        let image_size = |options: &str| {
+            render(&format!("-f ppm -o {} {}", path("page.ppm"), options));
+            let ppm = fs::read(path("page.ppm")).unwrap();
+            let header: Vec<_> = ppm.split(|&b| b == b'\n').take(2).collect();
+            String::from_utf8(header[1].to_vec()).unwrap()
+        };
+        assert_eq!("320 200", image_size("--width 320 --height 200"));
+        assert_eq!(
+            "320 1000",
+            image_size("--width 320 --height 200 --full-height")
+        );
+
+        for bad in ["banana", "-5", "0", "inf"] {
+            assert_eq!(
+                Err(format!(
+                    "--width must be a positive number of px, not '{}'",
+                    bad
+                )),
+                args(&format!("page.html --width {}", bad))
+            );
+        }
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn watch_for_changes() {
+        let dir = env::temp_dir().join(format!("robinson-watch-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = |name: &str| dir.join(name).to_str().unwrap().to_string();
+        fs::write(path("page.html"), "<div></div>").unwrap();
+        let write_css = |css: &str, seconds: u64| {
+            fs::write(path("style.css"), css).unwrap();
+            // Set the time explicitly, in case the file system's clock is coarse
+            let file = fs::File::options()
+                .append(true)
+                .open(path("style.css"))
+                .unwrap();
+            let time = SystemTime::UNIX_EPOCH + Duration::from_secs(seconds);
+            file.set_modified(time).unwrap();
+        };
+        write_css(
+            "div { display: block; height: 10px; background: #ff0000; }",
+            1,
+        );
+
+        let command = format!(
+            "{} --css {} -f svg -o {} --watch",
+            path("page.html"),
+            path("style.css"),
+            path("page.svg")
+        );
+        let Command::Render(options) = args(&command).unwrap() else {
+            panic!("expected a render command")
+        };
+        let mut inputs = Inputs::new(vec![path("page.html").into(), path("style.css").into()]);
+        let mut step = || watch_step(&options, &mut inputs, &mut io::sink());
+
+        // The page is rendered at first, and then only when an input changes
+        let status = step().unwrap();
+        assert!(status.starts_with(&format!("rendered {} in ", path("page.svg"))));
+        assert!(fs::read_to_string(path("page.svg"))
+            .unwrap()
+            .contains("#ff0000"));
+        assert_eq!(None, step());
+
+        // A broken stylesheet is reported, leaving the last good output in place
+        write_css("div > p { }", 2);
+        let status = step().unwrap();
+        assert!(
+            status.starts_with(&format!("error: {}:1:5: invalid CSS: ", path("style.css"))),
+            "{}",
+            status
+        );
+        assert!(fs::read_to_string(path("page.svg"))
+            .unwrap()
+            .contains("#ff0000"));
+        assert_eq!(None, step());
+
+        // Fixing it renders the page again
+        write_css(
+            "div { display: block; height: 10px; background: #0000ff; }",
+            3,
+        );
+        assert!(step().unwrap().starts_with("rendered "));
+        assert!(fs::read_to_string(path("page.svg"))
+            .unwrap()
+            .contains("#0000ff"));
+        assert!(!Path::new(&path("page.svg.tmp")).exists());
 
-    println!("{}", node);
+        assert_eq!(
+            Err("--watch needs its inputs to be files".to_string()),
+            args("- --css style.css --watch")
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
 }
@@ -0,0 +1,2300 @@
+use std::io;
+#[cfg(feature = "png")]
+use std::path::Path;
+
+use crate::{
+    css::{Color, LinearGradient, Value},
+    dom::DomNode,
+    layout::{EdgeSizes, LayoutBox, PaintStep, Rect},
+};
+
+pub type DisplayList = Vec<DisplayCommand>;
+
+const WHITE: Color = Color {
+    r: 255,
+    g: 255,
+    b: 255,
+    a: 255,
+};
+
+// The colors of the box drawn for an image that isn't loaded
+const PLACEHOLDER_FILL: Color = Color {
+    r: 221,
+    g: 221,
+    b: 221,
+    a: 255,
+};
+const PLACEHOLDER_OUTLINE: Color = Color {
+    r: 153,
+    g: 153,
+    b: 153,
+    a: 255,
+};
+
+// An image decoded to 8-bit RGBA, row by row from the top left
+#[cfg(feature = "image")]
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+    pub rgba: Vec<u8>,
+}
+
+// Loads the image for an `img` element's `src`, which is passed as written in the
+// document. Returning `None` paints the image's placeholder instead
+#[cfg(feature = "image")]
+pub type ImageLoader<'l> = dyn Fn(&str) -> Option<Image> + 'l;
+
+// Makes the command painting the image with the given `src` into a rectangle, if it can
+// be loaded
+type LoadImage<'l> = dyn Fn(&str, Rect) -> Option<DisplayCommand> + 'l;
+
+// With the `serde` feature, display lists are saved as JSON by `to_json`. Each command is
+// an object with one key, its variant name in snake_case, holding its fields: e.g.
+// `{"solid_color": [<color>, <rect>]}` or `"pop_clip"` for commands without fields
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum DisplayCommand {
+    SolidColor(Color, Rect),
+    // Fill the part of `rect` inside `shape` with its corners rounded off to `radius`
+    RoundedColor {
+        color: Color,
+        rect: Rect,
+        shape: Rect,
+        radius: f32,
+    },
+    // Fill a rectangle with a gradient, with its corners rounded off to `radius`
+    Gradient {
+        gradient: LinearGradient,
+        rect: Rect,
+        radius: f32,
+    },
+    // Draw a run of text starting at `origin`, the left end of its baseline
+    Text {
+        text: String,
+        origin: (f32, f32),
+        font_size: f32,
+        color: Color,
+    },
+    // Clip the following commands to a rectangle, until the matching `PopClip`
+    PushClip(Rect),
+    PopClip,
+    // Paint the following commands to a separate layer, until the matching `PopOpacity`,
+    // then composite that layer with the given opacity
+    PushOpacity(f32),
+    PopOpacity,
+    // Draw an image stretched over a rectangle, sampling its nearest pixel
+    #[cfg(feature = "image")]
+    Image {
+        image: Image,
+        rect: Rect,
+    },
+}
+
+pub struct Canvas {
+    pub pixels: Vec<Color>,
+    pub width: usize,
+    pub height: usize,
+    // The active clip rectangles, innermost last
+    clips: Vec<Rect>,
+    // The pixels beneath each open opacity layer, with the layer's opacity
+    layers: Vec<(Vec<Color>, f32)>,
+}
+
+// Paint a display list to an array of pixels, on a canvas big enough to cover `bounds`
+pub fn paint(display_list: &DisplayList, bounds: Rect) -> Canvas {
+    let mut canvas = Canvas::new(
+        bounds.width.ceil().max(0.0) as usize,
+        bounds.height.ceil().max(0.0) as usize,
+    );
+    for item in display_list {
+        canvas.paint_item(item);
+    }
+    canvas
+}
+
+pub fn build_display_list<'a, N: DomNode<'a>>(layout_root: &LayoutBox<'a, N>) -> DisplayList {
+    build_clipped_display_list(layout_root, None, &|_, _| None)
+}
+
+// Build a display list for the part of a layout inside `viewport`, leaving out anything
+// painted entirely outside it
+pub fn build_display_list_for_viewport<'a, N: DomNode<'a>>(
+    layout_root: &LayoutBox<'a, N>,
+    viewport: Rect,
+) -> DisplayList {
+    build_clipped_display_list(layout_root, Some(viewport), &|_, _| None)
+}
+
+// Like `build_display_list_for_viewport`, painting images with the pixels `images`
+// loads for them. It's called for each image in the viewport every time a list is built
+#[cfg(feature = "image")]
+pub fn build_display_list_with_images<'a, N: DomNode<'a>>(
+    layout_root: &LayoutBox<'a, N>,
+    viewport: Rect,
+    images: &ImageLoader,
+) -> DisplayList {
+    build_clipped_display_list(layout_root, Some(viewport), &|src, rect| {
+        images(src).map(|image| DisplayCommand::Image { image, rect })
+    })
+}
+
+// Each command is cut down to the area a box is clipped to, which is the viewport
+// intersected with the padding boxes of its `overflow: hidden` ancestors. Commands left
+// with nothing to paint are dropped
+fn build_clipped_display_list<'a, N: DomNode<'a>>(
+    layout_root: &LayoutBox<'a, N>,
+    viewport: Option<Rect>,
+    images: &LoadImage,
+) -> DisplayList {
+    let mut list = Vec::new();
+
+    // Boxes are painted back to front, switching clips whenever a step is clipped
+    // differently from the previous one. A translucent stacking context is painted as a
+    // group inside the clip of its root, and the clips open when it starts stay open until
+    // it ends, so clips and groups nest properly. Its descendants are clipped to that
+    // area anyway
+    let mut clips: Vec<Rect> = Vec::new();
+    // For each open stacking context, the number of clips open when it started, if it's
+    // painted as a group
+    let mut groups: Vec<Option<usize>> = Vec::new();
+    for (step, clip) in layout_root.paint_steps() {
+        let floor = groups.iter().rev().find_map(|&group| group).unwrap_or(0);
+        let visible = match (clip, viewport) {
+            (Some(clip), Some(viewport)) => Some(clip.intersection(viewport)),
+            (clip, viewport) => clip.or(viewport),
+        };
+        let items = match step {
+            PaintStep::PushContext(layout_box) => {
+                if layout_box.opacity < 1.0 {
+                    switch_clip(&mut list, &mut clips, floor, clip);
+                    list.push(DisplayCommand::PushOpacity(layout_box.opacity));
+                    groups.push(Some(clips.len()));
+                } else {
+                    groups.push(None);
+                }
+                continue;
+            }
+            PaintStep::PopContext => {
+                if let Some(Some(depth)) = groups.pop() {
+                    list.extend(clips.drain(depth..).map(|_| DisplayCommand::PopClip));
+                    list.push(DisplayCommand::PopOpacity);
+                }
+                continue;
+            }
+            PaintStep::Box(layout_box) => {
+                let mut items = Vec::new();
+                render_layout_box(&mut items, layout_box);
+                render_image(&mut items, layout_box, images);
+                items
+                    .into_iter()
+                    .filter_map(|item| clip_item(item, visible))
+                    .collect()
+            }
+            PaintStep::Text(layout_box) => render_text(layout_box, visible),
+        };
+        if !items.is_empty() {
+            switch_clip(&mut list, &mut clips, floor, clip);
+            list.extend(items);
+        }
+    }
+    list.extend(clips.drain(..).map(|_| DisplayCommand::PopClip));
+
+    list
+}
+
+// Make `clip` the innermost clip, closing clips opened after the first `floor` as needed
+fn switch_clip(list: &mut DisplayList, clips: &mut Vec<Rect>, floor: usize, clip: Option<Rect>) {
+    if clips.last().copied() == clip {
+        return;
+    }
+    while clips.len() > floor {
+        clips.pop();
+        list.push(DisplayCommand::PopClip);
+    }
+    if let Some(rect) = clip.filter(|&rect| clips.last() != Some(&rect)) {
+        clips.push(rect);
+        list.push(DisplayCommand::PushClip(rect));
+    }
+}
+
+// Cut a command down to the visible area, or drop it if none of it is visible. Gradients
+// are only dropped, since cutting their rectangle would move the gradient; the canvas
+// clips what's left of them
+fn clip_item(item: DisplayCommand, visible: Option<Rect>) -> Option<DisplayCommand> {
+    let visible = match visible {
+        Some(visible) => visible,
+        None => return Some(item),
+    };
+    let clip = |rect: Rect| Some(rect.intersection(visible)).filter(|r| !r.is_empty());
+    match item {
+        DisplayCommand::SolidColor(color, rect) => {
+            clip(rect).map(|rect| DisplayCommand::SolidColor(color, rect))
+        }
+        DisplayCommand::RoundedColor {
+            color,
+            rect,
+            shape,
+            radius,
+        } => clip(rect).map(|rect| DisplayCommand::RoundedColor {
+            color,
+            rect,
+            shape,
+            radius,
+        }),
+        DisplayCommand::Gradient { rect, .. } => clip(rect).map(|_| item),
+        #[cfg(feature = "image")]
+        DisplayCommand::Image { rect, .. } => clip(rect).map(|_| item),
+        item => Some(item),
+    }
+}
+
+// Paint each fragment of text in a box's lines that can be seen in the visible area, in
+// the color of its text node, with its `text-decoration` line if it has one
+fn render_text<'a, N: DomNode<'a>>(
+    layout_box: &LayoutBox<'a, N>,
+    visible: Option<Rect>,
+) -> DisplayList {
+    let black = Color {
+        r: 0,
+        g: 0,
+        b: 0,
+        a: 255,
+    };
+    let mut list = Vec::new();
+    let fragments = layout_box
+        .line_boxes
+        .iter()
+        .flat_map(|line| &line.fragments)
+        .filter(|fragment| fragment.replaced.is_none())
+        .filter(|fragment| {
+            visible.is_none_or(|visible| !fragment.rect.intersection(visible).is_empty())
+        });
+    for fragment in fragments {
+        let font_size = fragment.style.length_context.font_size;
+        let color = match fragment.style.value("color") {
+            Some(Value::ColorValue(color)) => color,
+            _ => black,
+        };
+        list.push(DisplayCommand::Text {
+            text: fragment.text.clone(),
+            origin: (fragment.rect.x, fragment.baseline),
+            font_size,
+            color,
+        });
+
+        // The line is drawn across the text's advance width, one thickness below the
+        // baseline for an underline, or through the middle of lowercase letters
+        let thickness = (font_size / 14.0).max(1.0);
+        let top = match fragment.style.value("text-decoration") {
+            Some(Value::Keyword(ref k)) if k == "underline" => fragment.baseline + thickness,
+            Some(Value::Keyword(ref k)) if k == "line-through" => {
+                fragment.baseline - font_size * 0.3
+            }
+            _ => continue,
+        };
+        let line = Rect {
+            x: fragment.rect.x,
+            y: top,
+            width: fragment.rect.width,
+            height: thickness,
+        };
+        list.extend(clip_item(DisplayCommand::SolidColor(color, line), visible));
+    }
+    list
+}
+
+fn render_layout_box<'a, N: DomNode<'a>>(list: &mut DisplayList, layout_box: &LayoutBox<'a, N>) {
+    render_background(list, layout_box);
+    render_borders(list, layout_box);
+}
+
+// Paint the content box of an `img` with the image `images` loads for its `src`, or else
+// with a placeholder: a grey box with a darker 1px outline
+fn render_image<'a, N: DomNode<'a>>(
+    list: &mut DisplayList,
+    layout_box: &LayoutBox<'a, N>,
+    images: &LoadImage,
+) {
+    let rect = layout_box.dimensions.content;
+    if layout_box.tag_name() != Some("img") || rect.is_empty() {
+        return;
+    }
+    let src = layout_box
+        .get_style_node()
+        .and_then(|style| style.attribute("src"));
+    if let Some(image) = src.and_then(|src| images(src, rect)) {
+        list.push(image);
+        return;
+    }
+
+    list.push(DisplayCommand::SolidColor(PLACEHOLDER_FILL, rect));
+    let inner = rect.expanded_by(EdgeSizes {
+        left: -1.0,
+        right: -1.0,
+        top: -1.0,
+        bottom: -1.0,
+    });
+    let outline = [
+        Rect {
+            height: 1.0,
+            ..rect
+        },
+        Rect {
+            y: rect.y + rect.height - 1.0,
+            height: 1.0,
+            ..rect
+        },
+        Rect {
+            y: inner.y,
+            width: 1.0,
+            height: inner.height,
+            ..rect
+        },
+        Rect {
+            x: rect.x + rect.width - 1.0,
+            y: inner.y,
+            width: 1.0,
+            height: inner.height,
+        },
+    ];
+    for side in outline.into_iter().filter(|side| !side.is_empty()) {
+        list.push(DisplayCommand::SolidColor(PLACEHOLDER_OUTLINE, side));
+    }
+}
+
+// Paint the background color, then any background gradient on top of it. Both fill the
+// area given by `background-clip`: the border box (the default), padding box, or content
+// box. Layers that can't be seen are skipped
+fn render_background<'a, N: DomNode<'a>>(list: &mut DisplayList, layout_box: &LayoutBox<'a, N>) {
+    let d = layout_box.dimensions;
+    let style = match layout_box.get_style_node() {
+        Some(style) => style,
+        None => return,
+    };
+    let (rect, inset) = match style.value("background-clip") {
+        Some(Value::Keyword(ref clip)) if clip == "padding-box" => (d.padding_box(), d.border),
+        Some(Value::Keyword(ref clip)) if clip == "content-box" => {
+            let mut inset = d.border;
+            inset.left += d.padding.left;
+            inset.right += d.padding.right;
+            inset.top += d.padding.top;
+            inset.bottom += d.padding.bottom;
+            (d.content, inset)
+        }
+        _ => (d.border_box(), Default::default()),
+    };
+    if rect.is_empty() {
+        return;
+    }
+    // The clip area's corners follow the inside of the border box's rounded corners
+    let radius = (border_radius(layout_box)
+        - inset.left.max(inset.right).max(inset.top).max(inset.bottom))
+    .max(0.0);
+
+    let gradient = match style.value("background-image") {
+        Some(Value::LinearGradient(gradient)) => Some(gradient),
+        _ => None,
+    };
+    // An opaque gradient covers the background color completely
+    let covered = gradient.is_some_and(|g| g.start.a == 255 && g.end.a == 255);
+    match layout_box.color("background") {
+        Some(color) if color.a > 0 && !covered => {
+            list.push(rounded_fill(color, rect, rect, radius));
+        }
+        _ => {}
+    }
+    if let Some(gradient) = gradient.filter(|g| g.start.a > 0 || g.end.a > 0) {
+        list.push(DisplayCommand::Gradient {
+            gradient,
+            rect,
+            radius,
+        });
+    }
+}
+
+// Fill part of a box's border box, rounding its corners by `border-radius` if it has one.
+// Borders are rounded by the same outer curve, so their inner edge stays square
+fn fill<'a, N: DomNode<'a>>(
+    color: Color,
+    rect: Rect,
+    layout_box: &LayoutBox<'a, N>,
+) -> DisplayCommand {
+    let shape = layout_box.dimensions.border_box();
+    rounded_fill(color, rect, shape, border_radius(layout_box))
+}
+
+// Fill the part of `rect` inside `shape`, with the shape's corners rounded to `radius`
+fn rounded_fill(color: Color, rect: Rect, shape: Rect, radius: f32) -> DisplayCommand {
+    if radius > 0.0 {
+        DisplayCommand::RoundedColor {
+            color,
+            rect,
+            shape,
+            radius,
+        }
+    } else {
+        DisplayCommand::SolidColor(color, rect)
+    }
+}
+
+// The used `border-radius` of a box, at most half the size of its border box. Corners
+// are circular, so a percentage is of the border box's shorter side
+fn border_radius<'a, N: DomNode<'a>>(layout_box: &LayoutBox<'a, N>) -> f32 {
+    let border_box = layout_box.dimensions.border_box();
+    layout_box
+        .get_style_node()
+        .and_then(|style| style.length("border-radius"))
+        .map_or(0.0, |radius| {
+            radius
+                .resolve_percentage(border_box.width.min(border_box.height))
+                .to_px()
+        })
+        .min(border_box.width / 2.0)
+        .min(border_box.height / 2.0)
+}
+
+// Paint each side of the border that has a nonzero used width. The top and bottom sides
+// span the full width of the border box, so they cover the corners; the left and right
+// sides fill the space between them
+fn render_borders<'a, N: DomNode<'a>>(list: &mut DisplayList, layout_box: &LayoutBox<'a, N>) {
+    let d = &layout_box.dimensions;
+    let border_box = d.border_box();
+    let inner_height = border_box.height - d.border.top - d.border.bottom;
+
+    let sides = [
+        (
+            "top",
+            Rect {
+                x: border_box.x,
+                y: border_box.y,
+                width: border_box.width,
+                height: d.border.top,
+            },
+        ),
+        (
+            "right",
+            Rect {
+                x: border_box.x + border_box.width - d.border.right,
+                y: border_box.y + d.border.top,
+                width: d.border.right,
+                height: inner_height,
+            },
+        ),
+        (
+            "bottom",
+            Rect {
+                x: border_box.x,
+                y: border_box.y + border_box.height - d.border.bottom,
+                width: border_box.width,
+                height: d.border.bottom,
+            },
+        ),
+        (
+            "left",
+            Rect {
+                x: border_box.x,
+                y: border_box.y + d.border.top,
+                width: d.border.left,
+                height: inner_height,
+            },
+        ),
+    ];
+    // Sides without a border paint nothing
+    for (side, rect) in sides {
+        if !rect.is_empty() {
+            list.push(fill(border_color(layout_box, side), rect, layout_box));
+        }
+    }
+}
+
+// The color of one side of a box's border: `border-<side>-color`, then `border-color`,
+// then the box's text color (black by default)
+fn border_color<'a, N: DomNode<'a>>(layout_box: &LayoutBox<'a, N>, side: &str) -> Color {
+    layout_box
+        .color(&format!("border-{}-color", side))
+        .or_else(|| layout_box.color("border-color"))
+        .or_else(|| layout_box.color("color"))
+        .unwrap_or(Color {
+            r: 0,
+            g: 0,
+            b: 0,
+            a: 255,
+        })
+}
+
+impl Canvas {
+    // Create a blank canvas
+    pub fn new(width: usize, height: usize) -> Canvas {
+        Canvas {
+            pixels: vec![WHITE; width * height],
+            width,
+            height,
+            clips: Vec::new(),
+            layers: Vec::new(),
+        }
+    }
+
+    pub fn paint_item(&mut self, item: &DisplayCommand) {
+        match *item {
+            DisplayCommand::SolidColor(color, rect) => self.fill(rect, None, |_, _| color),
+            DisplayCommand::RoundedColor {
+                color,
+                rect,
+                shape,
+                radius,
+            } => self.fill(rect, Some((shape, radius)), |_, _| color),
+            // Each pixel takes the color at its center along the gradient's axis
+            DisplayCommand::Gradient {
+                gradient,
+                rect,
+                radius,
+            } => self.fill(rect, Some((rect, radius)), |x, y| {
+                let t = match gradient.vertical {
+                    false => (x - rect.x) / rect.width,
+                    true => (y - rect.y) / rect.height,
+                };
+                interpolate(gradient.start, gradient.end, t.clamp(0.0, 1.0))
+            }),
+            DisplayCommand::Text {
+                ref text,
+                origin,
+                font_size,
+                color,
+            } => self.draw_text(text, origin, font_size, color),
+            // Nested clips are stored already intersected with their parent
+            DisplayCommand::PushClip(rect) => {
+                let rect = match self.clips.last() {
+                    Some(&clip) => rect.intersection(clip),
+                    None => rect,
+                };
+                self.clips.push(rect);
+            }
+            DisplayCommand::PopClip => {
+                self.clips.pop();
+            }
+            // Later commands paint to a transparent layer, so overlapping boxes in the
+            // group blend with each other before the group blends with what's beneath it
+            DisplayCommand::PushOpacity(opacity) => {
+                let transparent = Color { a: 0, ..WHITE };
+                let beneath = std::mem::replace(
+                    &mut self.pixels,
+                    vec![transparent; self.width * self.height],
+                );
+                self.layers.push((beneath, opacity));
+            }
+            DisplayCommand::PopOpacity => {
+                if let Some((beneath, opacity)) = self.layers.pop() {
+                    let layer = std::mem::replace(&mut self.pixels, beneath);
+                    for (pixel, color) in self.pixels.iter_mut().zip(layer) {
+                        let a = (color.a as f32 * opacity).round() as u8;
+                        *pixel = blend(*pixel, Color { a, ..color });
+                    }
+                }
+            }
+            // Each pixel takes the color of the image pixel its center falls on
+            #[cfg(feature = "image")]
+            DisplayCommand::Image { ref image, rect } => {
+                if image.width == 0
+                    || image.height == 0
+                    || image.rgba.len() < image.width * image.height * 4
+                {
+                    return;
+                }
+                self.fill(rect, None, |x, y| {
+                    let col = ((x - rect.x) / rect.width * image.width as f32) as usize;
+                    let row = ((y - rect.y) / rect.height * image.height as f32) as usize;
+                    let i =
+                        (row.min(image.height - 1) * image.width + col.min(image.width - 1)) * 4;
+                    Color {
+                        r: image.rgba[i],
+                        g: image.rgba[i + 1],
+                        b: image.rgba[i + 2],
+                        a: image.rgba[i + 3],
+                    }
+                })
+            }
+        }
+    }
+
+    // Repaint only the pixels in `dirty`, leaving the rest of the canvas as it was. The
+    // area is cleared, then the commands that overlap it are replayed, clipped to it. It's
+    // widened to whole pixels, so the pixels repainted match those of a full repaint
+    pub fn paint_partial(&mut self, display_list: &DisplayList, dirty: Rect) {
+        let (x, y) = (dirty.x.floor(), dirty.y.floor());
+        let dirty = Rect {
+            x,
+            y,
+            width: (dirty.x + dirty.width).ceil() - x,
+            height: (dirty.y + dirty.height).ceil() - y,
+        };
+        self.fill(dirty, None, |_, _| WHITE);
+        self.paint_item(&DisplayCommand::PushClip(dirty));
+        for item in display_list {
+            let outside =
+                command_bounds(item).is_some_and(|rect| rect.intersection(dirty).is_empty());
+            if !outside {
+                self.paint_item(item);
+            }
+        }
+        self.paint_item(&DisplayCommand::PopClip);
+    }
+
+    // Fill a rectangle, optionally only where it overlaps a rounded rectangle, with the
+    // color given for each pixel center. Pixels partly inside the rounded rectangle are
+    // blended by how much of them it covers
+    fn fill<F>(&mut self, rect: Rect, rounded: Option<(Rect, f32)>, color: F)
+    where
+        F: Fn(f32, f32) -> Color,
+    {
+        // Only the part of the rectangle inside the innermost clip is filled
+        let rect = match self.clips.last() {
+            Some(&clip) => rect.intersection(clip),
+            None => rect,
+        };
+
+        // Round the edges to the nearest pixel boundary, so a pixel is filled when
+        // the rectangle covers its center, then clip to the canvas boundaries
+        let x0 = rect.x.round().clamp(0.0, self.width as f32) as usize;
+        let y0 = rect.y.round().clamp(0.0, self.height as f32) as usize;
+        let x1 = (rect.x + rect.width).round().clamp(0.0, self.width as f32) as usize;
+        let y1 = (rect.y + rect.height)
+            .round()
+            .clamp(0.0, self.height as f32) as usize;
+
+        for y in y0..y1 {
+            for x in x0..x1 {
+                let (cx, cy) = (x as f32 + 0.5, y as f32 + 0.5);
+                let mut color = color(cx, cy);
+                if let Some((shape, radius)) = rounded {
+                    let coverage = rounded_coverage(x as f32, y as f32, shape, radius);
+                    if coverage == 0.0 {
+                        continue;
+                    }
+                    color.a = (color.a as f32 * coverage).round() as u8;
+                }
+                let pixel = &mut self.pixels[y * self.width + x];
+                *pixel = blend(*pixel, color);
+            }
+        }
+    }
+
+    // Blend the glyphs of the embedded font into the canvas, each pixel weighted by how
+    // much of it the glyph covers
+    #[cfg(feature = "text")]
+    fn draw_text(&mut self, text: &str, origin: (f32, f32), font_size: f32, color: Color) {
+        let font = crate::font::EmbeddedFont::get();
+        let clip = self.clips.last().copied();
+        let (mut x, baseline) = origin;
+        for c in text.chars() {
+            let (metrics, coverage) = font.rasterize(c, font_size);
+            // The bitmap's bottom row sits `ymin` pixels above the baseline
+            let left = (x + metrics.xmin as f32).round() as i64;
+            let top = (baseline - metrics.ymin as f32 - metrics.height as f32).round() as i64;
+            for (i, &alpha) in coverage.iter().enumerate() {
+                let px = left + (i % metrics.width.max(1)) as i64;
+                let py = top + (i / metrics.width.max(1)) as i64;
+                if alpha == 0
+                    || px < 0
+                    || py < 0
+                    || px >= self.width as i64
+                    || py >= self.height as i64
+                {
+                    continue;
+                }
+                let (cx, cy) = (px as f32 + 0.5, py as f32 + 0.5);
+                if clip.is_some_and(|clip| !clip.contains(cx, cy)) {
+                    continue;
+                }
+                let a = (color.a as u32 * alpha as u32 / 255) as u8;
+                let pixel = &mut self.pixels[py as usize * self.width + px as usize];
+                *pixel = blend(*pixel, Color { a, ..color });
+            }
+            x += metrics.advance_width;
+        }
+    }
+
+    // Without a font, text isn't drawn
+    #[cfg(not(feature = "text"))]
+    fn draw_text(&mut self, _text: &str, _origin: (f32, f32), _font_size: f32, _color: Color) {}
+
+    // Return the color of the pixel at (x, y)
+    pub fn pixel(&self, x: usize, y: usize) -> Color {
+        self.pixels[y * self.width + x]
+    }
+
+    // The pixels as 8-bit RGBA values, row by row
+    pub fn to_rgba(&self) -> Vec<u8> {
+        self.pixels
+            .iter()
+            .flat_map(|color| [color.r, color.g, color.b, color.a])
+            .collect()
+    }
+
+    // Encode the canvas as an RGBA PNG image
+    #[cfg(feature = "png")]
+    pub fn to_png_bytes(&self) -> io::Result<Vec<u8>> {
+        let mut bytes = Vec::new();
+        let mut encoder = png::Encoder::new(&mut bytes, self.width as u32, self.height as u32);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder.write_header()?.write_image_data(&self.to_rgba())?;
+        Ok(bytes)
+    }
+
+    // Write the canvas to a PNG file
+    #[cfg(feature = "png")]
+    pub fn save_png(&self, path: &Path) -> io::Result<()> {
+        std::fs::write(path, self.to_png_bytes()?)
+    }
+
+    // Write the canvas as a binary (P6) PPM image, one row at a time. PPM has no alpha
+    // channel, so translucent pixels are composited over white.
+    pub fn write_ppm<W: io::Write>(&self, mut w: W) -> io::Result<()> {
+        write!(w, "P6\n{} {}\n255\n", self.width, self.height)?;
+        let mut row = Vec::with_capacity(self.width * 3);
+        for y in 0..self.height {
+            row.clear();
+            for &color in &self.pixels[y * self.width..(y + 1) * self.width] {
+                let color = blend(WHITE, color);
+                row.extend_from_slice(&[color.r, color.g, color.b]);
+            }
+            w.write_all(&row)?;
+        }
+        w.flush()
+    }
+}
+
+// Render a display list as an SVG document whose view box covers `bounds`. Rectangles are
+// kept as vector shapes; clips and opacity layers become groups
+pub fn to_svg(display_list: &DisplayList, bounds: Rect) -> String {
+    let mut svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\" \
+         viewBox=\"{} {} {} {}\">\n",
+        svg_number(bounds.width),
+        svg_number(bounds.height),
+        svg_number(bounds.x),
+        svg_number(bounds.y),
+        svg_number(bounds.width),
+        svg_number(bounds.height),
+    );
+    let (mut clip_ids, mut gradient_ids) = (0, 0);
+    let mut depth = 1;
+    let mut clip_path = |svg: &mut String, depth: usize, rect: Rect, radius: f32| {
+        clip_ids += 1;
+        let indent = "  ".repeat(depth);
+        svg.push_str(&format!(
+            "{}<clipPath id=\"clip{}\">{}</clipPath>\n",
+            indent,
+            clip_ids,
+            svg_rect(rect, radius, "")
+        ));
+        clip_ids
+    };
+    for item in display_list {
+        let indent = "  ".repeat(depth);
+        match *item {
+            DisplayCommand::SolidColor(color, rect) => {
+                svg.push_str(&format!(
+                    "{}{}\n",
+                    indent,
+                    svg_rect(rect, 0.0, &svg_fill(color))
+                ));
+            }
+            DisplayCommand::RoundedColor {
+                color,
+                rect,
+                shape,
+                radius,
+            } => {
+                let fill = svg_fill(color);
+                if rect == shape {
+                    svg.push_str(&format!("{}{}\n", indent, svg_rect(rect, radius, &fill)));
+                } else {
+                    let id = clip_path(&mut svg, depth, shape, radius);
+                    let attributes = format!("{} clip-path=\"url(#clip{})\"", fill, id);
+                    svg.push_str(&format!("{}{}\n", indent, svg_rect(rect, 0.0, &attributes)));
+                }
+            }
+            DisplayCommand::Gradient {
+                gradient,
+                rect,
+                radius,
+            } => {
+                gradient_ids += 1;
+                let stop = |offset: u8, color: Color| {
+                    let mut stop = format!(
+                        "<stop offset=\"{}\" stop-color=\"#{:02x}{:02x}{:02x}\"",
+                        offset, color.r, color.g, color.b
+                    );
+                    if color.a != 255 {
+                        let opacity = svg_number(color.a as f32 / 255.0);
+                        stop.push_str(&format!(" stop-opacity=\"{}\"", opacity));
+                    }
+                    stop + "/>"
+                };
+                let (x2, y2) = if gradient.vertical { (0, 1) } else { (1, 0) };
+                svg.push_str(&format!(
+                    "{}<linearGradient id=\"gradient{}\" x2=\"{}\" y2=\"{}\">{}{}</linearGradient>\n",
+                    indent,
+                    gradient_ids,
+                    x2,
+                    y2,
+                    stop(0, gradient.start),
+                    stop(1, gradient.end)
+                ));
+                let fill = format!("fill=\"url(#gradient{})\"", gradient_ids);
+                svg.push_str(&format!("{}{}\n", indent, svg_rect(rect, radius, &fill)));
+            }
+            DisplayCommand::Text {
+                ref text,
+                origin,
+                font_size,
+                color,
+            } => {
+                let fill = svg_fill(color);
+                svg.push_str(&format!(
+                    "{}<text x=\"{}\" y=\"{}\" font-size=\"{}\" {}>{}</text>\n",
+                    indent,
+                    svg_number(origin.0),
+                    svg_number(origin.1),
+                    svg_number(font_size),
+                    fill,
+                    svg_escape(text)
+                ));
+            }
+            DisplayCommand::PushClip(rect) => {
+                let id = clip_path(&mut svg, depth, rect, 0.0);
+                svg.push_str(&format!("{}<g clip-path=\"url(#clip{})\">\n", indent, id));
+                depth += 1;
+            }
+            DisplayCommand::PushOpacity(opacity) => {
+                svg.push_str(&format!(
+                    "{}<g opacity=\"{}\">\n",
+                    indent,
+                    svg_number(opacity)
+                ));
+                depth += 1;
+            }
+            DisplayCommand::PopClip | DisplayCommand::PopOpacity => {
+                depth -= 1;
+                svg.push_str(&format!("{}</g>\n", "  ".repeat(depth)));
+            }
+            // Raster images aren't embedded, so they're drawn as a placeholder
+            #[cfg(feature = "image")]
+            DisplayCommand::Image { rect, .. } => {
+                let fill = svg_fill(PLACEHOLDER_FILL);
+                svg.push_str(&format!("{}{}\n", indent, svg_rect(rect, 0.0, &fill)));
+            }
+        }
+    }
+    svg.push_str("</svg>\n");
+    svg
+}
+
+// A `<rect>` element, with extra attributes appended
+fn svg_rect(rect: Rect, radius: f32, attributes: &str) -> String {
+    let mut element = format!(
+        "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\"",
+        svg_number(rect.x),
+        svg_number(rect.y),
+        svg_number(rect.width),
+        svg_number(rect.height)
+    );
+    if radius > 0.0 {
+        element.push_str(&format!(" rx=\"{}\"", svg_number(radius)));
+    }
+    if !attributes.is_empty() {
+        element.push(' ');
+        element.push_str(attributes);
+    }
+    element.push_str("/>");
+    element
+}
+
+fn svg_fill(color: Color) -> String {
+    let fill = format!("fill=\"#{:02x}{:02x}{:02x}\"", color.r, color.g, color.b);
+    match color.a {
+        255 => fill,
+        a => format!("{} fill-opacity=\"{}\"", fill, svg_number(a as f32 / 255.0)),
+    }
+}
+
+// Escape the characters with special meaning in XML text
+fn svg_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+// Format a number with at most two decimal places, so output is stable across small
+// floating-point differences
+fn svg_number(n: f32) -> String {
+    let s = format!("{:.2}", n);
+    let s = s.trim_end_matches('0').trim_end_matches('.');
+    match s {
+        "-0" => "0".to_string(),
+        s => s.to_string(),
+    }
+}
+
+// Write a display list as JSON, in the format described on `DisplayCommand`
+#[cfg(feature = "serde")]
+pub fn to_json(display_list: &DisplayList) -> String {
+    serde_json::to_string_pretty(display_list).expect("display lists can be serialized")
+}
+
+// Read a display list written by `to_json`
+#[cfg(feature = "serde")]
+pub fn from_json(json: &str) -> serde_json::Result<DisplayList> {
+    serde_json::from_str(json)
+}
+
+// A difference between two display lists, found by `diff`
+#[derive(Debug, Clone, PartialEq)]
+pub enum DisplayDiff {
+    // A command only in the second list, at its index there
+    Added {
+        index: usize,
+        command: DisplayCommand,
+    },
+    // A command only in the first list, at its index there
+    Removed {
+        index: usize,
+        command: DisplayCommand,
+    },
+    // A command of the same kind in both lists with different fields, at `index` in the
+    // first list and `new_index` in the second
+    Changed {
+        index: usize,
+        new_index: usize,
+        fields: Vec<FieldDelta>,
+    },
+}
+
+// One field of a changed command, e.g. `rect.y`, with its values in the first and second
+// lists
+#[derive(Debug, Clone, PartialEq)]
+pub struct FieldDelta {
+    pub field: String,
+    pub before: String,
+    pub after: String,
+}
+
+// The largest number of pairs of commands `diff` compares to line the lists up. Beyond
+// this, the commands that differ are compared in place
+const DIFF_LIMIT: usize = 1 << 22;
+
+// Compare two display lists. The commands the lists have in common are lined up, and
+// the commands between them are compared in order: commands of the same kind are
+// reported as changed, and others as removed from `a` and added in `b`
+pub fn diff(a: &DisplayList, b: &DisplayList) -> Vec<DisplayDiff> {
+    // Skip the commands the lists start and end with, which is usually most of them
+    let prefix = a.iter().zip(b).take_while(|(a, b)| a == b).count();
+    let suffix = a[prefix..]
+        .iter()
+        .rev()
+        .zip(b[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let a_rest = &a[prefix..a.len() - suffix];
+    let b_rest = &b[prefix..b.len() - suffix];
+
+    let mut diffs = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    let end = (a_rest.len(), b_rest.len());
+    for (next_i, next_j) in common_commands(a_rest, b_rest).into_iter().chain([end]) {
+        diff_between(
+            &a_rest[i..next_i],
+            &b_rest[j..next_j],
+            (prefix + i, prefix + j),
+            &mut diffs,
+        );
+        (i, j) = (next_i + 1, next_j + 1);
+    }
+    diffs
+}
+
+// The indices of the pairs of equal commands in the longest common subsequence of two
+// lists, or none if the lists are too long to compare
+fn common_commands(a: &[DisplayCommand], b: &[DisplayCommand]) -> Vec<(usize, usize)> {
+    if a.len().saturating_mul(b.len()) > DIFF_LIMIT {
+        return Vec::new();
+    }
+    // `lengths[i][j]` is the length of the longest common subsequence of `a[i..]` and `b[j..]`
+    let mut lengths = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for i in (0..a.len()).rev() {
+        for j in (0..b.len()).rev() {
+            lengths[i][j] = match a[i] == b[j] {
+                true => lengths[i + 1][j + 1] + 1,
+                false => lengths[i + 1][j].max(lengths[i][j + 1]),
+            };
+        }
+    }
+    let mut common = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() && j < b.len() {
+        if a[i] == b[j] {
+            common.push((i, j));
+            (i, j) = (i + 1, j + 1);
+        } else if lengths[i + 1][j] >= lengths[i][j + 1] {
+            i += 1;
+        } else {
+            j += 1;
+        }
+    }
+    common
+}
+
+// Compare runs of commands found between two common commands, starting at `start` in
+// each list
+fn diff_between(
+    a: &[DisplayCommand],
+    b: &[DisplayCommand],
+    start: (usize, usize),
+    diffs: &mut Vec<DisplayDiff>,
+) {
+    for n in 0..a.len().max(b.len()) {
+        let (index, new_index) = (start.0 + n, start.1 + n);
+        let removed = a.get(n).map(|command| DisplayDiff::Removed {
+            index,
+            command: command.clone(),
+        });
+        let added = b.get(n).map(|command| DisplayDiff::Added {
+            index: new_index,
+            command: command.clone(),
+        });
+        if let (Some(before), Some(after)) = (a.get(n), b.get(n)) {
+            let (before_kind, before_fields) = command_fields(before);
+            let (after_kind, after_fields) = command_fields(after);
+            if before_kind == after_kind {
+                let fields = before_fields
+                    .into_iter()
+                    .zip(after_fields)
+                    .filter(|((_, before), (_, after))| before != after)
+                    .map(|((field, before), (_, after))| FieldDelta {
+                        field,
+                        before,
+                        after,
+                    })
+                    .collect();
+                diffs.push(DisplayDiff::Changed {
+                    index,
+                    new_index,
+                    fields,
+                });
+                continue;
+            }
+        }
+        diffs.extend(removed.into_iter().chain(added));
+    }
+}
+
+// The area of the canvas that painting the commands found by `diff` changes, or `None` if
+// there are no differences. Commands that may paint anywhere, like text, clips and
+// opacity groups, make all of `bounds` dirty
+pub fn dirty_rect(
+    before: &DisplayList,
+    after: &DisplayList,
+    diffs: &[DisplayDiff],
+    bounds: Rect,
+) -> Option<Rect> {
+    let changed = diffs.iter().flat_map(|diff| match *diff {
+        DisplayDiff::Added { ref command, .. } | DisplayDiff::Removed { ref command, .. } => {
+            vec![command]
+        }
+        DisplayDiff::Changed {
+            index, new_index, ..
+        } => vec![&before[index], &after[new_index]],
+    });
+    changed
+        .map(|command| command_bounds(command).unwrap_or(bounds))
+        .reduce(Rect::union)
+        .map(|dirty| dirty.intersection(bounds))
+}
+
+// The area a command paints within, if it's limited to a rectangle
+fn command_bounds(command: &DisplayCommand) -> Option<Rect> {
+    match *command {
+        DisplayCommand::SolidColor(_, rect)
+        | DisplayCommand::RoundedColor { rect, .. }
+        | DisplayCommand::Gradient { rect, .. } => Some(rect),
+        #[cfg(feature = "image")]
+        DisplayCommand::Image { rect, .. } => Some(rect),
+        _ => None,
+    }
+}
+
+// The kind of a command, and the name and formatted value of each of its fields, with
+// rectangles, points and gradients split into their parts
+fn command_fields(command: &DisplayCommand) -> (&'static str, Vec<(String, String)>) {
+    let mut fields = Vec::new();
+    let kind = match *command {
+        DisplayCommand::SolidColor(color, rect) => {
+            push_color(&mut fields, "color", color);
+            push_rect(&mut fields, "rect", rect);
+            "solid_color"
+        }
+        DisplayCommand::RoundedColor {
+            color,
+            rect,
+            shape,
+            radius,
+        } => {
+            push_color(&mut fields, "color", color);
+            push_rect(&mut fields, "rect", rect);
+            push_rect(&mut fields, "shape", shape);
+            fields.push(("radius".to_string(), radius.to_string()));
+            "rounded_color"
+        }
+        DisplayCommand::Gradient {
+            gradient,
+            rect,
+            radius,
+        } => {
+            let vertical = gradient.vertical.to_string();
+            fields.push(("gradient.vertical".to_string(), vertical));
+            push_color(&mut fields, "gradient.start", gradient.start);
+            push_color(&mut fields, "gradient.end", gradient.end);
+            push_rect(&mut fields, "rect", rect);
+            fields.push(("radius".to_string(), radius.to_string()));
+            "gradient"
+        }
+        DisplayCommand::Text {
+            ref text,
+            origin,
+            font_size,
+            color,
+        } => {
+            fields.push(("text".to_string(), format!("{:?}", text)));
+            fields.push(("origin.x".to_string(), origin.0.to_string()));
+            fields.push(("origin.y".to_string(), origin.1.to_string()));
+            fields.push(("font_size".to_string(), font_size.to_string()));
+            push_color(&mut fields, "color", color);
+            "text"
+        }
+        DisplayCommand::PushClip(rect) => {
+            push_rect(&mut fields, "rect", rect);
+            "push_clip"
+        }
+        DisplayCommand::PopClip => "pop_clip",
+        DisplayCommand::PushOpacity(opacity) => {
+            fields.push(("opacity".to_string(), opacity.to_string()));
+            "push_opacity"
+        }
+        DisplayCommand::PopOpacity => "pop_opacity",
+        // The pixels are compared by a hash, to keep the fields short
+        #[cfg(feature = "image")]
+        DisplayCommand::Image { ref image, rect } => {
+            use std::hash::{Hash, Hasher};
+            let mut hasher = std::collections::hash_map::DefaultHasher::new();
+            image.rgba.hash(&mut hasher);
+            fields.push(("image.width".to_string(), image.width.to_string()));
+            fields.push(("image.height".to_string(), image.height.to_string()));
+            let hash = format!("{:016x}", hasher.finish());
+            fields.push(("image.rgba".to_string(), hash));
+            push_rect(&mut fields, "rect", rect);
+            "image"
+        }
+    };
+    (kind, fields)
+}
+
+// Print a command on one line as its kind and fields, e.g.
+// `solid_color color=#ff0000ff rect.x=0 rect.y=0 rect.width=10 rect.height=10`
+impl std::fmt::Display for DisplayCommand {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let (kind, fields) = command_fields(self);
+        write!(f, "{}", kind)?;
+        for (name, value) in fields {
+            write!(f, " {}={}", name, value)?;
+        }
+        Ok(())
+    }
+}
+
+fn push_color(fields: &mut Vec<(String, String)>, name: &str, color: Color) {
+    let hex = format!(
+        "#{:02x}{:02x}{:02x}{:02x}",
+        color.r, color.g, color.b, color.a
+    );
+    fields.push((name.to_string(), hex));
+}
+
+fn push_rect(fields: &mut Vec<(String, String)>, name: &str, rect: Rect) {
+    fields.push((format!("{}.x", name), rect.x.to_string()));
+    fields.push((format!("{}.y", name), rect.y.to_string()));
+    fields.push((format!("{}.width", name), rect.width.to_string()));
+    fields.push((format!("{}.height", name), rect.height.to_string()));
+}
+
+// Whether a point lies within a rectangle whose corners are quarter-circles of `radius`
+fn in_rounded_rect(x: f32, y: f32, rect: Rect, radius: f32) -> bool {
+    // The distance from the point to the rectangle inset by `radius` on each side, which
+    // is only nonzero in both directions in the corners
+    let dx = x.clamp(rect.x + radius, rect.x + rect.width - radius) - x;
+    let dy = y.clamp(rect.y + radius, rect.y + rect.height - radius) - y;
+    dx * dx + dy * dy <= radius * radius
+}
+
+// The fraction of the pixel with its top left corner at (x, y) that lies within a rounded
+// rectangle, sampled on a grid of points. The shape is convex, so a pixel with all four
+// corners inside it is covered completely
+fn rounded_coverage(x: f32, y: f32, rect: Rect, radius: f32) -> f32 {
+    const SAMPLES: usize = 4;
+    let corners = [(x, y), (x + 1.0, y), (x, y + 1.0), (x + 1.0, y + 1.0)];
+    if corners
+        .iter()
+        .all(|&(x, y)| in_rounded_rect(x, y, rect, radius))
+    {
+        return 1.0;
+    }
+    let offset = |i: usize| (i as f32 + 0.5) / SAMPLES as f32;
+    let inside = (0..SAMPLES * SAMPLES)
+        .filter(|i| {
+            in_rounded_rect(
+                x + offset(i % SAMPLES),
+                y + offset(i / SAMPLES),
+                rect,
+                radius,
+            )
+        })
+        .count();
+    inside as f32 / (SAMPLES * SAMPLES) as f32
+}
+
+// Mix two colors linearly, from `start` at t = 0 to `end` at t = 1
+fn interpolate(start: Color, end: Color, t: f32) -> Color {
+    let channel = |a: u8, b: u8| (a as f32 + (b as f32 - a as f32) * t).round() as u8;
+    Color {
+        r: channel(start.r, end.r),
+        g: channel(start.g, end.g),
+        b: channel(start.b, end.b),
+        a: channel(start.a, end.a),
+    }
+}
+
+// Composite `src` over `dst` ("source over" alpha blending)
+fn blend(dst: Color, src: Color) -> Color {
+    match src.a {
+        255 => src,
+        0 => dst,
+        _ => {
+            let (src_a, dst_a) = (src.a as f32 / 255.0, dst.a as f32 / 255.0);
+            let a = src_a + dst_a * (1.0 - src_a);
+            let channel = |s: u8, d: u8| {
+                ((s as f32 * src_a + d as f32 * dst_a * (1.0 - src_a)) / a).round() as u8
+            };
+            Color {
+                r: channel(src.r, dst.r),
+                g: channel(src.g, dst.g),
+                b: channel(src.b, dst.b),
+                a: (a * 255.0).round() as u8,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{css, dom, layout::layout_tree, style::style_tree};
+
+    fn render(html: &str, css: &str, width: f32, height: f32) -> Canvas {
+        let root = dom::Parser::parse(html.to_string());
+        let stylesheet = css::Parser::parse(css.to_string());
+        let style_root = style_tree(&root, &stylesheet);
+        let mut viewport: crate::layout::Dimensions = Default::default();
+        viewport.content.width = width;
+        viewport.content.height = height;
+        let tree = layout_tree(&style_root, viewport).unwrap();
+        paint(&build_display_list(&tree.root), viewport.content)
+    }
+
+    const RED: Color = Color {
+        r: 255,
+        g: 0,
+        b: 0,
+        a: 255,
+    };
+    const BLUE: Color = Color {
+        r: 0,
+        g: 0,
+        b: 255,
+        a: 255,
+    };
+
+    fn display_list(html: &str, css: &str, width: f32) -> DisplayList {
+        let root = dom::Parser::parse(html.to_string());
+        let stylesheet = css::Parser::parse(css.to_string());
+        let style_root = style_tree(&root, &stylesheet);
+        let mut viewport: crate::layout::Dimensions = Default::default();
+        viewport.content.width = width;
+        build_display_list(&layout_tree(&style_root, viewport).unwrap().root)
+    }
+
+    fn rect(x: f32, y: f32, width: f32, height: f32) -> Rect {
+        Rect {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    #[test]
+    fn display_list_for_parent_and_child() {
+        let list = display_list(
+            "<div><p></p></div>",
+            "div { display: block; padding: 5px; background: #0000ff; }
+             p { display: block; height: 10px; background: #ff0000;
+                 border-left-width: 2px; border-top-width: 1px; border-style: solid;
+                 border-color: #0000ff; border-width: 0; }",
+            50.0,
+        );
+        assert_eq!(
+            vec![
+                DisplayCommand::SolidColor(BLUE, rect(0.0, 0.0, 50.0, 21.0)),
+                DisplayCommand::SolidColor(RED, rect(5.0, 5.0, 40.0, 11.0)),
+                DisplayCommand::SolidColor(BLUE, rect(5.0, 5.0, 40.0, 1.0)),
+                DisplayCommand::SolidColor(BLUE, rect(5.0, 6.0, 2.0, 10.0)),
+            ],
+            list
+        );
+    }
+
+    #[test]
+    fn border_sides() {
+        // A uniform border: the top and bottom sides cover the corners
+        let list = display_list(
+            "<div></div>",
+            "div { display: block; height: 6px; border: 2px solid red; }",
+            10.0,
+        );
+        assert_eq!(
+            vec![
+                DisplayCommand::SolidColor(RED, rect(0.0, 0.0, 10.0, 2.0)),
+                DisplayCommand::SolidColor(RED, rect(8.0, 2.0, 2.0, 6.0)),
+                DisplayCommand::SolidColor(RED, rect(0.0, 8.0, 10.0, 2.0)),
+                DisplayCommand::SolidColor(RED, rect(0.0, 2.0, 2.0, 6.0)),
+            ],
+            list
+        );
+
+        // Per-side widths and colors, falling back to `border-color`
+        let list = display_list(
+            "<div></div>",
+            "div { display: block; height: 6px; border-style: solid; border-color: red;
+                   border-top-width: 1px; border-right-width: 2px; border-bottom-width: 3px;
+                   border-left-width: 4px; border-left-color: blue; }",
+            20.0,
+        );
+        assert_eq!(
+            vec![
+                DisplayCommand::SolidColor(RED, rect(0.0, 0.0, 20.0, 1.0)),
+                DisplayCommand::SolidColor(RED, rect(18.0, 1.0, 2.0, 6.0)),
+                DisplayCommand::SolidColor(RED, rect(0.0, 7.0, 20.0, 3.0)),
+                DisplayCommand::SolidColor(BLUE, rect(0.0, 1.0, 4.0, 6.0)),
+            ],
+            list
+        );
+
+        // Only the left side is set; its color falls back to the text color
+        let list = display_list(
+            "<div></div>",
+            "div { display: block; height: 6px; color: blue; border-left: 3px solid; }",
+            10.0,
+        );
+        assert_eq!(
+            vec![DisplayCommand::SolidColor(BLUE, rect(0.0, 0.0, 3.0, 6.0))],
+            list
+        );
+
+        // Sides with `border-style: none` paint nothing, whatever their width
+        let list = display_list(
+            "<div></div>",
+            "div { display: block; height: 6px; border: 2px red; }",
+            10.0,
+        );
+        assert!(list.is_empty());
+    }
+
+    #[test]
+    fn z_index_paint_order() {
+        let list = display_list(
+            "<div><p class=\"high\"></p><p class=\"low\"></p><p class=\"auto\"></p>\
+             <p class=\"flow\"></p></div>",
+            "div, p { display: block; }
+             p { height: 10px; margin-top: -5px; }
+             p.high { position: relative; z-index: 2; background: #ff0000; }
+             p.low { position: relative; z-index: 1; background: #0000ff; }
+             p.auto { position: relative; background: #00ff00; }
+             p.flow { background: #000000; }",
+            50.0,
+        );
+        let colors: Vec<Color> = list
+            .iter()
+            .map(|command| match *command {
+                DisplayCommand::SolidColor(color, _) => color,
+                _ => panic!("unexpected command {:?}", command),
+            })
+            .collect();
+        // In-flow content first, then positioned boxes: `auto` at level 0, then by z-index
+        let black = Color {
+            r: 0,
+            g: 0,
+            b: 0,
+            a: 255,
+        };
+        let green = Color {
+            r: 0,
+            g: 255,
+            b: 0,
+            a: 255,
+        };
+        assert_eq!(vec![black, green, BLUE, RED], colors);
+    }
+
+    #[test]
+    fn stacking_context_paint_order() {
+        let list = display_list(
+            "<div><p class=\"a\">A</p><p class=\"b\">B</p><p class=\"c\">C</p>\
+             <p class=\"d\">D</p></div>",
+            "div { display: block; background: #eeeeee; }
+             p { display: block; height: 16px; margin-top: -8px; }
+             p.a { margin-top: 0; background: #ff0000; }
+             p.b { position: relative; background: #0000ff; }
+             p.c { background: #00ff00; }
+             p.d { position: relative; z-index: -1; background: #000000; }",
+            100.0,
+        );
+        let color = |r, g, b| Color { r, g, b, a: 255 };
+        let text = |text: &str, y| DisplayCommand::Text {
+            text: text.to_string(),
+            origin: (0.0, y),
+            font_size: 16.0,
+            color: color(0, 0, 0),
+        };
+        let fill = |c, y| DisplayCommand::SolidColor(c, rect(0.0, y, 100.0, 16.0));
+        assert_eq!(
+            vec![
+                // The root's background
+                DisplayCommand::SolidColor(color(238, 238, 238), rect(0.0, 0.0, 100.0, 40.0)),
+                // Contexts with negative z-index, each painted whole
+                fill(color(0, 0, 0), 24.0),
+                text("D", 36.0),
+                // The backgrounds of in-flow boxes, then all of their text
+                fill(RED, 0.0),
+                fill(color(0, 255, 0), 16.0),
+                text("A", 12.0),
+                text("C", 28.0),
+                // Positioned boxes over the text of earlier siblings
+                fill(BLUE, 8.0),
+                text("B", 20.0),
+            ],
+            list
+        );
+    }
+
+    #[test]
+    fn transparent_background_paints_nothing() {
+        let list = display_list(
+            "<div><p>text</p></div>",
+            "div, p { display: block; background: transparent; }",
+            50.0,
+        );
+        // Only the text is painted
+        assert_eq!(1, list.len());
+        assert!(matches!(list[0], DisplayCommand::Text { .. }));
+    }
+
+    #[test]
+    fn background_clip() {
+        let background = |clip: &str| {
+            let css = format!(
+                "div {{ display: block; height: 10px; padding: 3px; border-width: 2px;
+                        border-style: solid; border-color: #0000ff; background: #ff0000;
+                        {} }}",
+                clip
+            );
+            display_list("<div></div>", &css, 30.0).remove(0)
+        };
+        let border_box = DisplayCommand::SolidColor(RED, rect(0.0, 0.0, 30.0, 20.0));
+        assert_eq!(border_box, background(""));
+        assert_eq!(border_box, background("background-clip: border-box;"));
+        assert_eq!(
+            DisplayCommand::SolidColor(RED, rect(2.0, 2.0, 26.0, 16.0)),
+            background("background-clip: padding-box;")
+        );
+        assert_eq!(
+            DisplayCommand::SolidColor(RED, rect(5.0, 5.0, 20.0, 10.0)),
+            background("background-clip: content-box;")
+        );
+    }
+
+    #[test]
+    fn invisible_backgrounds_are_skipped() {
+        // A fully transparent color, and a color under an opaque gradient
+        let list = display_list(
+            "<div><p></p></div>",
+            "div { display: block; height: 10px; background: #ff000000; }
+             p { display: block; height: 5px; background: #ff0000;
+                 background-image: linear-gradient(to right, #ff0000, #0000ff); }",
+            10.0,
+        );
+        assert_eq!(1, list.len());
+        assert!(matches!(list[0], DisplayCommand::Gradient { .. }));
+
+        // A translucent gradient leaves the color beneath it visible
+        let list = display_list(
+            "<div></div>",
+            "div { display: block; height: 10px; background: #ff0000;
+                   background-image: linear-gradient(to right, #ff000080, #0000ff); }",
+            10.0,
+        );
+        assert_eq!(2, list.len());
+    }
+
+    #[test]
+    fn text_commands_follow_inline_backgrounds() {
+        let list = display_list(
+            "<p>ab <span>cd</span></p>",
+            "p { display: block; color: #ff0000; }
+             span { background: #0000ff; font-size: 32px; }",
+            100.0,
+        );
+        // Both fragments share a baseline, 0.75 of the larger font size down. The space
+        // before the span is as wide as a space in its font
+        assert_eq!(
+            vec![
+                DisplayCommand::SolidColor(BLUE, rect(32.0, 0.0, 32.0, 32.0)),
+                DisplayCommand::Text {
+                    text: "ab".to_string(),
+                    origin: (0.0, 24.0),
+                    font_size: 16.0,
+                    color: RED,
+                },
+                DisplayCommand::Text {
+                    text: "cd".to_string(),
+                    origin: (32.0, 24.0),
+                    font_size: 32.0,
+                    color: RED,
+                },
+            ],
+            list
+        );
+    }
+
+    #[test]
+    fn text_in_flex_containers() {
+        let list = display_list(
+            "<div>hello <span>x</span></div>",
+            "div { display: flex; color: #ff0000; }",
+            200.0,
+        );
+        // The text is wrapped in an anonymous flex item, and the span is the next item
+        let texts: Vec<(&str, (f32, f32))> = list
+            .iter()
+            .filter_map(|item| match item {
+                DisplayCommand::Text { text, origin, .. } => Some((text.as_str(), *origin)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(vec![("hello", (0.0, 12.0)), ("x", (40.0, 12.0))], texts);
+    }
+
+    #[test]
+    fn text_decoration_lines() {
+        let list = display_list(
+            "<p><a>ab <em>cd</em></a><s>ef</s></p>",
+            "p { display: block; font-size: 28px; }
+             a { text-decoration: underline; }
+             em { text-decoration: none; }
+             s { text-decoration: line-through; color: #0000ff; }",
+            200.0,
+        );
+        // A link is underlined, except where a descendant sets `none`. The line
+        // is `font-size / 14` thick, and spans the run's advance width
+        let black = Color {
+            r: 0,
+            g: 0,
+            b: 0,
+            a: 255,
+        };
+        let decorations: Vec<&DisplayCommand> = list
+            .iter()
+            .filter(|item| matches!(item, DisplayCommand::SolidColor(..)))
+            .collect();
+        assert_eq!(
+            vec![
+                &DisplayCommand::SolidColor(black, rect(0.0, 23.0, 28.0, 2.0)),
+                &DisplayCommand::SolidColor(BLUE, rect(70.0, 21.0 - 28.0 * 0.3, 28.0, 2.0)),
+            ],
+            decorations
+        );
+        // Each line follows its run's text
+        assert!(matches!(
+            &list[..2],
+            [DisplayCommand::Text { text, .. }, DisplayCommand::SolidColor(..)] if text == "ab"
+        ));
+    }
+
+    #[cfg(feature = "text")]
+    #[test]
+    fn paint_text_with_embedded_font() {
+        use crate::{font::EmbeddedFont, layout::layout_tree_with_metrics};
+
+        let root = dom::Parser::parse("<p>Hi</p>".to_string());
+        let stylesheet = css::Parser::parse("p { display: block; font-size: 48px; }".to_string());
+        let style_root = style_tree(&root, &stylesheet);
+        let mut viewport: crate::layout::Dimensions = Default::default();
+        viewport.content.width = 200.0;
+        viewport.content.height = 100.0;
+        let tree = layout_tree_with_metrics(&style_root, viewport, EmbeddedFont::get()).unwrap();
+        let canvas = paint(&build_display_list(&tree.root), viewport.content);
+
+        let anonymous = &tree.root.children[0];
+        let line = anonymous.line_boxes[0].rect;
+        let text = anonymous.line_boxes[0].fragments[0].rect;
+        let dark = |x: usize, y: usize| canvas.pixel(x, y).r < 128;
+        for y in 0..canvas.height {
+            for x in 0..canvas.width {
+                let (cx, cy) = (x as f32 + 0.5, y as f32 + 0.5);
+                // Glyphs stay within their line, and within the measured width of the text
+                if !line.contains(cx, cy) || cx > text.x + text.width {
+                    assert_eq!(WHITE, canvas.pixel(x, y), "pixel ({}, {})", x, y);
+                }
+            }
+        }
+        // Each letter has dark pixels within its half of the text
+        let half = (text.width / 2.0) as usize;
+        let inked = |xs: std::ops::Range<usize>| {
+            xs.flat_map(|x| (0..line.height as usize).map(move |y| (x, y)))
+                .filter(|&(x, y)| dark(x, y))
+                .count()
+        };
+        assert!(inked(0..half) > 20);
+        assert!(inked(half..2 * half) > 20);
+    }
+
+    #[test]
+    fn rasterize_rect() {
+        let list = vec![DisplayCommand::SolidColor(RED, rect(2.0, 2.0, 3.0, 3.0))];
+        let canvas = paint(&list, rect(0.0, 0.0, 10.0, 10.0));
+        assert_eq!((10, 10), (canvas.width, canvas.height));
+        for y in 0..10 {
+            for x in 0..10 {
+                let inside = (2..5).contains(&x) && (2..5).contains(&y);
+                let expected = if inside { RED } else { WHITE };
+                assert_eq!(expected, canvas.pixel(x, y), "pixel ({}, {})", x, y);
+            }
+        }
+    }
+
+    #[test]
+    fn rasterize_rounds_edges_and_clamps_to_canvas() {
+        let list = vec![
+            // Covers the centers of pixels 1 to 3 horizontally
+            DisplayCommand::SolidColor(BLUE, rect(1.4, 0.0, 2.2, 1.0)),
+            // Partly and fully outside the canvas
+            DisplayCommand::SolidColor(RED, rect(-5.0, 8.0, 7.0, 10.0)),
+            DisplayCommand::SolidColor(RED, rect(20.0, 20.0, 5.0, 5.0)),
+            DisplayCommand::SolidColor(RED, rect(-20.0, -20.0, 5.0, 5.0)),
+        ];
+        let canvas = paint(&list, rect(0.0, 0.0, 10.0, 10.0));
+        let row: Vec<Color> = (0..5).map(|x| canvas.pixel(x, 0)).collect();
+        assert_eq!(vec![WHITE, BLUE, BLUE, BLUE, WHITE], row);
+        assert_eq!(RED, canvas.pixel(0, 9));
+        assert_eq!(RED, canvas.pixel(1, 8));
+        assert_eq!(WHITE, canvas.pixel(2, 9));
+        assert_eq!(WHITE, canvas.pixel(9, 9));
+    }
+
+    #[test]
+    fn blend_translucent_colors() {
+        let half_red = Color {
+            r: 255,
+            g: 0,
+            b: 0,
+            a: 128,
+        };
+        let list = vec![
+            DisplayCommand::SolidColor(half_red, rect(0.0, 0.0, 1.0, 1.0)),
+            DisplayCommand::SolidColor(Color { a: 0, ..BLUE }, rect(0.0, 0.0, 1.0, 1.0)),
+        ];
+        let canvas = paint(&list, rect(0.0, 0.0, 1.0, 1.0));
+        assert_eq!(
+            Color {
+                r: 255,
+                g: 127,
+                b: 127,
+                a: 255
+            },
+            canvas.pixel(0, 0)
+        );
+    }
+
+    #[test]
+    fn blend_exact_values() {
+        let black = Color {
+            r: 0,
+            g: 0,
+            b: 0,
+            a: 255,
+        };
+        let grey = |v: u8| Color {
+            r: v,
+            g: v,
+            b: v,
+            a: 255,
+        };
+        assert_eq!(grey(127), blend(WHITE, Color { a: 128, ..black }));
+        assert_eq!(grey(191), blend(WHITE, Color { a: 64, ..black }));
+
+        // Over a previously painted color, and stacked twice over white
+        let painted = blend(BLUE, Color { a: 128, ..RED });
+        assert_eq!(
+            Color {
+                r: 128,
+                g: 0,
+                b: 127,
+                a: 255
+            },
+            painted
+        );
+        let quarter = Color { a: 64, ..black };
+        assert_eq!(grey(143), blend(blend(WHITE, quarter), quarter));
+        assert_eq!(BLUE, blend(RED, BLUE));
+    }
+
+    #[cfg(feature = "png")]
+    #[test]
+    fn png_round_trip() {
+        let mut canvas = Canvas::new(3, 2);
+        canvas.pixels[1] = RED;
+        canvas.pixels[5] = Color {
+            r: 1,
+            g: 2,
+            b: 3,
+            a: 4,
+        };
+
+        let bytes = canvas.to_png_bytes().unwrap();
+        let mut reader = png::Decoder::new(&bytes[..]).read_info().unwrap();
+        let mut data = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut data).unwrap();
+        assert_eq!((3, 2), (info.width, info.height));
+        assert_eq!(png::ColorType::Rgba, info.color_type);
+        let pixels: Vec<Color> = data
+            .chunks(4)
+            .map(|p| Color {
+                r: p[0],
+                g: p[1],
+                b: p[2],
+                a: p[3],
+            })
+            .collect();
+        assert_eq!(canvas.pixels, pixels);
+    }
+
+    #[test]
+    fn border_radius_rounds_corners() {
+        let circle = "div { display: block; height: 20px; background: #ff0000;
+                            border-radius: 10px; }";
+        let canvas = render("<div></div>", circle, 20.0, 20.0);
+        // Pixels near each corner fall outside the quarter-circles
+        for (x, y) in [(0, 0), (1, 1), (19, 0), (0, 19), (19, 19), (18, 18), (5, 0)] {
+            assert_eq!(WHITE, canvas.pixel(x, y), "pixel ({}, {})", x, y);
+        }
+        // Pixels inside the curve are filled
+        for (x, y) in [(10, 10), (5, 5), (14, 14), (10, 1), (1, 10), (18, 9)] {
+            assert_eq!(RED, canvas.pixel(x, y), "pixel ({}, {})", x, y);
+        }
+        // Pixels the curve passes through are blended by how much of them it covers
+        for (x, y) in [(3, 2), (4, 1), (7, 0), (16, 17)] {
+            let pixel = canvas.pixel(x, y);
+            assert!(pixel.g > 0 && pixel.g < 255, "pixel ({}, {})", x, y);
+        }
+        // 10 of the 16 samples in this pixel are inside the curve
+        assert_eq!(
+            Color {
+                r: 255,
+                g: 96,
+                b: 96,
+                a: 255
+            },
+            canvas.pixel(2, 3)
+        );
+
+        // Percentages are of the shorter side, and radii past half the box are clamped
+        let percent = render("<div></div>", &circle.replace("10px", "50%"), 20.0, 20.0);
+        assert_eq!(canvas.pixels, percent.pixels);
+        let clamped = render("<div></div>", &circle.replace("10px", "40px"), 20.0, 20.0);
+        assert_eq!(canvas.pixels, clamped.pixels);
+
+        // Borders are clipped to the same curve
+        let canvas = render(
+            "<div></div>",
+            "div { display: block; height: 6px; border-width: 2px; border-style: solid;
+                   border-color: #0000ff; border-radius: 5px; }",
+            10.0,
+            10.0,
+        );
+        assert_eq!(WHITE, canvas.pixel(0, 0));
+        assert_eq!(BLUE, canvas.pixel(5, 1));
+        assert_eq!(BLUE, canvas.pixel(1, 2));
+    }
+
+    #[test]
+    fn linear_gradient_background() {
+        let render_gradient = |gradient: &str| {
+            render(
+                "<div></div>",
+                &format!(
+                    "div {{ display: block; height: 11px; background: #00ff00;
+                            background-image: {}; }}",
+                    gradient
+                ),
+                11.0,
+                11.0,
+            )
+        };
+        let purple = Color {
+            r: 128,
+            g: 0,
+            b: 128,
+            a: 255,
+        };
+
+        let canvas = render_gradient("linear-gradient(to right, #ff0000, #0000ff)");
+        assert_eq!(purple, canvas.pixel(5, 5));
+        assert_eq!(
+            Color {
+                r: 243,
+                g: 0,
+                b: 12,
+                a: 255
+            },
+            canvas.pixel(0, 10)
+        );
+
+        let canvas = render_gradient("linear-gradient(to top, #ff0000, #0000ff)");
+        assert_eq!(purple, canvas.pixel(5, 5));
+        assert_eq!(canvas.pixel(0, 0), canvas.pixel(10, 0));
+        assert!(canvas.pixel(0, 0).b > canvas.pixel(0, 10).b);
+
+        // A translucent gradient shows the background color beneath it
+        let canvas = render_gradient("linear-gradient(to bottom, #ff000000, #ff000000)");
+        assert_eq!(
+            Color {
+                r: 0,
+                g: 255,
+                b: 0,
+                a: 255
+            },
+            canvas.pixel(5, 5)
+        );
+    }
+
+    #[test]
+    fn group_opacity() {
+        let html = "<div><p></p></div>";
+        let group = "div { display: block; height: 4px; background: #ff0000; opacity: 0.5; }
+                     p { display: block; height: 2px; background: #0000ff; }";
+        assert_eq!(
+            vec![
+                DisplayCommand::PushOpacity(0.5),
+                DisplayCommand::SolidColor(RED, rect(0.0, 0.0, 4.0, 4.0)),
+                DisplayCommand::SolidColor(BLUE, rect(0.0, 0.0, 4.0, 2.0)),
+                DisplayCommand::PopOpacity,
+            ],
+            display_list(html, group, 4.0)
+        );
+
+        // The child covers its parent within the group, then the group is blended with
+        // the white canvas
+        let canvas = render(html, group, 4.0, 4.0);
+        let half_blue = Color {
+            r: 127,
+            g: 127,
+            b: 255,
+            a: 255,
+        };
+        let half_red = Color {
+            r: 255,
+            g: 127,
+            b: 127,
+            a: 255,
+        };
+        assert_eq!(half_blue, canvas.pixel(0, 0));
+        assert_eq!(half_red, canvas.pixel(0, 3));
+
+        // With per-box alpha, the parent shows through the child instead
+        let per_box = "div { display: block; height: 4px; background: #ff000080; }
+                       p { display: block; height: 2px; background: #0000ff80; }";
+        let canvas = render(html, per_box, 4.0, 4.0);
+        assert_eq!(
+            Color {
+                r: 127,
+                g: 63,
+                b: 191,
+                a: 255
+            },
+            canvas.pixel(0, 0)
+        );
+        assert_eq!(half_red, canvas.pixel(0, 3));
+    }
+
+    #[test]
+    fn svg_output() {
+        let list = display_list(
+            "<div><p></p></div>",
+            "div { display: block; padding: 1.5px; background: #ff000080; overflow: hidden;
+                   border-radius: 2px; }
+             p { display: block; height: 10px; border-width: 1px; border-style: solid;
+                 border-color: #0000ff; opacity: 0.25; }",
+            20.0,
+        );
+        let svg = to_svg(&list, rect(0.0, 0.0, 20.0, 15.0));
+        assert_eq!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"20\" height=\"15\" \
+             viewBox=\"0 0 20 15\">
+  <rect x=\"0\" y=\"0\" width=\"20\" height=\"15\" rx=\"2\" fill=\"#ff0000\" \
+  fill-opacity=\"0.5\"/>
+  <clipPath id=\"clip1\"><rect x=\"0\" y=\"0\" width=\"20\" height=\"15\"/></clipPath>
+  <g clip-path=\"url(#clip1)\">
+    <g opacity=\"0.25\">
+      <rect x=\"1.5\" y=\"1.5\" width=\"17\" height=\"1\" fill=\"#0000ff\"/>
+      <rect x=\"17.5\" y=\"2.5\" width=\"1\" height=\"10\" fill=\"#0000ff\"/>
+      <rect x=\"1.5\" y=\"12.5\" width=\"17\" height=\"1\" fill=\"#0000ff\"/>
+      <rect x=\"1.5\" y=\"2.5\" width=\"1\" height=\"10\" fill=\"#0000ff\"/>
+    </g>
+  </g>
+</svg>
+",
+            svg
+        );
+        assert_well_formed(&svg);
+    }
+
+    // Check that every tag in a simple XML document is closed in the right order
+    fn assert_well_formed(xml: &str) {
+        let mut open = Vec::new();
+        let mut rest = xml;
+        while let Some(start) = rest.find('<') {
+            let end = start + rest[start..].find('>').expect("unterminated tag");
+            let tag = &rest[start + 1..end];
+            assert_eq!(
+                0,
+                tag.matches('"').count() % 2,
+                "unbalanced quotes in <{}>",
+                tag
+            );
+            if let Some(name) = tag.strip_prefix('/') {
+                assert_eq!(Some(name), open.pop(), "mismatched </{}>", name);
+            } else if !tag.ends_with('/') {
+                open.push(tag.split_whitespace().next().expect("empty tag"));
+            }
+            rest = &rest[end + 1..];
+        }
+        assert!(open.is_empty(), "unclosed tags: {:?}", open);
+    }
+
+    #[test]
+    fn ppm_output() {
+        let canvas = render(
+            "<div><p></p></div>",
+            "div { display: block; height: 4px; background: #0000ff80; }
+             p { display: block; width: 2px; height: 2px; background: #ff0000; }",
+            4.0,
+            5.0,
+        );
+        let mut bytes = Vec::new();
+        canvas.write_ppm(&mut bytes).unwrap();
+
+        let header = b"P6\n4 5\n255\n";
+        assert_eq!(header, &bytes[..header.len()]);
+        let pixels = &bytes[header.len()..];
+        assert_eq!(4 * 5 * 3, pixels.len());
+        let sample = |x: usize, y: usize| {
+            let i = (y * 4 + x) * 3;
+            [pixels[i], pixels[i + 1], pixels[i + 2]]
+        };
+        assert_eq!([255, 0, 0], sample(0, 0));
+        assert_eq!([255, 0, 0], sample(1, 1));
+        assert_eq!([127, 127, 255], sample(3, 0));
+        assert_eq!([127, 127, 255], sample(2, 3));
+        assert_eq!([255, 255, 255], sample(0, 4));
+    }
+
+    #[test]
+    fn paint_backgrounds_and_borders() {
+        let canvas = render(
+            "<div></div>",
+            "div { display: block; width: 10px; height: 10px; background: #0000ff;
+                   border-width: 2px; border-style: solid; border-color: red; }",
+            20.0,
+            20.0,
+        );
+        assert_eq!(RED, canvas.pixel(0, 0));
+        assert_eq!(RED, canvas.pixel(13, 13));
+        assert_eq!(BLUE, canvas.pixel(2, 2));
+        assert_eq!(BLUE, canvas.pixel(11, 11));
+        assert_eq!(WHITE, canvas.pixel(14, 14));
+    }
+
+    #[test]
+    fn overflow_hidden_clips_descendants() {
+        let html = "<div><p></p></div>";
+        let css = "div { display: block; width: 10px; height: 10px; padding: 2px;
+                         overflow: hidden; background: #0000ff; }
+                   p { display: block; width: 30px; height: 30px; background: #ff0000; }";
+        let canvas = render(html, css, 40.0, 40.0);
+
+        // The child covers the parent's content and padding, but nothing outside it
+        assert_eq!(RED, canvas.pixel(2, 2));
+        assert_eq!(RED, canvas.pixel(13, 13));
+        assert_eq!(WHITE, canvas.pixel(14, 2));
+        assert_eq!(WHITE, canvas.pixel(2, 14));
+        assert_eq!(WHITE, canvas.pixel(30, 30));
+
+        let root = dom::Parser::parse(html.to_string());
+        let stylesheet = css::Parser::parse(css.to_string());
+        let style_root = style_tree(&root, &stylesheet);
+        let tree = layout_tree(&style_root, Default::default()).unwrap();
+        // The child's background is cut down to the parent's padding box
+        assert_eq!(
+            vec![
+                DisplayCommand::SolidColor(BLUE, rect(0.0, 0.0, 14.0, 14.0)),
+                DisplayCommand::PushClip(rect(0.0, 0.0, 14.0, 14.0)),
+                DisplayCommand::SolidColor(RED, rect(2.0, 2.0, 12.0, 12.0)),
+                DisplayCommand::PopClip,
+            ],
+            build_display_list(&tree.root)
+        );
+    }
+
+    #[test]
+    fn viewport_drops_offscreen_commands() {
+        let html = format!("<div>{}</div>", "<p>Hello</p>".repeat(625));
+        let css = "div { display: block; background: #0000ff; }
+                   p { display: block; height: 16px; background: #ff0000; }";
+        let root = dom::Parser::parse(html);
+        let stylesheet = css::Parser::parse(css.to_string());
+        let style_root = style_tree(&root, &stylesheet);
+        let mut viewport: crate::layout::Dimensions = Default::default();
+        viewport.content.width = 800.0;
+        viewport.content.height = 600.0;
+        let tree = layout_tree(&style_root, viewport).unwrap();
+        assert_eq!(10_000.0, tree.root.dimensions.border_box().height);
+
+        let list = build_display_list_for_viewport(&tree.root, viewport.content);
+        // The page's background, then 37.5 rows of paragraphs: the backgrounds, then their
+        // lines of text
+        assert_eq!(1 + 38 * 2, list.len());
+        assert_eq!(
+            DisplayCommand::SolidColor(BLUE, rect(0.0, 0.0, 800.0, 600.0)),
+            list[0]
+        );
+        assert_eq!(
+            DisplayCommand::SolidColor(RED, rect(0.0, 592.0, 800.0, 8.0)),
+            list[38]
+        );
+        assert!(list.iter().all(|item| match item {
+            DisplayCommand::Text { origin, .. } => origin.1 < 600.0 + 16.0,
+            _ => true,
+        }));
+        assert_eq!(1 + 625 * 2, build_display_list(&tree.root).len());
+    }
+
+    #[test]
+    fn diff_display_lists() {
+        let a = vec![
+            DisplayCommand::SolidColor(RED, rect(0.0, 0.0, 10.0, 10.0)),
+            DisplayCommand::PushOpacity(0.5),
+            DisplayCommand::SolidColor(BLUE, rect(0.0, 0.0, 10.0, 4.0)),
+            DisplayCommand::PopOpacity,
+        ];
+        assert_eq!(Vec::<DisplayDiff>::new(), diff(&a, &a));
+
+        // A command moved down, inside a new clip
+        let mut b = a.clone();
+        b[2] = DisplayCommand::SolidColor(BLUE, rect(0.0, 2.0, 10.0, 4.0));
+        b.insert(1, DisplayCommand::PushClip(rect(0.0, 0.0, 5.0, 5.0)));
+        b.push(DisplayCommand::PopClip);
+        assert_eq!(
+            vec![
+                DisplayDiff::Added {
+                    index: 1,
+                    command: DisplayCommand::PushClip(rect(0.0, 0.0, 5.0, 5.0)),
+                },
+                DisplayDiff::Changed {
+                    index: 2,
+                    new_index: 3,
+                    fields: vec![FieldDelta {
+                        field: "rect.y".to_string(),
+                        before: "0".to_string(),
+                        after: "2".to_string(),
+                    }],
+                },
+                DisplayDiff::Added {
+                    index: 5,
+                    command: DisplayCommand::PopClip,
+                },
+            ],
+            diff(&a, &b)
+        );
+
+        // Commands of different kinds are removed and added
+        let c = vec![DisplayCommand::PushClip(rect(0.0, 0.0, 10.0, 10.0))];
+        assert_eq!(
+            vec![
+                DisplayDiff::Removed {
+                    index: 0,
+                    command: a[0].clone(),
+                },
+                DisplayDiff::Added {
+                    index: 0,
+                    command: c[0].clone(),
+                },
+                DisplayDiff::Removed {
+                    index: 1,
+                    command: a[1].clone(),
+                },
+                DisplayDiff::Removed {
+                    index: 2,
+                    command: a[2].clone(),
+                },
+                DisplayDiff::Removed {
+                    index: 3,
+                    command: a[3].clone(),
+                },
+            ],
+            diff(&a, &c)
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn display_list_json_round_trip() {
+        let list = display_list(
+            "<div><p>Hi</p></div>",
+            "div { display: block; padding: 2px; overflow: hidden; border-radius: 3px;
+                   background: #ff000080; }
+             p { display: block; opacity: 0.5; color: #0000ff;
+                 background-image: linear-gradient(to right, #00ff00, #0000ff); }",
+            20.0,
+        );
+        let json = to_json(&list);
+        assert_eq!(
+            include_str!("../tests/fixtures/display_list.json").trim_end(),
+            json
+        );
+
+        let replayed = from_json(&json).unwrap();
+        assert_eq!(list, replayed);
+        let bounds = rect(0.0, 0.0, 20.0, 20.0);
+        assert_eq!(paint(&list, bounds).pixels, paint(&replayed, bounds).pixels);
+
+        assert!(from_json("[{\"solid_color\": []}]").is_err());
+    }
+
+    #[test]
+    fn partial_repaint_matches_full_repaint() {
+        let html = "<div><p class=\"a\"></p><p class=\"b\"></p><p class=\"c\"></p></div>";
+        let css = "div { display: block; padding: 3px; background: #00ff0080; }
+                   p { display: block; height: 5.5px; margin: 1px; border-radius: 2px;
+                       background: #0000ff; }";
+        let bounds = rect(0.0, 0.0, 20.0, 30.0);
+        let before = display_list(html, css, 20.0);
+        let after = display_list(
+            html,
+            &format!("{} .b {{ background: #ff0000c0; }}", css),
+            20.0,
+        );
+
+        let diffs = diff(&before, &after);
+        let dirty = dirty_rect(&before, &after, &diffs, bounds).unwrap();
+        assert_eq!(rect(4.0, 11.5, 12.0, 5.5), dirty);
+
+        let mut canvas = paint(&before, bounds);
+        canvas.paint_partial(&after, dirty);
+        assert_eq!(paint(&after, bounds).pixels, canvas.pixels);
+
+        // Without differences, nothing is dirty
+        assert_eq!(None, dirty_rect(&before, &before, &[], bounds));
+    }
+
+    #[test]
+    fn image_placeholder() {
+        let list = display_list(
+            "<div><img src=\"missing.png\" width=\"4\" height=\"3\"></div>",
+            "div { display: block; }",
+            10.0,
+        );
+        assert_eq!(
+            vec![
+                DisplayCommand::SolidColor(PLACEHOLDER_FILL, rect(0.0, 0.0, 4.0, 3.0)),
+                DisplayCommand::SolidColor(PLACEHOLDER_OUTLINE, rect(0.0, 0.0, 4.0, 1.0)),
+                DisplayCommand::SolidColor(PLACEHOLDER_OUTLINE, rect(0.0, 2.0, 4.0, 1.0)),
+                DisplayCommand::SolidColor(PLACEHOLDER_OUTLINE, rect(0.0, 1.0, 1.0, 1.0)),
+                DisplayCommand::SolidColor(PLACEHOLDER_OUTLINE, rect(3.0, 1.0, 1.0, 1.0)),
+            ],
+            list
+        );
+    }
+
+    #[cfg(feature = "image")]
+    #[test]
+    fn images_from_loader() {
+        let root = dom::Parser::parse(
+            "<div><img src=\"test.png\" width=\"4\" height=\"4\"><img src=\"missing.png\"\
+             width=\"2\" height=\"2\"></div>"
+                .to_string(),
+        );
+        let stylesheet = css::Parser::parse("div { display: block; }".to_string());
+        let style_root = style_tree(&root, &stylesheet);
+        let mut viewport: crate::layout::Dimensions = Default::default();
+        viewport.content.width = 6.0;
+        viewport.content.height = 4.0;
+        let tree = layout_tree(&style_root, viewport).unwrap();
+
+        // A 2x2 image, stretched over the 4x4 box with each pixel covering 2x2
+        let loader = |src: &str| {
+            (src == "test.png").then(|| Image {
+                width: 2,
+                height: 2,
+                rgba: vec![
+                    255, 0, 0, 255, /**/ 0, 0, 255, 255, //
+                    0, 255, 0, 255, /**/ 0, 0, 0, 255,
+                ],
+            })
+        };
+        let list = build_display_list_with_images(&tree.root, viewport.content, &loader);
+        let canvas = paint(&list, viewport.content);
+        let green = Color {
+            r: 0,
+            g: 255,
+            b: 0,
+            a: 255,
+        };
+        let black = Color {
+            r: 0,
+            g: 0,
+            b: 0,
+            a: 255,
+        };
+        let rows: Vec<Vec<Color>> = (0..4)
+            .map(|y| (0..4).map(|x| canvas.pixel(x, y)).collect())
+            .collect();
+        assert_eq!(vec![RED, RED, BLUE, BLUE], rows[0]);
+        assert_eq!(vec![RED, RED, BLUE, BLUE], rows[1]);
+        assert_eq!(vec![green, green, black, black], rows[2]);
+        assert_eq!(vec![green, green, black, black], rows[3]);
+
+        // An image the loader doesn't have gets a placeholder
+        assert_eq!(PLACEHOLDER_OUTLINE, canvas.pixel(4, 2));
+    }
+}
@@ -0,0 +1,667 @@
+// Running a page through the whole engine: parsing the HTML and CSS, styling, layout and
+// painting. `render_with_stats` also measures each stage, which `render_with_options`
+// doesn't spend any time on. Malformed HTML or CSS is an error rather than a panic
+
+use std::time::{Duration, Instant};
+
+use crate::{
+    css, dom, font,
+    layout::{self, LayoutBox, LayoutError, Rect},
+    paint::{self, Canvas, DisplayList},
+    style::{self, StyledNode},
+    Error,
+};
+
+// The painted page, and the display list it was painted from
+pub struct Rendering {
+    pub display_list: DisplayList,
+    pub canvas: Canvas,
+}
+
+impl Rendering {
+    // The area the canvas covers, which is taller than the viewport with `full_height`
+    pub fn bounds(&self) -> Rect {
+        Rect {
+            x: 0.0,
+            y: 0.0,
+            width: self.canvas.width as f32,
+            height: self.canvas.height as f32,
+        }
+    }
+}
+
+// How long each stage of a run took, and how much it worked on
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PipelineStats {
+    pub parse_time: Duration,
+    pub html_bytes: usize,
+    pub nodes: usize,
+
+    pub css_parse_time: Duration,
+    pub css_bytes: usize,
+    pub rules: usize,
+
+    pub style_time: Duration,
+    pub styled_nodes: usize,
+    // The number of (element, rule) pairs where the rule applies to the element
+    pub matched_rules: usize,
+
+    pub layout_time: Duration,
+    pub boxes: usize,
+
+    pub paint_time: Duration,
+    pub commands: usize,
+    pub pixels: usize,
+}
+
+impl PipelineStats {
+    pub fn total_time(&self) -> Duration {
+        self.parse_time + self.css_parse_time + self.style_time + self.layout_time + self.paint_time
+    }
+}
+
+// Print the stats as a table with a row per stage
+impl std::fmt::Display for PipelineStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let rows = [
+            (
+                "parse",
+                self.parse_time,
+                format!("{} bytes, {} nodes", self.html_bytes, self.nodes),
+            ),
+            (
+                "css parse",
+                self.css_parse_time,
+                format!("{} bytes, {} rules", self.css_bytes, self.rules),
+            ),
+            (
+                "style",
+                self.style_time,
+                format!(
+                    "{} nodes, {} rules matched",
+                    self.styled_nodes, self.matched_rules
+                ),
+            ),
+            ("layout", self.layout_time, format!("{} boxes", self.boxes)),
+            (
+                "paint",
+                self.paint_time,
+                format!("{} commands, {} pixels", self.commands, self.pixels),
+            ),
+            ("total", self.total_time(), String::new()),
+        ];
+        writeln!(f, "{:<10} {:>10}  counts", "stage", "time")?;
+        for (stage, time, counts) in rows {
+            let ms = format!("{:.3} ms", time.as_secs_f64() * 1000.0);
+            writeln!(f, "{:<10} {:>10}  {}", stage, ms, counts)?;
+        }
+        Ok(())
+    }
+}
+
+// The size of the viewport a page is laid out in, in px
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Viewport {
+    pub width: f32,
+    pub height: f32,
+}
+
+// How a page is rendered, beyond the stylesheets and the viewport
+#[derive(Clone, Copy)]
+pub struct RenderOptions {
+    // Apply `style::DEFAULT_CSS` before the page's stylesheets
+    pub default_css: bool,
+    // Keep whitespace between tags, as `dom::ParseOptions::preserve_whitespace` does
+    pub preserve_whitespace: bool,
+    // Grow the canvas past the bottom of the viewport to fit the whole page
+    pub full_height: bool,
+    // What to measure text with when laying it out
+    pub metrics: &'static dyn font::FontMetrics,
+}
+
+// The default stylesheet is applied, and text is measured to match the painted glyphs
+impl Default for RenderOptions {
+    fn default() -> RenderOptions {
+        RenderOptions {
+            default_css: true,
+            preserve_whitespace: false,
+            full_height: false,
+            metrics: font::text_metrics(),
+        }
+    }
+}
+
+/// Render a page styled with `css` (after the default stylesheet) to a canvas the size of
+/// the viewport.
+///
+/// ```
+/// use robinson::{render, Viewport};
+///
+/// let viewport = Viewport {
+///     width: 20.0,
+///     height: 10.0,
+/// };
+/// let canvas = render("<div></div>", "div { height: 4px; background: #ff0000; }", viewport)?;
+/// assert_eq!(255, canvas.pixel(0, 0).r);
+/// assert_eq!(255, canvas.pixel(0, 4).g);
+/// # Ok::<(), robinson::Error>(())
+/// ```
+pub fn render(html: &str, css: &str, viewport: Viewport) -> Result<Canvas, Error> {
+    let rendering = render_with_options(html, &[css], viewport, &RenderOptions::default())?;
+    Ok(rendering.canvas)
+}
+
+// Render a page as the options say. Each of the stylesheets in `css` is parsed on its own
+// and then merged in order, so later ones win ties in specificity. A malformed stylesheet
+// is reported by its index in `css`
+pub fn render_with_options(
+    html: &str,
+    css: &[&str],
+    viewport: Viewport,
+    options: &RenderOptions,
+) -> Result<Rendering, Error> {
+    run_stages(html, css, viewport, options, None, |tree| {
+        paint_layout(tree, viewport, options)
+    })
+}
+
+// Like `render_with_options`, also returning how long each stage took and what it worked on
+pub fn render_with_stats(
+    html: &str,
+    css: &[&str],
+    viewport: Viewport,
+    options: &RenderOptions,
+) -> Result<(Rendering, PipelineStats), Error> {
+    let mut stats = PipelineStats::default();
+    let rendering = run_stages(html, css, viewport, options, Some(&mut stats), |tree| {
+        paint_layout(tree, viewport, options)
+    })?;
+    stats.commands = rendering.display_list.len();
+    stats.pixels = rendering.canvas.width * rendering.canvas.height;
+    Ok((rendering, stats))
+}
+
+// Go as far as the display list, without painting it
+pub fn render_to_display_list(
+    html: &str,
+    css: &[&str],
+    viewport: Viewport,
+    options: &RenderOptions,
+) -> Result<DisplayList, Error> {
+    let stylesheet = stylesheet(css, options)?;
+    let display_list = with_layout(html, &stylesheet, viewport, options, |tree| {
+        paint::build_display_list_for_viewport(&tree.root, paint_bounds(tree, viewport, options))
+    })?;
+    // Nothing is drawn if the root has `display: none`
+    Ok(display_list.unwrap_or_default())
+}
+
+// Parse each of the stylesheets, and merge them in order into one
+pub fn parse_stylesheets(css: &[&str]) -> Result<css::StyleSheet, Error> {
+    let mut stylesheet = css::StyleSheet::default();
+    for (i, source) in css.iter().enumerate() {
+        let sheet =
+            css::Parser::try_parse(source.to_string()).map_err(|e| Error::CssParse(i, e))?;
+        stylesheet.merge(sheet);
+    }
+    Ok(stylesheet)
+}
+
+// Parse and merge the stylesheets, after the default stylesheet if the options ask for it
+pub fn stylesheet(css: &[&str], options: &RenderOptions) -> Result<css::StyleSheet, Error> {
+    let mut stylesheet = match options.default_css {
+        true => css::Parser::parse(style::DEFAULT_CSS.to_string()),
+        false => css::StyleSheet::default(),
+    };
+    stylesheet.merge(parse_stylesheets(css)?);
+    Ok(stylesheet)
+}
+
+// Parse the page, style it and lay it out in the viewport, then make an output from the
+// layout. Returns `None` if the root has `display: none`
+pub fn with_layout<T>(
+    html: &str,
+    stylesheet: &css::StyleSheet,
+    viewport: Viewport,
+    options: &RenderOptions,
+    output: impl FnOnce(&layout::LayoutTree) -> T,
+) -> Result<Option<T>, Error> {
+    let root_node = parse_html(html, options)?;
+    let style_root = style::style_tree(&root_node, stylesheet);
+    match layout::layout_tree_with_metrics(&style_root, dimensions(viewport), options.metrics) {
+        Ok(tree) => Ok(Some(output(&tree))),
+        Err(LayoutError::HiddenRoot) => Ok(None),
+    }
+}
+
+// Like `with_layout`, parsing the stylesheets as `render_with_options` does, and also
+// returning how long each stage took and what it worked on. Making the output is timed as
+// the paint stage, which paints no commands or pixels
+pub fn with_layout_and_stats<T>(
+    html: &str,
+    css: &[&str],
+    viewport: Viewport,
+    options: &RenderOptions,
+    output: impl FnOnce(&layout::LayoutTree) -> T,
+) -> Result<(Option<T>, PipelineStats), Error> {
+    let mut stats = PipelineStats::default();
+    let output = run_stages(html, css, viewport, options, Some(&mut stats), |tree| {
+        tree.map(output)
+    })?;
+    Ok((output, stats))
+}
+
+pub(crate) fn parse_html(html: &str, options: &RenderOptions) -> Result<dom::Node, Error> {
+    let parse_options = dom::ParseOptions {
+        preserve_whitespace: options.preserve_whitespace,
+    };
+    Ok(dom::Parser::try_parse_with_options(
+        html.to_string(),
+        parse_options,
+    )?)
+}
+
+fn dimensions(viewport: Viewport) -> layout::Dimensions {
+    let mut dimensions: layout::Dimensions = Default::default();
+    dimensions.content.width = viewport.width;
+    dimensions.content.height = viewport.height;
+    dimensions
+}
+
+// The area to paint: the viewport, or with `full_height` as much of the page as is below it
+pub fn paint_bounds(
+    tree: &layout::LayoutTree,
+    viewport: Viewport,
+    options: &RenderOptions,
+) -> Rect {
+    let mut bounds = dimensions(viewport).content;
+    if options.full_height {
+        let bottom = tree.content_size.y + tree.content_size.height;
+        bounds.height = bounds.height.max(bottom.ceil());
+    }
+    bounds
+}
+
+// Paint a layout, or a blank viewport if the root has `display: none`
+fn paint_layout(
+    tree: Option<&layout::LayoutTree>,
+    viewport: Viewport,
+    options: &RenderOptions,
+) -> Rendering {
+    let (display_list, bounds) = match tree {
+        Some(tree) => {
+            let bounds = paint_bounds(tree, viewport, options);
+            let display_list = paint::build_display_list_for_viewport(&tree.root, bounds);
+            (display_list, bounds)
+        }
+        None => (Vec::new(), dimensions(viewport).content),
+    };
+    let canvas = paint::paint(&display_list, bounds);
+    Rendering {
+        display_list,
+        canvas,
+    }
+}
+
+// Parse, style and lay out a page, then make an output from the layout, which is `None` if
+// the root has `display: none`. Making the output is timed as the paint stage
+fn run_stages<T>(
+    html: &str,
+    css: &[&str],
+    viewport: Viewport,
+    options: &RenderOptions,
+    mut stats: Option<&mut PipelineStats>,
+    output: impl FnOnce(Option<&layout::LayoutTree>) -> T,
+) -> Result<T, Error> {
+    // Time a stage only if stats are wanted
+    let start = |stats: &Option<&mut PipelineStats>| stats.as_ref().map(|_| Instant::now());
+    let elapsed = |start: Option<Instant>| start.map(|t| t.elapsed()).unwrap_or_default();
+
+    let t = start(&stats);
+    let root_node = parse_html(html, options)?;
+    let parse_time = elapsed(t);
+
+    let t = start(&stats);
+    let stylesheet = stylesheet(css, options)?;
+    let css_parse_time = elapsed(t);
+
+    let t = start(&stats);
+    let style_root = style::style_tree(&root_node, &stylesheet);
+    let style_time = elapsed(t);
+
+    let t = start(&stats);
+    let tree = layout::layout_tree_with_metrics(&style_root, dimensions(viewport), options.metrics);
+    let layout_time = elapsed(t);
+
+    let t = start(&stats);
+    let output = match tree {
+        Ok(ref tree) => output(Some(tree)),
+        // Nothing is displayed
+        Err(LayoutError::HiddenRoot) => output(None),
+    };
+    let paint_time = elapsed(t);
+
+    // Counting is done after all the stages, so it isn't timed
+    if let Some(ref mut stats) = stats {
+        let default_css = match options.default_css {
+            true => style::DEFAULT_CSS.len(),
+            false => 0,
+        };
+        **stats = PipelineStats {
+            parse_time,
+            html_bytes: html.len(),
+            nodes: count_nodes(&root_node),
+            css_parse_time,
+            css_bytes: default_css + css.iter().map(|source| source.len()).sum::<usize>(),
+            rules: stylesheet.rules.len(),
+            style_time,
+            styled_nodes: count_styled_nodes(&style_root),
+            matched_rules: count_matched_rules(&root_node, &stylesheet),
+            layout_time,
+            boxes: tree.as_ref().map_or(0, |tree| count_boxes(&tree.root)),
+            paint_time,
+            commands: 0,
+            pixels: 0,
+        };
+    }
+    Ok(output)
+}
+
+fn count_nodes(node: &dom::Node) -> usize {
+    1 + node.children.iter().map(count_nodes).sum::<usize>()
+}
+
+fn count_styled_nodes(node: &StyledNode) -> usize {
+    1 + node.children.iter().map(count_styled_nodes).sum::<usize>()
+}
+
+fn count_matched_rules(node: &dom::Node, stylesheet: &css::StyleSheet) -> usize {
+    let matched = stylesheet
+        .rules
+        .iter()
+        .filter(|rule| rule.selectors.iter().any(|s| style::matches(node, s)))
+        .count();
+    let children: usize = node
+        .children
+        .iter()
+        .map(|child| count_matched_rules(child, stylesheet))
+        .sum();
+    matched + children
+}
+
+fn count_boxes(layout_box: &LayoutBox) -> usize {
+    1 + layout_box.children.iter().map(count_boxes).sum::<usize>()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Render a page without the default stylesheet, in a viewport of `width` x `height` px
+    fn render_plain(html: &str, css: &[&str], width: f32, height: f32) -> Result<Rendering, Error> {
+        let options = RenderOptions {
+            default_css: false,
+            ..RenderOptions::default()
+        };
+        render_with_options(html, css, Viewport { width, height }, &options)
+    }
+
+    #[test]
+    fn stats_count_each_stage() {
+        let html = "<div class=\"page\"><p>Hello</p><p class=\"note\">world</p></div>";
+        let css = "div { display: block; }
+                   p { display: block; height: 10px; background: #ff0000; }
+                   .note { color: #0000ff; }
+                   span { display: none; }";
+        let viewport = Viewport {
+            width: 20.0,
+            height: 30.0,
+        };
+        let options = RenderOptions {
+            default_css: false,
+            ..RenderOptions::default()
+        };
+        let (rendering, stats) = render_with_stats(html, &[css], viewport, &options).unwrap();
+
+        assert_eq!(html.len(), stats.html_bytes);
+        // The div, the paragraphs and their text
+        assert_eq!(5, stats.nodes);
+        assert_eq!(css.len(), stats.css_bytes);
+        assert_eq!(4, stats.rules);
+        assert_eq!(5, stats.styled_nodes);
+        // div, p, and p with .note
+        assert_eq!(4, stats.matched_rules);
+        // Each paragraph's text is also wrapped in an anonymous block
+        assert_eq!(7, stats.boxes);
+        assert_eq!(rendering.display_list.len(), stats.commands);
+        assert_eq!(20 * 30, stats.pixels);
+
+        let table = stats.to_string();
+        assert!(table.starts_with("stage"));
+        assert!(table.contains("5 nodes, 4 rules matched"));
+
+        // Rendering without stats gives the same result
+        let plain = render_with_options(html, &[css], viewport, &options).unwrap();
+        assert_eq!(rendering.display_list, plain.display_list);
+    }
+
+    #[test]
+    fn render_a_page() {
+        let html = "<body><h1>Hi</h1><p class=\"note\"></p></body>";
+        let css = ".note { height: 2px; background: #0000ff; }";
+        let viewport = Viewport {
+            width: 40.0,
+            height: 60.0,
+        };
+        let canvas = render(html, css, viewport).unwrap();
+        assert_eq!((40, 60), (canvas.width, canvas.height));
+        // The heading is a block, from the default stylesheet, and the paragraph is below it
+        let blue = |y| canvas.pixel(0, y).b == 255 && canvas.pixel(0, y).r == 0;
+        let rows: Vec<_> = (0..60).filter(|&y| blue(y)).collect();
+        assert_eq!(2, rows.len());
+        assert!(rows[0] > 16);
+
+        let display_list =
+            render_to_display_list(html, &[css], viewport, &RenderOptions::default()).unwrap();
+        let rendering =
+            render_with_options(html, &[css], viewport, &RenderOptions::default()).unwrap();
+        assert_eq!(rendering.display_list, display_list);
+    }
+
+    #[test]
+    fn render_options() {
+        let html = "<div><p>a</p>  <p>b</p></div>";
+        let css = "p { height: 10px; background: #ff0000; }";
+        let viewport = Viewport {
+            width: 40.0,
+            height: 10.0,
+        };
+        let render =
+            |options: RenderOptions| render_with_options(html, &[css], viewport, &options).unwrap();
+
+        // Without the default stylesheet, paragraphs are inline, so they don't fill the width
+        let with_default = render(RenderOptions::default());
+        let without_default = render(RenderOptions {
+            default_css: false,
+            ..RenderOptions::default()
+        });
+        assert_eq!(0, with_default.canvas.pixel(39, 0).g);
+        assert_eq!(255, without_default.canvas.pixel(39, 0).g);
+
+        // The canvas grows to fit the second paragraph, and the text overflowing it
+        let full_height = render(RenderOptions {
+            full_height: true,
+            ..RenderOptions::default()
+        });
+        assert!(full_height.canvas.height > 20);
+        assert_eq!(0, full_height.canvas.pixel(39, 15).g);
+
+        // The whitespace between the paragraphs is kept as a text node
+        let count_nodes = |options: RenderOptions| {
+            render_with_stats(html, &[css], viewport, &options)
+                .unwrap()
+                .1
+                .nodes
+        };
+        let preserved = RenderOptions {
+            preserve_whitespace: true,
+            ..RenderOptions::default()
+        };
+        assert_eq!(5, count_nodes(RenderOptions::default()));
+        assert_eq!(6, count_nodes(preserved));
+    }
+
+    #[test]
+    fn default_css_underlines_links() {
+        let viewport = Viewport {
+            width: 100.0,
+            height: 20.0,
+        };
+        let underlines = |default_css| {
+            let options = RenderOptions {
+                default_css,
+                ..RenderOptions::default()
+            };
+            render_to_display_list("<p><a>link</a></p>", &[], viewport, &options)
+                .unwrap()
+                .iter()
+                .filter(|command| matches!(command, paint::DisplayCommand::SolidColor(..)))
+                .count()
+        };
+        // Like the other defaults, the underline goes away without the default stylesheet
+        assert_eq!(1, underlines(true));
+        assert_eq!(0, underlines(false));
+    }
+
+    #[test]
+    fn default_css_makes_room_for_list_markers() {
+        let viewport = Viewport {
+            width: 100.0,
+            height: 40.0,
+        };
+        let list = render_to_display_list(
+            "<ol><li>one</li><li>two</li></ol>",
+            &[],
+            viewport,
+            &RenderOptions::default(),
+        )
+        .unwrap();
+        // The markers sit in the list's left padding, inside the viewport
+        let markers: Vec<_> = list
+            .iter()
+            .filter_map(|command| match command {
+                paint::DisplayCommand::Text { text, origin, .. } if text.ends_with('.') => {
+                    Some((text.as_str(), origin.0 >= 0.0))
+                }
+                _ => None,
+            })
+            .collect();
+        assert_eq!(vec![("1.", true), ("2.", true)], markers);
+    }
+
+    #[test]
+    fn astral_plane_text() {
+        let html = "<div><p>ok 👋🏽</p><p class=\"note\"></p></div>";
+        let css = "div, p { display: block; }
+                   p { white-space: nowrap; }
+                   .note::before { content: \"𝄞😀\"; }";
+        let rendering = render_plain(html, &[css], 24.0, 40.0).unwrap();
+        let texts: Vec<_> = rendering
+            .display_list
+            .iter()
+            .filter_map(|command| match command {
+                paint::DisplayCommand::Text { text, .. } => Some(&**text),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(vec!["ok 👋🏽", "𝄞😀"], texts);
+    }
+
+    #[test]
+    fn parse_errors() {
+        let error = |html: &str, css: &[&str]| render_plain(html, css, 10.0, 10.0).err().unwrap();
+        assert_eq!(
+            "invalid HTML: expected '>', found end of input at byte 4",
+            error("<div", &[]).to_string()
+        );
+        // The index says which stylesheet is malformed
+        assert!(matches!(
+            error("<div></div>", &["div {}", "div > p {}"]),
+            Error::CssParse(1, _)
+        ));
+    }
+
+    #[test]
+    fn css_error_is_the_source() {
+        use std::error::Error as _;
+
+        let error = render_plain(
+            "<div></div>",
+            &["div { color: red }", "div > p {}"],
+            10.0,
+            10.0,
+        )
+        .err()
+        .unwrap();
+        let source = error.source().unwrap();
+        let css_error = source.downcast_ref::<css::ParseError>().unwrap();
+        assert_eq!(4, css_error.pos);
+        assert!(source.downcast_ref::<dom::ParseError>().is_none());
+    }
+
+    #[test]
+    fn layout_error_is_the_source() {
+        use std::error::Error as _;
+
+        let viewport = Viewport {
+            width: 4.0,
+            height: 4.0,
+        };
+        // A hidden root can't be laid out, which `?` turns into the crate's error
+        let lay_out = || -> Result<(), Error> {
+            let root = dom::parse("<div></div>")?;
+            let stylesheet = css::parse("div { display: none; }")?;
+            let style_root = style::style_tree(&root, &stylesheet);
+            layout::layout_tree(&style_root, dimensions(viewport))?;
+            Ok(())
+        };
+        let error = lay_out().err().unwrap();
+        assert!(matches!(error, Error::Layout(LayoutError::HiddenRoot)));
+        let source = error.source().unwrap();
+        assert!(source.downcast_ref::<LayoutError>().is_some());
+
+        // The pipeline renders a blank canvas instead
+        let canvas = render("<div></div>", "div { display: none; }", viewport).unwrap();
+        let white = |c: &css::Color| (c.r, c.g, c.b) == (255, 255, 255);
+        assert!(canvas.pixels.iter().all(white));
+    }
+
+    #[test]
+    fn later_stylesheets_win_ties() {
+        let html = "<div class=\"box\"></div>";
+        let rendering = render_plain(
+            html,
+            &[
+                "div { display: block; height: 10px; background: #ff0000; }",
+                ".box { background: #00ff00; } div { background: #0000ff; }",
+            ],
+            10.0,
+            10.0,
+        )
+        .unwrap();
+        // The class selector is more specific, even though the stylesheet with `div` in it
+        // comes later
+        assert_eq!(255, rendering.canvas.pixel(0, 0).g);
+        let rendering = render_plain(
+            html,
+            &[
+                "div { display: block; height: 10px; background: #ff0000; }",
+                "div { background: #0000ff; }",
+            ],
+            10.0,
+            10.0,
+        )
+        .unwrap();
+        assert_eq!(255, rendering.canvas.pixel(0, 0).b);
+    }
+}
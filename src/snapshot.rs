@@ -0,0 +1,200 @@
+// An owned copy of the geometry computed by layout, for use outside the crate (e.g. by
+// external renderers) and for comparing layouts in regression tests
+
+use crate::{
+    css::{Color, Value},
+    dom::DomNode,
+    layout::{BoxType, LayoutBox, Rect},
+};
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct LayoutSnapshot {
+    pub box_type: SnapshotBoxType,
+    // The element's tag name, id and classes, for boxes generated by elements
+    pub tag: Option<String>,
+    pub id: Option<String>,
+    pub classes: Vec<String>,
+    // The text of a text node
+    pub text: Option<String>,
+    // Absolute coordinates of each area of the box model
+    pub content: Rect,
+    pub padding: Rect,
+    pub border: Rect,
+    pub margin: Rect,
+    pub background: Option<Color>,
+    pub border_color: Option<Color>,
+    // Whether descendants are clipped to the padding box by `overflow: hidden`
+    pub clipped: bool,
+    // Whether the box has `position: fixed`
+    pub fixed: bool,
+    pub children: Vec<LayoutSnapshot>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum SnapshotBoxType {
+    Block,
+    Flex,
+    Inline,
+    Anonymous,
+    Marker,
+}
+
+impl<'a, N: DomNode<'a>> LayoutBox<'a, N> {
+    // Copy the layout of this box and its descendants into owned data
+    pub fn to_snapshot(&self) -> LayoutSnapshot {
+        let box_type = match self.box_type {
+            BoxType::BlockNode(_) => SnapshotBoxType::Block,
+            BoxType::FlexContainer(_) => SnapshotBoxType::Flex,
+            BoxType::InlineNode(_) => SnapshotBoxType::Inline,
+            BoxType::AnonymousBlock(_) => SnapshotBoxType::Anonymous,
+            BoxType::Marker(_) => SnapshotBoxType::Marker,
+        };
+        let style = self.get_style_node();
+        let keyword = |name: &str, expected: &str| match style.and_then(|s| s.value(name)) {
+            Some(Value::Keyword(k)) => k == expected,
+            _ => false,
+        };
+
+        let id = style.and_then(|s| s.attribute("id")).map(String::from);
+        let mut classes: Vec<String> = style
+            .and_then(|s| s.attribute("class"))
+            .map_or(Vec::new(), |c| {
+                c.split_whitespace().map(String::from).collect()
+            });
+        classes.sort();
+        classes.dedup();
+        let text = style.and_then(|s| s.text()).map(String::from);
+
+        let d = self.dimensions;
+        LayoutSnapshot {
+            box_type,
+            tag: self.tag_name().map(String::from),
+            id,
+            classes,
+            text,
+            content: d.content,
+            padding: d.padding_box(),
+            border: d.border_box(),
+            margin: d.margin_box(),
+            background: self.color("background"),
+            border_color: self.color("border-color"),
+            clipped: keyword("overflow", "hidden"),
+            fixed: keyword("position", "fixed"),
+            children: self
+                .children
+                .iter()
+                .map(|child| child.to_snapshot())
+                .collect(),
+        }
+    }
+}
+
+impl LayoutSnapshot {
+    // Compare two snapshots, allowing coordinates to differ by up to `tolerance` px
+    pub fn approx_eq(&self, other: &LayoutSnapshot, tolerance: f32) -> bool {
+        let rect_eq = |a: Rect, b: Rect| {
+            (a.x - b.x).abs() <= tolerance
+                && (a.y - b.y).abs() <= tolerance
+                && (a.width - b.width).abs() <= tolerance
+                && (a.height - b.height).abs() <= tolerance
+        };
+        self.box_type == other.box_type
+            && self.tag == other.tag
+            && self.id == other.id
+            && self.classes == other.classes
+            && self.text == other.text
+            && rect_eq(self.content, other.content)
+            && rect_eq(self.padding, other.padding)
+            && rect_eq(self.border, other.border)
+            && rect_eq(self.margin, other.margin)
+            && self.background == other.background
+            && self.border_color == other.border_color
+            && self.clipped == other.clipped
+            && self.fixed == other.fixed
+            && self.children.len() == other.children.len()
+            && self
+                .children
+                .iter()
+                .zip(&other.children)
+                .all(|(a, b)| a.approx_eq(b, tolerance))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{css, dom, layout::layout_tree, style::style_tree};
+
+    const FIXTURE_HTML: &str = "<div id=\"main\" class=\"page wide\">\
+        <p class=\"note\">Hello world</p><section></section></div>";
+    const FIXTURE_CSS: &str = "div { display: block; padding: 4px; background: #eeeeee;
+                                     overflow: hidden; }
+                               p { display: block; margin: 2px; border-width: 1px;
+                                   border-style: solid; border-color: #ff0000; }
+                               section { display: block; height: 10.5px; position: fixed; }";
+
+    fn snapshot(html: &str, css: &str, width: f32) -> LayoutSnapshot {
+        let root = dom::Parser::parse(html.to_string());
+        let stylesheet = css::Parser::parse(css.to_string());
+        let style_root = style_tree(&root, &stylesheet);
+        let mut viewport: crate::layout::Dimensions = Default::default();
+        viewport.content.width = width;
+        layout_tree(&style_root, viewport)
+            .unwrap()
+            .root
+            .to_snapshot()
+    }
+
+    #[test]
+    fn snapshot_records_boxes() {
+        let snapshot = snapshot(FIXTURE_HTML, FIXTURE_CSS, 200.0);
+        assert_eq!(Some("div".to_string()), snapshot.tag);
+        assert_eq!(Some("main".to_string()), snapshot.id);
+        assert_eq!(vec!["page", "wide"], snapshot.classes);
+        assert!(snapshot.clipped);
+
+        let paragraph = &snapshot.children[0];
+        assert_eq!(
+            Rect {
+                x: 4.0,
+                y: 4.0,
+                width: 192.0,
+                height: 22.0
+            },
+            paragraph.margin
+        );
+        let text = &paragraph.children[0].children[0];
+        assert_eq!(SnapshotBoxType::Inline, text.box_type);
+        assert_eq!(Some("Hello world".to_string()), text.text);
+        assert!(snapshot.children[1].fixed);
+    }
+
+    #[test]
+    fn snapshots_compare_with_tolerance() {
+        let a = snapshot(FIXTURE_HTML, FIXTURE_CSS, 200.0);
+        let b = snapshot(FIXTURE_HTML, FIXTURE_CSS, 200.4);
+        assert!(a.approx_eq(&b, 0.5));
+        assert!(!a.approx_eq(&b, 0.1));
+
+        let c = snapshot(
+            FIXTURE_HTML,
+            &FIXTURE_CSS.replace("#eeeeee", "#000000"),
+            200.0,
+        );
+        assert!(!a.approx_eq(&c, 0.5));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn snapshot_json_matches_golden_file() {
+        let snapshot = snapshot(FIXTURE_HTML, FIXTURE_CSS, 200.0);
+        let json = serde_json::to_string_pretty(&snapshot).unwrap();
+        assert_eq!(
+            include_str!("../tests/fixtures/layout_snapshot.json").trim_end(),
+            json
+        );
+    }
+}
@@ -1,37 +1,62 @@
-use std::collections::HashMap;
+use std::{borrow::Cow, collections::HashMap};
 
 use crate::{
-    css::{Rule, Selector, SimpleSelector, Specificity, StyleSheet, Value},
-    dom::{ElementData, Node, NodeType},
+    atom::Atom,
+    css::{
+        self, Color, LengthContext, PseudoElement, Rule, Selector, Specificity, StyleSheet, Unit,
+        Value,
+    },
+    dom::{DomNode, Node, NodeType},
 };
 
 // Map from CSS property names to values
-pub type PropertyMap = HashMap<String, Value>;
+pub type PropertyMap = HashMap<Atom, Value>;
 
-// A node with associated style data
-pub struct StyledNode<'a> {
-    pub node: &'a Node, // pointer to a DOM node
+// A node with associated style data. `N` is how the DOM node is borrowed: `&Node` for a
+// tree of nodes, or `arena::NodeRef` for an arena-backed document
+pub struct StyledNode<'a, N = &'a Node> {
+    pub node: Content<'a, N>,
     pub specified_values: PropertyMap,
-    pub children: Vec<StyledNode<'a>>,
+    pub children: Vec<StyledNode<'a, N>>,
+    // Computed font sizes used to resolve relative lengths
+    pub length_context: LengthContext,
+    // The text of the marker box of a `display: list-item` element, e.g. a bullet
+    pub marker: Option<Box<StyledNode<'a, N>>>,
 }
 
+// What a styled node is styling
+pub enum Content<'a, N> {
+    Node(N),
+    // Text that isn't a DOM node of its own: content generated by `::before`/`::after`,
+    // a list marker, or either part of a text node split by `::first-letter`
+    Text(Cow<'a, str>),
+}
+
+// The initial value of `font-size`, in px
+pub const DEFAULT_FONT_SIZE: f32 = 16.0;
+
 pub enum Display {
     Inline,
     Block,
+    Flex,
+    ListItem,
     None,
 }
 
-impl StyledNode<'_> {
+impl<N> StyledNode<'_, N> {
     // Return the specified value of a property if it exists, otherwise `None`.
     pub fn value(&self, name: &str) -> Option<Value> {
         self.specified_values.get(name).cloned()
     }
 
-    // The value of the `display` property (defaults to inline)
+    // The computed value of the `display` property. Its initial value, which `initial`
+    // and unknown keywords also give, is inline
     pub fn display(&self) -> Display {
         match self.value("display") {
             Some(Value::Keyword(s)) => match &*s {
                 "block" => Display::Block,
+                "flex" => Display::Flex,
+                "list-item" => Display::ListItem,
                 "none" => Display::None,
                 _ => Display::Inline,
             },
@@ -45,85 +70,918 @@ impl StyledNode<'_> {
         self.value(name)
             .unwrap_or_else(|| self.value(fallback_name).unwrap_or_else(|| default.clone()))
     }
+
+    // Return the specified value of a property, with relative lengths resolved to px
+    pub fn length(&self, name: &str) -> Option<Value> {
+        self.value(name).map(|v| v.resolve(&self.length_context))
+    }
+
+    // Like `lookup`, but with relative lengths resolved to px
+    pub fn lookup_length(&self, name: &str, fallback_name: &str, default: &Value) -> Value {
+        self.lookup(name, fallback_name, default)
+            .resolve(&self.length_context)
+    }
+}
+
+impl<'a, N: DomNode<'a>> StyledNode<'a, N> {
+    // The tag name of the element, or `None` for text nodes
+    pub fn tag_name(&self) -> Option<&'a str> {
+        match self.node {
+            Content::Node(node) => node.tag_name(),
+            Content::Text(_) => None,
+        }
+    }
+
+    // The text of a text node, or `None` for elements
+    pub fn text(&self) -> Option<&str> {
+        match self.node {
+            Content::Node(node) => node.text(),
+            Content::Text(ref text) => Some(text),
+        }
+    }
+
+    // The value of one of the element's attributes
+    pub fn attribute(&self, name: &str) -> Option<&'a str> {
+        match self.node {
+            Content::Node(node) => node.attribute(name),
+            Content::Text(_) => None,
+        }
+    }
+
+    // Describe the node like a selector, e.g. `div#main.note`, or quote its text
+    pub(crate) fn label(&self) -> String {
+        match self.node {
+            Content::Node(node) => node.label(),
+            Content::Text(ref text) => format!("{:?}", text),
+        }
+    }
+
+    fn fmt_indented(&self, f: &mut std::fmt::Formatter<'_>, depth: usize) -> std::fmt::Result {
+        write!(f, "{:indent$}{}", "", self.label(), indent = depth * 2)?;
+        if !self.specified_values.is_empty() {
+            let mut names: Vec<_> = self.specified_values.keys().collect();
+            names.sort();
+            write!(f, " {{")?;
+            for name in names {
+                write!(f, " {}: {};", name, self.specified_values[name])?;
+            }
+            write!(f, " }}")?;
+        }
+        writeln!(f)?;
+        if let Some(ref marker) = self.marker {
+            write!(f, "{:indent$}::marker ", "", indent = depth * 2 + 2)?;
+            marker.fmt_indented(f, 0)?;
+        }
+        for child in &self.children {
+            child.fmt_indented(f, depth + 1)?;
+        }
+        Ok(())
+    }
+}
+
+// Print the tree one node per line, indented by depth, with each node's values sorted by
+// property name, e.g. `div.note { display: block; margin: 4px; }`
+impl<'a, N: DomNode<'a>> std::fmt::Display for StyledNode<'a, N> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.fmt_indented(f, 0)
+    }
+}
+
+// Properties whose values are inherited from the parent node when not specified
+const INHERITED_PROPERTIES: &[&str] = &[
+    "color",
+    "direction",
+    "font-family",
+    "font-style",
+    "font-weight",
+    "line-height",
+    "list-style-type",
+    "tab-size",
+    "text-align",
+    "text-decoration",
+    "visibility",
+    "white-space",
+];
+
+// A user-agent stylesheet with browsers' usual defaults for common elements, for callers
+// to put before their own stylesheets. `style_tree` doesn't apply it by itself
+pub const DEFAULT_CSS: &str = "\
+html, body, div, p, h1, h2, h3, h4, h5, h6, ul, ol, pre, blockquote, hr,
+header, footer, main, nav, section, article, aside, form { display: block; }
+head, title, style, script, link, meta { display: none; }
+h1 { font-size: 2em; font-weight: bold; }
+h2 { font-size: 1.5em; font-weight: bold; }
+h3 { font-size: 1.17em; font-weight: bold; }
+h4, h5, h6, b, strong, th { font-weight: bold; }
+i, em { font-style: italic; }
+pre { white-space: pre; }
+li { display: list-item; }
+ul, ol { padding-left: 40px; }
+ol { list-style-type: decimal; }
+a { text-decoration: underline; }
+";
+
+// Where a stylesheet comes from, which decides how its declarations rank in the cascade.
+// Normal declarations from later origins here win over earlier ones, and `!important`
+// declarations win the other way round, so an important user-agent declaration beats all
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Origin {
+    UserAgent,
+    User,
+    Author,
+}
+
+// Apply a stylesheet to an entire DOM tree, returning a StyleNode tree. The stylesheet's
+// rules are all author rules
+pub fn style_tree<'a, N: DomNode<'a>>(root: N, stylesheet: &StyleSheet) -> StyledNode<'a, N> {
+    style_tree_with_origins(root, &[(Origin::Author, stylesheet)])
+}
+
+// Like `style_tree`, with stylesheets from any origin. Stylesheets from the same origin
+// are in order, so a later one wins over an earlier one where specificity is equal
+pub fn style_tree_with_origins<'a, N: DomNode<'a>>(
+    root: N,
+    stylesheets: &[(Origin, &StyleSheet)],
+) -> StyledNode<'a, N> {
+    style_node(root, stylesheets, None)
 }
 
-// Apply a stylesheet to an entire DOM tree, returning a StyleNode tree
-pub fn style_tree<'a>(root: &'a Node, stylesheet: &'a StyleSheet) -> StyledNode<'a> {
+fn style_node<'a, N: DomNode<'a>>(
+    node: N,
+    stylesheets: &[(Origin, &StyleSheet)],
+    parent: Option<(&PropertyMap, &LengthContext)>,
+) -> StyledNode<'a, N> {
+    let is_element = node.tag_name().is_some();
+    let specified = match is_element {
+        true => specified_values(node, stylesheets, None),
+        false => HashMap::new(),
+    };
+    let (values, length_context) = computed_values(specified, parent);
+
+    let mut children: Vec<StyledNode<'a, N>> = node
+        .children()
+        .map(|child| style_node(child, stylesheets, Some((&values, &length_context))))
+        .collect();
+    if is_element {
+        let parent = (&values, &length_context);
+        if let Some(before) = generated_content(node, stylesheets, PseudoElement::Before, parent) {
+            children.insert(0, before);
+        }
+        if let Some(after) = generated_content(node, stylesheets, PseudoElement::After, parent) {
+            children.push(after);
+        }
+        split_first_letter(node, stylesheets, parent, &mut children);
+    }
+
+    // Number the list items among the children, for ordered lists
+    let mut count = 0;
+    for child in &mut children {
+        if let Display::ListItem = child.display() {
+            count += 1;
+            child.marker = list_marker(child, count).map(Box::new);
+        }
+    }
+
     StyledNode {
-        node: root,
-        specified_values: match root.node_type {
-            NodeType::Element(ref elem) => specified_values(elem, stylesheet),
-            NodeType::Text(_) => HashMap::new(),
-        },
-        children: root
-            .children
+        node: Content::Node(node),
+        children,
+        specified_values: values,
+        length_context,
+        marker: None,
+    }
+}
+
+// The stylesheets of a document in document order: the contents of its `<style>` elements,
+// and the stylesheets its `<link rel="stylesheet">` elements refer to, which `resolver`
+// loads given their `href`. Links the resolver returns `None` for, stylesheets whose
+// `media` doesn't match a screen, and malformed stylesheets are skipped
+pub fn extract_stylesheets(
+    doc: &Node,
+    resolver: &mut dyn FnMut(&str) -> Option<String>,
+) -> Vec<StyleSheet> {
+    document_stylesheets(doc, resolver)
+        .into_iter()
+        .filter_map(|(_, text)| css::Parser::try_parse(text).ok())
+        .collect()
+}
+
+// Like `extract_stylesheets`, returning the text of each stylesheet before it's parsed,
+// with the `href` it was loaded from, or `None` for a `<style>` element
+pub fn document_stylesheets(
+    doc: &Node,
+    resolver: &mut dyn FnMut(&str) -> Option<String>,
+) -> Vec<(Option<String>, String)> {
+    let mut sheets = Vec::new();
+    collect_stylesheets(doc, resolver, &mut sheets);
+    sheets
+}
+
+fn collect_stylesheets(
+    node: &Node,
+    resolver: &mut dyn FnMut(&str) -> Option<String>,
+    sheets: &mut Vec<(Option<String>, String)>,
+) {
+    if let NodeType::Element(ref elem) = node.node_type {
+        let for_screen = elem
+            .attribute("media")
+            .is_none_or(|m| css::media_matches(m));
+        let rel = elem.attribute("rel").map_or("", |rel| rel);
+        let is_stylesheet = rel
+            .split_whitespace()
+            .any(|rel| rel.eq_ignore_ascii_case("stylesheet"));
+        match &*elem.tag_name.to_ascii_lowercase() {
+            "style" if for_screen => sheets.push((None, node.text_content())),
+            "link" if for_screen && is_stylesheet => {
+                if let Some(href) = elem.attribute("href") {
+                    if let Some(text) = resolver(href) {
+                        sheets.push((Some(href.clone()), text));
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    for child in &node.children {
+        collect_stylesheets(child, resolver, sheets);
+    }
+}
+
+// Style the marker text of the `number`th list item in its parent, based on its
+// `list-style-type`
+fn list_marker<'a, N>(list_item: &StyledNode<'a, N>, number: usize) -> Option<StyledNode<'a, N>> {
+    let text = match list_item.value("list-style-type") {
+        Some(Value::Keyword(ref k)) if k == "none" => return None,
+        Some(Value::Keyword(ref k)) if k == "decimal" => format!("{}.", number),
+        Some(Value::Keyword(ref k)) if k == "circle" => "◦".to_string(),
+        Some(Value::Keyword(ref k)) if k == "square" => "▪".to_string(),
+        _ => "•".to_string(), // disc
+    };
+    let parent = (&list_item.specified_values, &list_item.length_context);
+    let (values, length_context) = computed_values(HashMap::new(), Some(parent));
+    Some(StyledNode {
+        node: Content::Text(Cow::Owned(text)),
+        children: Vec::new(),
+        specified_values: values,
+        length_context,
+        marker: None,
+    })
+}
+
+// Style the text generated by a `::before` or `::after` rule with a string `content` value
+fn generated_content<'a, N: DomNode<'a>>(
+    elem: N,
+    stylesheets: &[(Origin, &StyleSheet)],
+    pseudo_element: PseudoElement,
+    parent: (&PropertyMap, &LengthContext),
+) -> Option<StyledNode<'a, N>> {
+    let specified = specified_values(elem, stylesheets, Some(pseudo_element));
+    let content = match specified.get("content") {
+        Some(Value::StringValue(content)) => content.clone(),
+        _ => return None,
+    };
+    let (values, length_context) = computed_values(specified, Some(parent));
+    Some(StyledNode {
+        node: Content::Text(Cow::Owned(content)),
+        children: Vec::new(),
+        specified_values: values,
+        length_context,
+        marker: None,
+    })
+}
+
+// Split the first letter of an element's text off into a text node of its own, styled by
+// the element's `::first-letter` rules. Whitespace before the letter goes with it. The
+// letter is only looked for in text that comes before any child element
+fn split_first_letter<'a, N: DomNode<'a>>(
+    elem: N,
+    stylesheets: &[(Origin, &StyleSheet)],
+    parent: (&PropertyMap, &LengthContext),
+    children: &mut Vec<StyledNode<'a, N>>,
+) {
+    let specified = specified_values(elem, stylesheets, Some(PseudoElement::FirstLetter));
+    if specified.is_empty() {
+        return;
+    }
+    for i in 0..children.len() {
+        let text: Cow<'a, str> = match children[i].node {
+            Content::Node(node) => match node.text() {
+                Some(text) => Cow::Borrowed(text),
+                None => return,
+            },
+            Content::Text(ref text) => text.clone(),
+        };
+        let Some((start, c)) = text.char_indices().find(|(_, c)| !c.is_whitespace()) else {
+            continue;
+        };
+        let split = start + c.len_utf8();
+        let (letter, rest) = match text {
+            Cow::Borrowed(text) => (Cow::Borrowed(&text[..split]), Cow::Borrowed(&text[split..])),
+            Cow::Owned(text) => (
+                Cow::Owned(text[..split].to_string()),
+                Cow::Owned(text[split..].to_string()),
+            ),
+        };
+        let (values, length_context) = computed_values(specified, Some(parent));
+        children[i].node = Content::Text(rest);
+        children.insert(
+            i,
+            StyledNode {
+                node: Content::Text(letter),
+                children: Vec::new(),
+                specified_values: values,
+                length_context,
+                marker: None,
+            },
+        );
+        return;
+    }
+}
+
+// Combine the inherited values with a node's specified values, and resolve its lengths
+fn computed_values(
+    specified: PropertyMap,
+    parent: Option<(&PropertyMap, &LengthContext)>,
+) -> (PropertyMap, LengthContext) {
+    // Start from the inherited values, then apply the cascade on top
+    let mut values: PropertyMap = match parent {
+        Some((parent_values, _)) => INHERITED_PROPERTIES
             .iter()
-            .map(|child| style_tree(child, stylesheet))
+            .filter_map(|&name| parent_values.get_key_value(name))
+            .map(|(name, value)| (name.clone(), value.clone()))
             .collect(),
-    }
+        None => HashMap::new(),
+    };
+    values.extend(specified);
+    resolve_global_keywords(&mut values, parent.map(|(parent_values, _)| parent_values));
+    resolve_current_color(&mut values, parent.map(|(parent_values, _)| parent_values));
+
+    let length_context = compute_font_size(&values, parent.map(|(_, context)| context));
+    resolve_font_relative_lengths(&mut values, &length_context);
+    compute_line_height(&mut values, &length_context);
+    (values, length_context)
 }
 
-// Apply styles to a single element, returning the specified values
-fn specified_values(elem: &ElementData, stylesheet: &StyleSheet) -> PropertyMap {
-    let mut values = HashMap::new();
-    let mut rules = matching_rules(elem, stylesheet);
+// Resolve the keywords that any property can take: `inherit` takes the parent's value,
+// and `initial` the property's initial value. Properties only have an initial value
+// where they're looked up, e.g. `inline` in `display()`, so that's the same as not having
+// a value, except for `font-size`, which would otherwise be inherited
+fn resolve_global_keywords(values: &mut PropertyMap, parent: Option<&PropertyMap>) {
+    let keyword = |value: &Value, keyword: &str| matches!(value, Value::Keyword(k) if k.eq_ignore_ascii_case(keyword));
+    let names: Vec<Atom> = values
+        .iter()
+        .filter(|(_, value)| keyword(value, "inherit") || keyword(value, "initial"))
+        .map(|(name, _)| name.clone())
+        .collect();
+    for name in names {
+        let value = match keyword(&values[name.as_str()], "inherit") {
+            true => parent.and_then(|parent| parent.get(name.as_str())).cloned(),
+            false if name == "font-size" => Some(Value::Length(DEFAULT_FONT_SIZE, Unit::Px)),
+            false => None,
+        };
+        match value {
+            Some(value) => values.insert(name, value),
+            None => values.remove(name.as_str()),
+        };
+    }
+}
 
-    // Go through the rules from lowest to highest specificity
-    rules.sort_by(|&(a, _), &(b, _)| a.cmp(&b));
-    for (_, rule) in rules {
-        for declaration in &rule.declarations {
-            values.insert(declaration.name.clone(), declaration.value.clone());
+// Replace `currentColor` with the node's computed `color`. In `color` itself, it means
+// the inherited color. Where there's no color to use, it's the initial color, black
+fn resolve_current_color(values: &mut PropertyMap, parent: Option<&PropertyMap>) {
+    let is_current_color = |value: &Value| matches!(value, Value::Keyword(k) if k.eq_ignore_ascii_case("currentcolor"));
+    let black = Value::ColorValue(Color {
+        r: 0,
+        g: 0,
+        b: 0,
+        a: 255,
+    });
+    if let Some(color) = values
+        .get_mut("color")
+        .filter(|color| is_current_color(color))
+    {
+        let inherited = parent.and_then(|parent| parent.get("color"));
+        *color = inherited.unwrap_or(&black).clone();
+    }
+    let color = values.get("color").cloned().unwrap_or(black);
+    for value in values.values_mut() {
+        if is_current_color(value) {
+            *value = color.clone();
         }
     }
-    values
 }
 
-fn matches(elem: &ElementData, selector: &Selector) -> bool {
-    match *selector {
-        Selector::Simple(ref simple_selector) => matches_simple_selector(elem, simple_selector),
+// Compute the absolute font-size of a node from its specified value and its parent's font-size
+fn compute_font_size(values: &PropertyMap, parent: Option<&LengthContext>) -> LengthContext {
+    let parent_font_size = parent.map_or(DEFAULT_FONT_SIZE, |p| p.font_size);
+    let root_font_size = parent.map_or(DEFAULT_FONT_SIZE, |p| p.root_font_size);
+    let font_size = match values.get("font-size") {
+        Some(&Value::Length(size, Unit::Px)) => size,
+        Some(&Value::Length(size, Unit::Em)) => size * parent_font_size,
+        Some(&Value::Length(size, Unit::Rem)) => size * root_font_size,
+        _ => parent_font_size, // font-size is inherited
+    };
+    LengthContext {
+        font_size,
+        // The root element's font-size is the base for `rem` everywhere else
+        root_font_size: parent.map_or(font_size, |p| p.root_font_size),
     }
 }
 
-fn matches_simple_selector(elem: &ElementData, selector: &SimpleSelector) -> bool {
-    // Check type selector
-    if selector.tag_name.iter().any(|name| elem.tag_name != *name) {
-        return false;
+// Convert `em` and `rem` lengths to px once the node's font size is known, so that
+// inherited values and later lookups see absolute lengths
+fn resolve_font_relative_lengths(values: &mut PropertyMap, context: &LengthContext) {
+    for (name, value) in values.iter_mut() {
+        if name == "font-size" {
+            // `em` in font-size refers to the parent, which `compute_font_size` handled
+            if let Value::Length(_, Unit::Em | Unit::Rem) = value {
+                *value = Value::Length(context.font_size, Unit::Px);
+            }
+        } else if let Value::Length(_, Unit::Em | Unit::Rem) = value {
+            *value = value.resolve(context);
+        }
     }
+}
 
-    // Check id selector
-    if selector.id.iter().any(|id| elem.id() != Some(id)) {
-        return false;
+// Resolve a relative `line-height` length against the node's own font size, so that
+// children inherit the absolute value. A unitless number is inherited as-is, and is
+// multiplied by each descendant's own font size instead
+fn compute_line_height(values: &mut PropertyMap, context: &LengthContext) {
+    if let Some(value @ Value::Length(..)) = values.get_mut("line-height") {
+        let px = match *value {
+            Value::Length(size, Unit::Percent) => size / 100.0 * context.font_size,
+            _ => value.resolve(context).to_px(),
+        };
+        *value = Value::Length(px, Unit::Px);
     }
+}
 
-    // Check class selectors
-    let elem_classes = elem.classes();
-    if selector
-        .class
+// Apply styles to a single element, or one of its pseudo-elements, returning the specified values
+fn specified_values<'a>(
+    elem: impl DomNode<'a>,
+    stylesheets: &[(Origin, &StyleSheet)],
+    pseudo_element: Option<PseudoElement>,
+) -> PropertyMap {
+    let mut values = HashMap::new();
+    let matched: Vec<(Origin, Vec<MatchedRule>)> = stylesheets
         .iter()
-        .any(|class| !elem_classes.contains(&**class))
-    {
-        return false;
+        .map(|&(origin, stylesheet)| (origin, matching_rules(elem, stylesheet, pseudo_element)))
+        .collect();
+
+    // Go through the normal declarations origin by origin, and then the `!important`
+    // ones, which override all the normal ones, with the origins reversed. Within an
+    // origin, rules go from lowest to highest specificity
+    let origins = [Origin::UserAgent, Origin::User, Origin::Author];
+    let normal = origins.iter().map(|&origin| (origin, false));
+    let important = origins.iter().rev().map(|&origin| (origin, true));
+    for (origin, important) in normal.chain(important) {
+        let mut rules: Vec<&MatchedRule> = matched
+            .iter()
+            .filter(|(rules_origin, _)| *rules_origin == origin)
+            .flat_map(|(_, rules)| rules)
+            .collect();
+        rules.sort_by_key(|&&(a, _, _)| a);
+        for (_, selector, rule) in rules {
+            for declaration in rule.declarations.iter() {
+                if declaration.important == important {
+                    let name = declaration.name.clone();
+                    let old = values.insert(name, declaration.value.clone());
+                    trace!(
+                        "{}: {}: {}{} (from {})",
+                        target_label(elem, pseudo_element),
+                        declaration.name,
+                        declaration.value,
+                        old.map_or(String::new(), |old| format!(", overriding {}", old)),
+                        selector
+                    );
+                }
+            }
+        }
     }
+    values
+}
 
-    true
+/// Return true if `selector` matches the node. Text nodes never match. Selectors with a pseudo-element match
+/// the pseudo-element rather than the element itself, so they never match.
+///
+/// ```
+/// use robinson::{css, dom, style};
+///
+/// let stylesheet = css::Parser::parse("p.note#intro {}".to_string());
+/// let selector = &stylesheet.rules[0].selectors[0];
+/// let node = dom::Parser::parse("<p class=\"note big\" id=\"intro\">Hi</p>".to_string());
+/// assert!(style::matches(&node, selector));
+/// assert!(!style::matches(&node.children[0], selector));
+/// ```
+pub fn matches<'a>(node: impl DomNode<'a>, selector: &Selector) -> bool {
+    selector.matches(node)
 }
 
-pub type MatchedRule<'a> = (Specificity, &'a Rule);
+// A rule that matched, with the selector in it that matched
+type MatchedRule<'a> = (Specificity, &'a Selector, &'a Rule);
 
-// If `rule` matches `elem`, return a `MatchedRule`. Otherwise return `Node`
-fn match_rule<'a>(elem: &ElementData, rule: &'a Rule) -> Option<MatchedRule<'a>> {
+// If `rule` matches `elem` (or the given pseudo-element of it), return a `MatchedRule`.
+// Otherwise return `None`
+fn match_rule<'a, 'n>(
+    elem: impl DomNode<'n>,
+    rule: &'a Rule,
+    pseudo_element: Option<PseudoElement>,
+) -> Option<MatchedRule<'a>> {
     // Find the first (highest-specificity) matching selector
     rule.selectors
         .iter()
-        .find(|selector| matches(elem, *selector))
-        .map(|selector| (selector.specificity(), rule))
+        .find(|selector| {
+            let Selector::Simple(ref simple) = **selector;
+            if simple.pseudo_element != pseudo_element {
+                return false;
+            }
+            trace!(
+                "{}: {}",
+                target_label(elem, pseudo_element),
+                match mismatch(elem, simple) {
+                    None => format!("matches {}", selector),
+                    Some(why) => format!("doesn't match {}: {}", selector, why),
+                }
+            );
+            simple.matches(elem)
+        })
+        .map(|selector| (selector.specificity(), selector, rule))
+}
+
+// Describe an element, or one of its pseudo-elements, for tracing
+fn target_label<'a>(elem: impl DomNode<'a>, pseudo_element: Option<PseudoElement>) -> String {
+    match pseudo_element {
+        Some(pseudo_element) => format!("{}::{}", elem.label(), pseudo_element),
+        None => elem.label(),
+    }
+}
+
+// Why `selector` doesn't match `elem`, or `None` if it does
+fn mismatch<'a>(elem: impl DomNode<'a>, selector: &css::SimpleSelector) -> Option<String> {
+    if let Some(tag_name) = selector
+        .tag_name
+        .as_ref()
+        .filter(|name| elem.tag_name().is_none_or(|tag_name| **name != tag_name))
+    {
+        return Some(format!("the tag name isn't {}", tag_name));
+    }
+    if let Some(id) = selector
+        .id
+        .as_ref()
+        .filter(|id| elem.attribute("id") != Some(id.as_str()))
+    {
+        return Some(format!("the id isn't {}", id));
+    }
+    let classes = elem.attribute("class").unwrap_or_default();
+    let missing = selector
+        .class
+        .iter()
+        .find(|class| !classes.split_whitespace().any(|c| ***class == *c));
+    missing.map(|class| format!("it has no class {}", class))
 }
 
-// Find all CSS Rules that match the given element
-fn matching_rules<'a>(elem: &ElementData, stylesheet: &'a StyleSheet) -> Vec<MatchedRule<'a>> {
+// Find all CSS Rules that match the given element or pseudo-element
+fn matching_rules<'a, 'n>(
+    elem: impl DomNode<'n>,
+    stylesheet: &'a StyleSheet,
+    pseudo_element: Option<PseudoElement>,
+) -> Vec<MatchedRule<'a>> {
     stylesheet
         .rules
         .iter()
-        .filter_map(|rule| match_rule(elem, rule))
+        .filter_map(|rule| match_rule(elem, rule, pseudo_element))
         .collect()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{css, dom};
+
+    #[test]
+    fn em_lengths_resolve_against_computed_font_size() {
+        let root = dom::Parser::parse("<div><p><span></span></p></div>".to_string());
+        let stylesheet = css::Parser::parse(
+            "div { font-size: 20px; }
+             p { margin: 2em; }
+             span { font-size: 1.5em; padding: 1em; }"
+                .to_string(),
+        );
+        let div = style_tree(&root, &stylesheet);
+        let p = &div.children[0];
+        let span = &p.children[0];
+
+        assert_eq!(Some(Value::Length(40.0, Unit::Px)), p.value("margin"));
+        assert_eq!(Some(Value::Length(30.0, Unit::Px)), span.value("font-size"));
+        assert_eq!(Some(Value::Length(30.0, Unit::Px)), span.value("padding"));
+    }
+
+    #[test]
+    fn important_declarations_win() {
+        let root = dom::Parser::parse("<p id=\"intro\" class=\"note\"></p>".to_string());
+        let stylesheet = css::Parser::parse(
+            "p { color: #ff0000 !important; display: inline !important; }
+             p.note { display: block !important; }
+             #intro { color: #0000ff; display: none; }"
+                .to_string(),
+        );
+        let p = style_tree(&root, &stylesheet);
+
+        assert_eq!(
+            Some(Value::ColorValue(css::Color {
+                r: 255,
+                g: 0,
+                b: 0,
+                a: 255
+            })),
+            p.value("color")
+        );
+        // Between important declarations, specificity decides as usual
+        assert_eq!(
+            Some(Value::Keyword("block".to_string())),
+            p.value("display")
+        );
+    }
+
+    #[test]
+    fn cascade_origins() {
+        let root = dom::Parser::parse("<p id=\"intro\"></p>".to_string());
+        let user_agent = css::Parser::parse(
+            "#intro { display: block; color: red !important; font-style: italic; }".to_string(),
+        );
+        let user = css::Parser::parse("p { font-style: normal !important; }".to_string());
+        let author = css::Parser::parse(
+            "p { display: inline; color: blue; font-style: oblique !important; }".to_string(),
+        );
+        let p = style_tree_with_origins(
+            &root,
+            &[
+                (Origin::Author, &author),
+                (Origin::User, &user),
+                (Origin::UserAgent, &user_agent),
+            ],
+        );
+        let keyword = |name: &str| match p.value(name) {
+            Some(Value::Keyword(keyword)) => keyword,
+            value => panic!("{:?}", value),
+        };
+
+        // An author normal declaration beats a more specific user-agent one
+        assert_eq!("inline", keyword("display"));
+        // A user-agent `!important` declaration beats an author normal one
+        assert_eq!("#ff0000", p.value("color").unwrap().to_string());
+        // Between `!important` declarations, user beats author
+        assert_eq!("normal", keyword("font-style"));
+    }
+
+    #[test]
+    fn rem_lengths_resolve_against_root_font_size() {
+        let root = dom::Parser::parse("<html><div><p><span></span></p></div></html>".to_string());
+        let stylesheet = css::Parser::parse(
+            "html { font-size: 10px; }
+             div { font-size: 3em; }
+             p { font-size: 2rem; margin: 1.5rem; }
+             span { padding: 1rem; }"
+                .to_string(),
+        );
+        let html = style_tree(&root, &stylesheet);
+        let div = &html.children[0];
+        let p = &div.children[0];
+        let span = &p.children[0];
+
+        assert_eq!(Some(Value::Length(30.0, Unit::Px)), div.value("font-size"));
+        assert_eq!(Some(Value::Length(20.0, Unit::Px)), p.value("font-size"));
+        assert_eq!(Some(Value::Length(15.0, Unit::Px)), p.value("margin"));
+        assert_eq!(Some(Value::Length(10.0, Unit::Px)), span.value("padding"));
+    }
+
+    #[test]
+    fn display_tree() {
+        let root = dom::Parser::parse("<div class=\"note\"><p>hi</p></div>".to_string());
+        let stylesheet = css::Parser::parse(
+            "div { margin: 4px; display: block; } p { color: red; }".to_string(),
+        );
+        let text = style_tree(&root, &stylesheet).to_string();
+        assert_eq!(
+            "div.note { display: block; margin: 4px; }\n  \
+             p { color: #ff0000; }\n    \
+             \"hi\" { color: #ff0000; }\n",
+            text
+        );
+    }
+
+    #[test]
+    fn display_inherit_and_initial() {
+        let root =
+            dom::Parser::parse("<div><span><em></em></span><p></p><b></b></div>".to_string());
+        let mut stylesheet = css::Parser::parse(DEFAULT_CSS.to_string());
+        stylesheet.merge(css::Parser::parse(
+            "span, em { display: inherit; } p { display: initial; } b { display: INHERIT; }"
+                .to_string(),
+        ));
+        let div = style_tree(&root, &stylesheet);
+        let (span, p, b) = (&div.children[0], &div.children[1], &div.children[2]);
+
+        // `div` is a block from the default stylesheet alone, and `inherit` passes that down
+        assert!(matches!(div.display(), Display::Block));
+        assert!(matches!(span.display(), Display::Block));
+        assert!(matches!(span.children[0].display(), Display::Block));
+        assert!(matches!(b.display(), Display::Block));
+        // `initial` overrides the default stylesheet's `display: block`
+        assert!(matches!(p.display(), Display::Inline));
+        assert_eq!(None, p.value("display"));
+
+        // With no parent, `inherit` gives the initial value
+        let root = dom::Parser::parse("<div></div>".to_string());
+        let stylesheet = css::Parser::parse("div { display: inherit; }".to_string());
+        assert!(matches!(
+            style_tree(&root, &stylesheet).display(),
+            Display::Inline
+        ));
+    }
+
+    #[test]
+    fn tag_name() {
+        let root = dom::Parser::parse("<div><p>text</p></div>".to_string());
+        let stylesheet = css::Parser::parse(String::new());
+        let div = style_tree(&root, &stylesheet);
+        assert_eq!(Some("div"), div.tag_name());
+        assert_eq!(Some("p"), div.children[0].tag_name());
+        assert_eq!(None, div.children[0].children[0].tag_name());
+    }
+
+    #[test]
+    fn match_selectors_against_nodes() {
+        let root = dom::Parser::parse("<div id=\"main\" class=\"a b\">text</div>".to_string());
+        let stylesheet = css::Parser::parse("div.a.b, #main, p, .c, div::before {}".to_string());
+        let selectors = &stylesheet.rules[0].selectors;
+        let matched: Vec<bool> = selectors.iter().map(|s| matches(&root, s)).collect();
+        // Selectors are sorted by specificity
+        assert_eq!(vec![true, true, false, false, false], matched);
+        assert!(!matches(&root.children[0], &selectors[0]));
+    }
+
+    #[test]
+    fn current_color_resolves_to_color() {
+        let root = dom::Parser::parse("<div><p></p><span></span></div>".to_string());
+        let stylesheet = css::Parser::parse(
+            "div { color: red; border-color: currentColor; }
+             p { color: currentcolor; background: currentColor; }
+             span { color: blue; border-left-color: currentColor; }"
+                .to_string(),
+        );
+        let div = style_tree(&root, &stylesheet);
+        let (p, span) = (&div.children[0], &div.children[1]);
+        let color = |r, b| Some(Value::ColorValue(Color { r, g: 0, b, a: 255 }));
+        assert_eq!(color(255, 0), div.value("border-color"));
+        assert_eq!(color(255, 0), p.value("color"));
+        assert_eq!(color(255, 0), p.value("background"));
+        assert_eq!(color(0, 255), span.value("border-left-color"));
+
+        // At the root, `color: currentColor` falls back to black
+        let root = dom::Parser::parse("<div></div>".to_string());
+        let stylesheet = css::Parser::parse(
+            "div { color: currentColor; border-color: currentColor; }".to_string(),
+        );
+        let div = style_tree(&root, &stylesheet);
+        assert_eq!(color(0, 0), div.value("color"));
+        assert_eq!(color(0, 0), div.value("border-color"));
+    }
+
+    #[test]
+    fn first_letter() {
+        let root =
+            dom::Parser::parse("<div><p>Hello</p><h1><b>Hi</b> there</h1></div>".to_string());
+        let stylesheet = css::Parser::parse(
+            "div { font-size: 16px; }
+             p::first-letter, h1::first-letter { font-size: 2em; }
+             p::first-line { font-size: 3em; }"
+                .to_string(),
+        );
+        let div = style_tree(&root, &stylesheet);
+        fn text_content(node: &StyledNode) -> String {
+            match node.text() {
+                Some(text) => text.to_string(),
+                None => node.children.iter().map(text_content).collect(),
+            }
+        }
+        let texts = |node: &StyledNode| -> Vec<(String, f32)> {
+            node.children
+                .iter()
+                .map(|child| (text_content(child), child.length_context.font_size))
+                .collect()
+        };
+        assert_eq!(
+            vec![("H".to_string(), 32.0), ("ello".to_string(), 16.0)],
+            texts(&div.children[0])
+        );
+        // Text inside a child element isn't split
+        assert_eq!(
+            vec![("Hi".to_string(), 16.0), ("there".to_string(), 16.0)],
+            texts(&div.children[1])
+        );
+    }
+
+    #[test]
+    fn stylesheets_in_the_document() {
+        let root = dom::Parser::parse(
+            "<html>
+               <head>
+                 <style>p { display: block; }</style>
+                 <link rel=\"stylesheet\" href=\"theme.css\">
+                 <link rel=\"stylesheet\" href=\"missing.css\">
+                 <style media=\"print\">p { display: none; }</style>
+                 <link rel=\"Alternate StyleSheet\" media=\"screen and (min-width: 600px)\"
+                       href=\"wide.css\">
+               </head>
+               <p class=\"note\"></p>
+             </html>"
+                .to_string(),
+        );
+        let mut requested = Vec::new();
+        let mut resolver = |href: &str| {
+            requested.push(href.to_string());
+            match href {
+                "theme.css" => Some(".note { background: #0000ff; }".to_string()),
+                "wide.css" => Some("p { color: #ff0000; }".to_string()),
+                _ => None,
+            }
+        };
+        let mut stylesheets = extract_stylesheets(&root, &mut resolver);
+        assert_eq!(vec!["theme.css", "missing.css", "wide.css"], requested);
+        assert_eq!(3, stylesheets.len());
+
+        // The element is styled by both the `<style>` element and the linked stylesheets
+        let mut stylesheet = stylesheets.remove(0);
+        for other in stylesheets {
+            stylesheet.merge(other);
+        }
+        let html = style_tree(&root, &stylesheet);
+        let p = &html.children[1];
+        assert_eq!(
+            "p.note { background: #0000ff; color: #ff0000; display: block; }",
+            p.to_string().trim_end()
+        );
+    }
+
+    // Events are captured per thread, so tests running at the same time don't mix
+    #[cfg(feature = "trace")]
+    fn capture_events(f: impl FnOnce()) -> Vec<String> {
+        use std::cell::RefCell;
+
+        thread_local! {
+            static EVENTS: RefCell<Option<Vec<String>>> = const { RefCell::new(None) };
+        }
+
+        struct Capture;
+
+        impl log::Log for Capture {
+            fn enabled(&self, metadata: &log::Metadata) -> bool {
+                metadata.target().starts_with("robinson::style")
+            }
+
+            fn log(&self, record: &log::Record) {
+                if self.enabled(record.metadata()) {
+                    EVENTS.with_borrow_mut(|events| {
+                        if let Some(events) = events {
+                            events.push(record.args().to_string());
+                        }
+                    });
+                }
+            }
+
+            fn flush(&self) {}
+        }
+
+        static CAPTURE: Capture = Capture;
+        let _ = log::set_logger(&CAPTURE);
+        log::set_max_level(log::LevelFilter::Debug);
+        EVENTS.set(Some(Vec::new()));
+        f();
+        EVENTS.take().unwrap()
+    }
+
+    #[cfg(feature = "trace")]
+    #[test]
+    fn trace_matching_and_cascade() {
+        let root = dom::Parser::parse("<div><p class=\"note\"></p><span></span></div>".to_string());
+        let stylesheet =
+            css::Parser::parse("p.note, span { color: red; color: blue; }".to_string());
+        let events = capture_events(|| {
+            style_tree(&root, &stylesheet);
+        });
+        assert_eq!(
+            vec![
+                "div: doesn't match p.note: the tag name isn't p",
+                "div: doesn't match span: the tag name isn't span",
+                "p.note: matches p.note",
+                "p.note: color: #ff0000 (from p.note)",
+                "p.note: color: #0000ff, overriding #ff0000 (from p.note)",
+                "span: doesn't match p.note: the tag name isn't p",
+                "span: matches span",
+                "span: color: #ff0000 (from span)",
+                "span: color: #0000ff, overriding #ff0000 (from span)",
+            ],
+            events
+        );
+    }
+}
@@ -0,0 +1,115 @@
+// Entry points for running the engine from JavaScript. These are the Rust side, returning
+// the crate's `Error`; with the `wasm` feature, `js` exports them through `wasm-bindgen`
+// under the same names, with errors thrown in JavaScript as `Error`s.
+//
+// Like `pipeline::render` and the C interface, they apply the default stylesheet before
+// the page's CSS
+
+use crate::{pipeline, Error, RenderOptions, Viewport};
+
+fn viewport(width: u32, height: u32) -> Viewport {
+    Viewport {
+        width: width as f32,
+        height: height as f32,
+    }
+}
+
+// Render a page to a `width` x `height` viewport, returning its pixels as 8-bit RGBA
+// values row by row, ready for e.g. a canvas `ImageData`
+pub fn render_to_rgba(html: &str, css: &str, width: u32, height: u32) -> Result<Vec<u8>, Error> {
+    let canvas = pipeline::render(html, css, viewport(width, height))?;
+    Ok(canvas.to_rgba())
+}
+
+// Lay out a page in a `width` x `height` viewport, and describe the layout tree one box
+// per line, as the CLI's `--dump-layout` does. Empty if the root has `display: none`
+pub fn dump_layout(html: &str, css: &str, width: u32, height: u32) -> Result<String, Error> {
+    let options = RenderOptions::default();
+    let stylesheet = pipeline::stylesheet(&[css], &options)?;
+    let layout = pipeline::with_layout(
+        html,
+        &stylesheet,
+        viewport(width, height),
+        &options,
+        |tree| tree.root.to_string(),
+    )?;
+    Ok(layout.unwrap_or_default())
+}
+
+// The functions exported to JavaScript
+#[cfg(feature = "wasm")]
+pub mod js {
+    use wasm_bindgen::prelude::{wasm_bindgen, JsError, JsValue};
+
+    use crate::dom;
+
+    // Parse a page into its DOM, as a JavaScript object in the same shape as the DOM's JSON
+    // serialization, e.g. `{"node_type": {"element": {"tag_name": "p", ...}}, "children": []}`
+    #[wasm_bindgen]
+    pub fn parse_html(html: &str) -> Result<JsValue, JsError> {
+        let json = serde_json::to_string(&dom::parse(html)?)?;
+        js_sys::JSON::parse(&json).map_err(|_| JsError::new("couldn't convert the DOM"))
+    }
+
+    #[wasm_bindgen]
+    pub fn render_to_rgba(
+        html: &str,
+        css: &str,
+        width: u32,
+        height: u32,
+    ) -> Result<Vec<u8>, JsError> {
+        Ok(super::render_to_rgba(html, css, width, height)?)
+    }
+
+    #[wasm_bindgen]
+    pub fn dump_layout(html: &str, css: &str, width: u32, height: u32) -> Result<String, JsError> {
+        Ok(super::dump_layout(html, css, width, height)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_to_rgba_returns_rgba_rows() {
+        let pixels = render_to_rgba(
+            "<div><p></p></div>",
+            "div { display: block; height: 2px; background: #ff0000; }
+             p { display: block; width: 1px; height: 1px; background: #0000ff80; }",
+            3,
+            4,
+        )
+        .unwrap();
+        assert_eq!(3 * 4 * 4, pixels.len());
+        let pixel = |x: usize, y: usize| &pixels[(y * 3 + x) * 4..][..4];
+        assert_eq!([127, 0, 128, 255], pixel(0, 0));
+        assert_eq!([255, 0, 0, 255], pixel(2, 1));
+        assert_eq!([255, 255, 255, 255], pixel(0, 3));
+    }
+
+    #[test]
+    fn dump_layout_lists_boxes() {
+        let css = "div, p { height: 2px; }";
+        let layout = dump_layout("<div><p></p></div>", css, 10, 10).unwrap();
+        // The boxes are blocks from the default stylesheet
+        assert_eq!(2, layout.lines().count());
+        assert!(layout.lines().next().unwrap().contains("BlockNode(div)"));
+        assert_eq!(
+            "",
+            dump_layout("<div></div>", "div { display: none; }", 10, 10).unwrap()
+        );
+    }
+
+    #[test]
+    fn malformed_pages_are_errors() {
+        assert!(matches!(
+            render_to_rgba("<div", "", 1, 1),
+            Err(Error::HtmlParse(_))
+        ));
+        assert!(matches!(
+            dump_layout("<div></div>", "div > p {}", 1, 1),
+            Err(Error::CssParse(0, _))
+        ));
+    }
+}
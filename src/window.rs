@@ -0,0 +1,146 @@
+// A live preview of a page in a window, with the `window` feature. The page is laid out
+// again whenever the window is resized, using the window's size as the viewport. The
+// rendering itself doesn't need a window, so it's available without the feature
+
+#[cfg(feature = "window")]
+use minifb::{Key, Window, WindowOptions};
+
+use crate::pipeline::{self, RenderOptions, Viewport};
+use crate::Error;
+
+// A page whose HTML and stylesheets have been checked, ready to be rendered at any size
+pub struct Preview {
+    html: String,
+    css: Vec<String>,
+    options: RenderOptions,
+}
+
+impl Preview {
+    // Check that the page and its stylesheets parse, so errors in them are found before
+    // a window opens. `full_height` is ignored, since the window is the viewport
+    pub fn new(html: &str, css: &[&str], options: RenderOptions) -> Result<Preview, Error> {
+        let options = RenderOptions {
+            full_height: false,
+            ..options
+        };
+        pipeline::parse_html(html, &options)?;
+        pipeline::parse_stylesheets(css)?;
+        Ok(Preview {
+            html: html.to_string(),
+            css: css.iter().map(|source| source.to_string()).collect(),
+            options,
+        })
+    }
+
+    // Lay out and paint the page in a `width` x `height` viewport, returning its pixels
+    // row by row as `0x00RRGGBB` values, the format window buffers take
+    pub fn render(&self, width: usize, height: usize) -> Result<Vec<u32>, Error> {
+        let css: Vec<&str> = self.css.iter().map(|source| &**source).collect();
+        let viewport = Viewport {
+            width: width as f32,
+            height: height as f32,
+        };
+        let rendering = pipeline::render_with_options(&self.html, &css, viewport, &self.options)?;
+        Ok(rendering
+            .canvas
+            .pixels
+            .iter()
+            .map(|c| (c.r as u32) << 16 | (c.g as u32) << 8 | c.b as u32)
+            .collect())
+    }
+}
+
+// Show a page in a window of `initial_size` (width, height) until it's closed or Escape is
+// pressed. Errors in the page are returned before the window opens, and errors from the
+// window itself are I/O errors
+#[cfg(feature = "window")]
+pub fn preview(
+    html: &str,
+    css: &[&str],
+    options: RenderOptions,
+    initial_size: (usize, usize),
+) -> Result<(), Error> {
+    let page = Preview::new(html, css, options)?;
+    let window_options = WindowOptions {
+        resize: true,
+        ..WindowOptions::default()
+    };
+    let window_error = |e: minifb::Error| Error::Io(std::io::Error::other(e));
+    let (mut width, mut height) = initial_size;
+    let mut window =
+        Window::new("robinson", width, height, window_options).map_err(window_error)?;
+    window.set_target_fps(60);
+
+    let mut pixels = page.render(width, height)?;
+    while window.is_open() && !window.is_key_down(Key::Escape) {
+        let size = window.get_size();
+        if size != (width, height) {
+            (width, height) = size;
+            pixels = page.render(width, height)?;
+        }
+        window
+            .update_with_buffer(&pixels, width, height)
+            .map_err(window_error)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_again_after_resize() {
+        let page = Preview::new(
+            "<div><p></p></div>",
+            &["div { display: block; height: 2px; background: #ff0000; }
+               p { display: block; margin-left: 2px; height: 1px; background: #0000ff; }"],
+            RenderOptions::default(),
+        )
+        .unwrap();
+        let (red, blue, white) = (0xff0000, 0x0000ff, 0xffffff);
+
+        let pixels = page.render(4, 3).unwrap();
+        assert_eq!(4 * 3, pixels.len());
+        assert_eq!(vec![red, red, blue, blue], pixels[..4]);
+        assert_eq!(vec![white; 4], pixels[8..]);
+
+        // The page is laid out again in the new viewport
+        let pixels = page.render(8, 2).unwrap();
+        assert_eq!(8 * 2, pixels.len());
+        assert_eq!(
+            vec![red, red, blue, blue, blue, blue, blue, blue],
+            pixels[..8]
+        );
+        assert_eq!(vec![red; 8], pixels[8..]);
+    }
+
+    #[test]
+    fn malformed_page_is_an_error() {
+        let new = |html: &str, css: &str| Preview::new(html, &[css], RenderOptions::default());
+        assert!(matches!(new("<div", ""), Err(Error::HtmlParse(_))));
+        assert!(matches!(
+            new("<div></div>", "div > p {}"),
+            Err(Error::CssParse(0, _))
+        ));
+    }
+
+    #[test]
+    fn render_options_apply() {
+        // Without the default stylesheet, the paragraphs are inline, so don't fill the width
+        let html = "<div><p></p></div>";
+        let css = "p { height: 1px; background: #ff0000; }";
+        let first_pixel = |default_css| {
+            let options = RenderOptions {
+                default_css,
+                ..RenderOptions::default()
+            };
+            Preview::new(html, &[css], options)
+                .unwrap()
+                .render(2, 1)
+                .unwrap()[0]
+        };
+        assert_eq!(0xff0000, first_pixel(true));
+        assert_eq!(0xffffff, first_pixel(false));
+    }
+}
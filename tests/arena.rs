@@ -0,0 +1,79 @@
+// How much parsing allocates: far less into an arena than into a tree of nodes, and in
+// either case nothing per character of text. This counts allocations with a global
+// allocator, so it's a test binary of its own. Allocations are counted per thread, so tests
+// running at the same time don't count each other's
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+
+use robinson::{arena::Document, dom};
+
+struct CountingAllocator;
+
+thread_local! {
+    // Const-initialized, so counting doesn't allocate and recurse into the allocator
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+fn count_allocation() {
+    let _ = ALLOCATIONS.try_with(|count| count.set(count.get() + 1));
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        count_allocation();
+        System.alloc(layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        count_allocation();
+        System.realloc(ptr, layout, new_size)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+// The number of allocations and reallocations `f` makes on this thread
+fn allocations(f: impl FnOnce()) -> usize {
+    let before = ALLOCATIONS.get();
+    f();
+    ALLOCATIONS.get() - before
+}
+
+#[test]
+fn arena_allocates_less() {
+    let item = "<li class=\"item\" title=\"An item\">Some <em>text</em> here</li>";
+    let html = format!("<ul>{}</ul>", item.repeat(1000));
+    // Intern the names first, and keep them alive, so neither count includes it
+    let _interned = dom::parse(&html).unwrap();
+
+    let tree = allocations(|| drop(dom::parse(&html).unwrap()));
+    let arena = allocations(|| drop(Document::parse(&html).unwrap()));
+    // About 8 per element for the tree, and a few dozen in all for the arena
+    assert!(tree > 5_000);
+    assert!(arena * 100 < tree);
+}
+
+#[test]
+fn tree_allocations_dont_depend_on_text_length() {
+    let page = |text: &str| {
+        format!(
+            "<div id=\"main\"><p title=\"{0}\">{0}</p><script>{0}</script></div>",
+            text
+        )
+    };
+    let (short, long) = (page("x"), page(&"some text ".repeat(1000)));
+    let _interned = dom::parse(&short).unwrap();
+
+    let short_count = allocations(|| drop(dom::parse(&short).unwrap()));
+    let long_count = allocations(|| drop(dom::parse(&long).unwrap()));
+    assert_eq!(short_count, long_count);
+    // 3 elements, 2 attributes and 2 text nodes, about one allocation each, and a few
+    // for the parser itself
+    assert!(short_count < 2 * 7);
+}
@@ -0,0 +1,260 @@
+// Running the `robinson` binary, checking its exit codes and what it prints to stderr
+
+use std::{
+    env, fs,
+    io::Write,
+    process::{self, Command, Stdio},
+};
+
+// Run the binary with `stdin` as its input, returning the exit code and stderr
+fn robinson(args: &[&str], stdin: &str) -> (i32, String) {
+    let (code, _, stderr) = robinson_output(args, stdin);
+    (code, stderr)
+}
+
+// Like `robinson`, also returning stdout
+fn robinson_output(args: &[&str], stdin: &str) -> (i32, String, String) {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_robinson"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(stdin.as_bytes())
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    (output.status.code().unwrap(), stdout, stderr)
+}
+
+#[test]
+fn usage_errors() {
+    let (code, stderr) = robinson(&["page.html", "--bogus"], "");
+    assert_eq!(1, code);
+    assert_eq!("robinson: unknown option: --bogus (see --help)\n", stderr);
+}
+
+#[test]
+fn nonexistent_file() {
+    let (code, stderr) = robinson(&["no/such/page.html"], "");
+    assert_eq!(3, code);
+    assert!(
+        stderr.starts_with("robinson: no/such/page.html: "),
+        "{}",
+        stderr
+    );
+    assert_eq!(1, stderr.lines().count(), "{}", stderr);
+}
+
+#[test]
+fn broken_html() {
+    let dir = env::temp_dir().join(format!("robinson-broken-{}", process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let page = dir.join("page.html");
+    fs::write(&page, "<div>\n  <p>Hello</div>\n").unwrap();
+    let page = page.to_str().unwrap();
+    let output = dir.join("page.png");
+
+    let (code, stderr) = robinson(&[page, "-o", output.to_str().unwrap()], "");
+    assert_eq!(2, code);
+    assert_eq!(
+        format!(
+            "robinson: {}:2:13: invalid HTML: expected </p>, found </div>\n",
+            page
+        ),
+        stderr
+    );
+    assert!(!output.exists());
+
+    // The same from stdin
+    let (code, stderr) = robinson(&["-", "--dump-dom"], "<div>\n  <p>Hello</div>\n");
+    assert_eq!(2, code);
+    assert!(stderr.starts_with("robinson: <stdin>:2:13: "), "{}", stderr);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn stylesheets_cascade_in_order() {
+    let dir = env::temp_dir().join(format!("robinson-cascade-{}", process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = |name: &str| dir.join(name).to_str().unwrap().to_string();
+    fs::write(path("reset.css"), "div { margin: 0px; color: #000000; }").unwrap();
+    fs::write(path("theme.css"), "div { color: #336699; }").unwrap();
+    fs::write(path("broken.css"), "div {}\ndiv > p {}").unwrap();
+    let html = "<div>hi</div>";
+
+    let (reset, theme) = (path("reset.css"), path("theme.css"));
+    let args = ["-", "--css", &reset, "--css", &theme, "--dump-style"];
+    let (code, stdout, _) = robinson_output(&args, html);
+    assert_eq!(0, code);
+    assert!(
+        stdout.starts_with("div { color: #336699; display: block; margin: 0px; }"),
+        "{}",
+        stdout
+    );
+
+    // Without the default styles, a div is inline
+    let (code, stdout, _) = robinson_output(&["-", "--no-default-css", "--dump-style"], html);
+    assert_eq!(0, code);
+    assert!(!stdout.contains("display: block"), "{}", stdout);
+
+    let broken = path("broken.css");
+    let (code, stderr) = robinson(
+        &["-", "--css", &reset, "--css", &broken, "--dump-style"],
+        html,
+    );
+    assert_eq!(2, code);
+    assert!(
+        stderr.starts_with(&format!("robinson: {}:2:5: invalid CSS: ", broken)),
+        "{}",
+        stderr
+    );
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn document_stylesheets() {
+    let dir = env::temp_dir().join(format!("robinson-document-css-{}", process::id()));
+    fs::create_dir_all(dir.join("css")).unwrap();
+    let path = |name: &str| dir.join(name).to_str().unwrap().to_string();
+    fs::write(
+        path("page.html"),
+        "<html>
+  <head>
+    <style>p { display: block; }</style>
+    <link rel=\"stylesheet\" href=\"css/theme.css\">
+  </head>
+  <p class=\"note\"></p>
+</html>",
+    )
+    .unwrap();
+    fs::write(path("css/theme.css"), ".note { color: #336699; }").unwrap();
+    fs::write(path("override.css"), ".note { color: #000000; }").unwrap();
+
+    // The `<style>` element and the linked stylesheet both apply, before `--css`
+    let page = path("page.html");
+    let (code, stdout, _) = robinson_output(&[&page, "--no-default-css", "--dump-style"], "");
+    assert_eq!(0, code);
+    assert!(
+        stdout.contains("p.note { color: #336699; display: block; }"),
+        "{}",
+        stdout
+    );
+    let overridden = path("override.css");
+    let args = [&*page, "--css", &overridden, "--dump-style"];
+    let (_, stdout, _) = robinson_output(&args, "");
+    assert!(stdout.contains("p.note { color: #000000;"), "{}", stdout);
+
+    let args = [
+        &*page,
+        "--no-default-css",
+        "--no-document-css",
+        "--dump-style",
+    ];
+    let (_, stdout, _) = robinson_output(&args, "");
+    assert!(stdout.contains("p.note\n"), "{}", stdout);
+
+    // Errors in a `<style>` element point into the HTML file
+    fs::write(
+        path("page.html"),
+        "<html>\n  <style>\n    p {}\n    p > em {}\n  </style>\n</html>",
+    )
+    .unwrap();
+    let (code, stderr) = robinson(&[&page, "--dump-style"], "");
+    assert_eq!(2, code);
+    assert!(
+        stderr.starts_with(&format!("robinson: {}:4:7: invalid CSS: ", page)),
+        "{}",
+        stderr
+    );
+
+    // A linked stylesheet that doesn't exist fails the page
+    fs::write(
+        path("page.html"),
+        "<html><link rel=\"stylesheet\" href=\"missing.css\"></html>",
+    )
+    .unwrap();
+    let (code, stderr) = robinson(&[&page, "--dump-style"], "");
+    assert_eq!(3, code);
+    assert!(
+        stderr.starts_with(&format!("robinson: {}: ", path("missing.css"))),
+        "{}",
+        stderr
+    );
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn output_formats() {
+    let page = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/page.html");
+    let output = |args: &[&str]| {
+        let mut command = Command::new(env!("CARGO_BIN_EXE_robinson"));
+        let output = command
+            .arg(page)
+            .args(["--width", "160", "--height", "64"])
+            .args(args)
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{:?}", output);
+        output.stdout
+    };
+
+    // Text is the default on stdout
+    let text = String::from_utf8(output(&[])).unwrap();
+    let lines: Vec<_> = text.lines().collect();
+    assert!(lines[0].starts_with("┌──"), "{}", text);
+    assert!(lines[1].contains("robinson"), "{}", text);
+    assert_eq!(
+        text,
+        String::from_utf8(output(&["-o", "-", "-f", "txt"])).unwrap()
+    );
+
+    let svg = String::from_utf8(output(&["-f", "svg"])).unwrap();
+    assert!(
+        svg.starts_with("<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"160\" height=\"64\""),
+        "{}",
+        svg
+    );
+    assert!(svg.contains("fill=\"#336699\""), "{}", svg);
+
+    let ppm = output(&["-f", "ppm", "-o", "-"]);
+    assert!(ppm.starts_with(b"P6\n160 64\n255\n"));
+    assert_eq!(14 + 160 * 64 * 3, ppm.len());
+
+    #[cfg(feature = "png")]
+    assert!(output(&["-f", "png"]).starts_with(b"\x89PNG\r\n\x1a\n"));
+
+    #[cfg(feature = "serde")]
+    {
+        let json = String::from_utf8(output(&["-f", "json"])).unwrap();
+        assert!(
+            json.starts_with("{\n  \"box_type\": \"block\","),
+            "{}",
+            json
+        );
+        assert!(json.contains("\"tag\": \"div\""), "{}", json);
+    }
+
+    // The format of an output file comes from its extension
+    let dir = env::temp_dir().join(format!("robinson-formats-{}", process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let svg_path = dir.join("page.svg");
+    assert!(output(&["-o", svg_path.to_str().unwrap()]).is_empty());
+    assert_eq!(svg, fs::read_to_string(&svg_path).unwrap());
+    let (code, stderr) = robinson(&[page, "-o", "page"], "");
+    assert_eq!(1, code);
+    assert_eq!(
+        "robinson: can't tell the format of page; use --format (see --help)\n",
+        stderr
+    );
+    fs::remove_dir_all(&dir).unwrap();
+}
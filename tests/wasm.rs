@@ -0,0 +1,43 @@
+// Tests of the JavaScript bindings, run in a WebAssembly engine by
+// `wasm-bindgen-test-runner`. On other targets there's nothing here
+
+#![cfg(target_arch = "wasm32")]
+
+use robinson::wasm::js::{dump_layout, parse_html, render_to_rgba};
+use wasm_bindgen_test::wasm_bindgen_test;
+
+#[wasm_bindgen_test]
+fn render_a_tiny_document() {
+    let pixels = render_to_rgba(
+        "<div></div>",
+        "div { display: block; width: 1px; height: 1px; background: #ff0000; }",
+        2,
+        1,
+    )
+    .unwrap();
+    assert_eq!(vec![255, 0, 0, 255, 255, 255, 255, 255], pixels);
+}
+
+#[wasm_bindgen_test]
+fn parse_html_to_an_object() {
+    let root = parse_html("<p class=\"note\">Hi</p>").unwrap();
+    let json = js_sys::JSON::stringify(&root).unwrap();
+    assert_eq!(
+        "{\"node_type\":{\"element\":{\"tag_name\":\"p\",\"attributes\":{\"class\":\"note\"}}},\
+         \"children\":[{\"node_type\":{\"text\":\"Hi\"},\"children\":[]}]}",
+        String::from(json)
+    );
+}
+
+#[wasm_bindgen_test]
+fn dump_a_layout() {
+    let layout = dump_layout("<div></div>", "", 10, 10).unwrap();
+    assert_eq!(1, layout.lines().count());
+}
+
+#[wasm_bindgen_test]
+fn malformed_pages_are_errors() {
+    assert!(parse_html("<div").is_err());
+    assert!(render_to_rgba("<div></div>", "div > p {}", 1, 1).is_err());
+    assert!(dump_layout("<div", "", 1, 1).is_err());
+}